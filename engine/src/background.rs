@@ -0,0 +1,126 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, JoinSet};
+use tracing::warn;
+
+type Job = Pin<Box<dyn Future<Output = ()> + Send>>;
+
+/// Bounded executor for fire-and-forget persistence (decision logs,
+/// decision contexts, analyzer enqueues). Detached `tokio::spawn`s would
+/// accumulate without bound under overload and be abandoned mid-write on
+/// shutdown; this instead feeds a fixed worker pool through a bounded
+/// channel and can be drained before the process exits.
+pub struct BackgroundLogger {
+    tx: Mutex<Option<mpsc::Sender<Job>>>,
+    worker: Mutex<Option<JoinHandle<()>>>,
+    in_flight: Arc<AtomicU64>,
+    dropped: AtomicU64,
+}
+
+impl BackgroundLogger {
+    pub fn new(queue_capacity: usize, concurrency: usize) -> Self {
+        let (tx, rx) = mpsc::channel(queue_capacity.max(1));
+        let in_flight = Arc::new(AtomicU64::new(0));
+        let worker = tokio::spawn(run_consumer(rx, concurrency.max(1), in_flight.clone()));
+        Self {
+            tx: Mutex::new(Some(tx)),
+            worker: Mutex::new(Some(worker)),
+            in_flight,
+            dropped: AtomicU64::new(0),
+        }
+    }
+
+    /// Queue a persistence job. Never blocks the scoring path: when the
+    /// queue is full (or the logger has shut down) the job is dropped and
+    /// counted rather than piling up unbounded tasks.
+    pub fn submit<F>(&self, job: F)
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let guard = self.tx.lock().expect("background logger poisoned");
+        let Some(tx) = guard.as_ref() else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = tx.try_send(Box::pin(job)) {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            warn!(error = %e, "background logging job dropped");
+        }
+    }
+
+    /// Jobs queued or currently executing.
+    pub fn in_flight(&self) -> u64 {
+        self.in_flight.load(Ordering::Relaxed)
+    }
+
+    /// Jobs dropped because the queue was full or already closed.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Close the queue and wait until every already-accepted job has run.
+    pub async fn shutdown(&self) {
+        let tx = self.tx.lock().expect("background logger poisoned").take();
+        drop(tx);
+        let worker = self.worker.lock().expect("background logger poisoned").take();
+        if let Some(worker) = worker {
+            if let Err(e) = worker.await {
+                warn!(error = %e, "background logging worker panicked");
+            }
+        }
+    }
+}
+
+async fn run_consumer(mut rx: mpsc::Receiver<Job>, concurrency: usize, in_flight: Arc<AtomicU64>) {
+    let mut pool = JoinSet::new();
+    while let Some(job) = rx.recv().await {
+        // Cap concurrent writes; further jobs wait in the channel, which is
+        // what bounds the sender side.
+        while pool.len() >= concurrency {
+            pool.join_next().await;
+        }
+        let in_flight = in_flight.clone();
+        pool.spawn(async move {
+            job.await;
+            in_flight.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+    // Channel closed: the queue is already drained, finish what is running.
+    while pool.join_next().await.is_some() {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_drains_every_accepted_job() {
+        let logger = BackgroundLogger::new(64, 2);
+        let completed = Arc::new(AtomicU64::new(0));
+        for _ in 0..20 {
+            let completed = completed.clone();
+            logger.submit(async move {
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                completed.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        logger.shutdown().await;
+        assert_eq!(completed.load(Ordering::Relaxed), 20);
+        assert_eq!(logger.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn submits_after_shutdown_are_dropped_and_counted() {
+        let logger = BackgroundLogger::new(4, 1);
+        logger.shutdown().await;
+        logger.submit(async {});
+        assert_eq!(logger.dropped(), 1);
+        assert_eq!(logger.in_flight(), 0);
+    }
+}
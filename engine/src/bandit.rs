@@ -0,0 +1,334 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::TieBreak;
+use crate::error::AppError;
+use crate::models::Action;
+
+/// The action arms, in arm-index order.
+pub const ARMS: [Action; 3] = [Action::Allow, Action::Warn, Action::Block];
+
+/// LinUCB contextual bandit used to resolve decisions in the uncertain band.
+///
+/// Each arm keeps the standard `A = I + Σ x xᵀ` design matrix and `b = Σ r x`
+/// response vector; the UCB is `θᵀx + α √(xᵀ A⁻¹ x)` with `θ = A⁻¹ b`.
+pub struct LinUCBBandit {
+    alpha: f64,
+    dimensions: usize,
+    arms: Vec<Arm>,
+}
+
+struct Arm {
+    a: Vec<Vec<f64>>,
+    b: Vec<f64>,
+    pulls: u64,
+}
+
+impl Arm {
+    fn new(dimensions: usize) -> Self {
+        Self {
+            a: identity(dimensions),
+            b: vec![0.0; dimensions],
+            pulls: 0,
+        }
+    }
+}
+
+/// Serializable snapshot of bandit state for persistence.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LinUCBParameters {
+    pub alpha: f64,
+    pub dimensions: usize,
+    pub arms: Vec<ArmParameters>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmParameters {
+    pub a: Vec<Vec<f64>>,
+    pub b: Vec<f64>,
+    pub pulls: u64,
+}
+
+impl LinUCBBandit {
+    pub fn new(alpha: f64, dimensions: usize) -> Self {
+        Self {
+            alpha,
+            dimensions,
+            arms: (0..ARMS.len()).map(|_| Arm::new(dimensions)).collect(),
+        }
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn arm_pulls(&self) -> Vec<u64> {
+        self.arms.iter().map(|arm| arm.pulls).collect()
+    }
+
+    /// Whether every arm has seen at least `min_arm_pulls` updates. Until
+    /// then the confidence bounds are dominated by the identity prior and
+    /// the bandit's choices should not influence decisions.
+    pub fn is_warmed(&self, min_arm_pulls: u64) -> bool {
+        self.arms.iter().all(|arm| arm.pulls >= min_arm_pulls)
+    }
+
+    /// Pick the arm with the highest upper confidence bound for `context`.
+    /// Exact ties — the norm while arms still carry equal identity priors —
+    /// resolve by `tie_break` rather than always falling on arm 0 (ALLOW);
+    /// `seed` keeps the random variant stable for a given decision.
+    pub fn select_arm(&self, context: &[f64], tie_break: &TieBreak, seed: u64) -> usize {
+        let ucbs: Vec<f64> = self
+            .arms
+            .iter()
+            .map(|arm| {
+                let a_inv = invert(&arm.a);
+                let theta = mat_vec(&a_inv, &arm.b);
+                let exploit: f64 = theta.iter().zip(context.iter()).map(|(t, x)| t * x).sum();
+                let explore = self.alpha * quadratic_form(&a_inv, context).max(0.0).sqrt();
+                exploit + explore
+            })
+            .collect();
+        let best = ucbs.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        // Identical arm state yields bit-identical UCBs, so exact equality
+        // is the right tie test.
+        let tied: Vec<usize> = ucbs
+            .iter()
+            .enumerate()
+            .filter(|(_, &ucb)| ucb == best)
+            .map(|(index, _)| index)
+            .collect();
+        if tied.len() == 1 {
+            return tied[0];
+        }
+        match tie_break {
+            TieBreak::Random => tied[(seed % tied.len() as u64) as usize],
+            TieBreak::Prefer(action) => tied
+                .iter()
+                .copied()
+                .find(|&arm| ARMS[arm] == *action)
+                .unwrap_or(tied[0]),
+        }
+    }
+
+    pub fn update(&mut self, arm: usize, context: &[f64], reward: f64) {
+        let arm = &mut self.arms[arm];
+        for i in 0..self.dimensions {
+            for j in 0..self.dimensions {
+                arm.a[i][j] += context[i] * context[j];
+            }
+        }
+        for (b, x) in arm.b.iter_mut().zip(context.iter()) {
+            *b += reward * x;
+        }
+        arm.pulls += 1;
+    }
+
+    pub fn reset(&mut self) {
+        for arm in &mut self.arms {
+            *arm = Arm::new(self.dimensions);
+        }
+    }
+
+    pub fn parameters(&self) -> LinUCBParameters {
+        LinUCBParameters {
+            alpha: self.alpha,
+            dimensions: self.dimensions,
+            arms: self
+                .arms
+                .iter()
+                .map(|arm| ArmParameters {
+                    a: arm.a.clone(),
+                    b: arm.b.clone(),
+                    pulls: arm.pulls,
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_parameters(params: LinUCBParameters) -> Result<Self, AppError> {
+        if params.arms.len() != ARMS.len() {
+            return Err(AppError::Model(format!(
+                "bandit snapshot has {} arms, expected {}",
+                params.arms.len(),
+                ARMS.len()
+            )));
+        }
+        Ok(Self {
+            alpha: params.alpha,
+            dimensions: params.dimensions,
+            arms: params
+                .arms
+                .into_iter()
+                .map(|arm| Arm {
+                    a: arm.a,
+                    b: arm.b,
+                    pulls: arm.pulls,
+                })
+                .collect(),
+        })
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    let mut m = vec![vec![0.0; n]; n];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+fn mat_vec(m: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    m.iter()
+        .map(|row| row.iter().zip(v.iter()).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+fn quadratic_form(m: &[Vec<f64>], v: &[f64]) -> f64 {
+    mat_vec(m, v).iter().zip(v.iter()).map(|(a, b)| a * b).sum()
+}
+
+/// Gauss-Jordan inverse. The design matrices are symmetric positive definite
+/// (identity plus outer products), so this does not need handling for
+/// singular inputs.
+fn invert(m: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = m.len();
+    let mut aug: Vec<Vec<f64>> = m
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut r = row.clone();
+            r.extend((0..n).map(|j| if i == j { 1.0 } else { 0.0 }));
+            r
+        })
+        .collect();
+
+    for col in 0..n {
+        // Partial pivot for numerical stability.
+        let pivot_row = (col..n)
+            .max_by(|&a, &b| aug[a][col].abs().partial_cmp(&aug[b][col].abs()).unwrap())
+            .unwrap();
+        aug.swap(col, pivot_row);
+        let pivot = aug[col][col];
+        for x in aug[col].iter_mut() {
+            *x /= pivot;
+        }
+        for row in 0..n {
+            if row != col {
+                let factor = aug[row][col];
+                for k in 0..2 * n {
+                    aug[row][k] -= factor * aug[col][k];
+                }
+            }
+        }
+    }
+
+    aug.into_iter().map(|row| row[n..].to_vec()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invert_identity_is_identity() {
+        let inv = invert(&identity(4));
+        for (i, row) in inv.iter().enumerate() {
+            for (j, &x) in row.iter().enumerate() {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((x - expected).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn rewarded_arm_wins() {
+        let mut bandit = LinUCBBandit::new(0.1, 4);
+        let context = vec![1.0, 0.5, 0.0, 0.2];
+        for _ in 0..50 {
+            bandit.update(2, &context, 1.0);
+            bandit.update(0, &context, -1.0);
+        }
+        assert_eq!(bandit.select_arm(&context, &TieBreak::Random, 0), 2);
+    }
+
+    #[test]
+    fn cold_ties_resolve_by_the_configured_tie_break() {
+        let bandit = LinUCBBandit::new(1.0, 2);
+        let context = vec![1.0, 0.5];
+
+        // A cold bandit's UCBs are all equal; the preferred action wins
+        // the tie instead of arm 0.
+        assert_eq!(
+            bandit.select_arm(&context, &TieBreak::Prefer(Action::Warn), 0),
+            1
+        );
+        assert_eq!(
+            bandit.select_arm(&context, &TieBreak::Prefer(Action::Block), 0),
+            2
+        );
+
+        // Seeded random spreads across all arms yet repeats per seed.
+        let picks: std::collections::HashSet<usize> = (0..30)
+            .map(|seed| bandit.select_arm(&context, &TieBreak::Random, seed))
+            .collect();
+        assert_eq!(picks.len(), ARMS.len());
+        assert_eq!(
+            bandit.select_arm(&context, &TieBreak::Random, 7),
+            bandit.select_arm(&context, &TieBreak::Random, 7)
+        );
+
+        // A clear winner ignores the tie-break entirely.
+        let mut trained = LinUCBBandit::new(0.1, 2);
+        for _ in 0..50 {
+            trained.update(2, &context, 1.0);
+            trained.update(0, &context, -1.0);
+        }
+        assert_eq!(
+            trained.select_arm(&context, &TieBreak::Prefer(Action::Allow), 0),
+            2
+        );
+    }
+
+    #[test]
+    fn cold_bandit_stays_unwarmed_until_every_arm_has_min_pulls() {
+        let mut bandit = LinUCBBandit::new(1.0, 2);
+        assert!(bandit.is_warmed(0));
+        assert!(!bandit.is_warmed(1));
+
+        let context = vec![1.0, 0.5];
+        bandit.update(0, &context, 1.0);
+        bandit.update(1, &context, 0.0);
+        // One arm still cold suppresses the whole bandit.
+        assert!(!bandit.is_warmed(1));
+
+        bandit.update(2, &context, -1.0);
+        assert!(bandit.is_warmed(1));
+        assert!(!bandit.is_warmed(2));
+    }
+
+    #[test]
+    fn export_reset_import_restores_statistics() {
+        let mut bandit = LinUCBBandit::new(1.0, 3);
+        let context = vec![1.0, 0.2, 0.8];
+        for _ in 0..10 {
+            bandit.update(2, &context, 1.0);
+        }
+        let snapshot = bandit.parameters();
+
+        bandit.reset();
+        assert_eq!(bandit.arm_pulls(), vec![0, 0, 0]);
+
+        let restored = LinUCBBandit::from_parameters(snapshot).unwrap();
+        assert_eq!(restored.arm_pulls(), vec![0, 0, 10]);
+        assert_eq!(restored.select_arm(&context, &TieBreak::Random, 0), 2);
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let mut bandit = LinUCBBandit::new(1.0, 3);
+        bandit.update(1, &[1.0, 0.0, 1.0], 0.5);
+        let params = bandit.parameters();
+        let restored = LinUCBBandit::from_parameters(params).unwrap();
+        assert_eq!(restored.arm_pulls(), bandit.arm_pulls());
+    }
+}
@@ -0,0 +1,1541 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use tokio::sync::Mutex;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::background::BackgroundLogger;
+use crate::bandit::{LinUCBBandit, LinUCBParameters};
+use crate::config::{Config, ModelConfig, OnErrorAction, ThresholdConfig};
+use crate::error::AppError;
+use crate::features::{Feature, FeatureExtractor, FeatureSet};
+use crate::intel::HardIntelChecker;
+use crate::metrics::Metrics;
+use crate::model::{OnlineTrainer, StudentModel};
+use crate::models::{
+    Action, AnalyzerTask, Decision, DecisionContext, FeedbackRequest, ScoreRequest,
+    ScoreResponse,
+};
+use crate::pipeline::{ScoreStage, ScoringContext, StageOutcome};
+use crate::redis_client::RedisClient;
+use crate::storage::ClickHouseClient;
+use crate::velocity::VelocityTracker;
+
+/// Reason string marking a decision that was resolved by the bandit in the
+/// uncertain band; such decisions are cached with the short WARN TTL.
+pub const BANDIT_REASON: &str = "Decision refined by contextual bandit";
+
+/// Reason attached when a client deadline cut the pipeline short. Routes
+/// key off this to keep partial decisions out of the response cache.
+pub(crate) const DEADLINE_REASON: &str =
+    "deadline_exceeded: partial decision from completed stages";
+
+/// The core scoring engine. Scoring itself runs as an ordered pipeline of
+/// [`ScoreStage`]s (hard intel gate, feature extraction, student model
+/// inference, LinUCB refinement in the uncertain band); the engine owns the
+/// shared state the stages draw on.
+pub struct ThreatEngine {
+    config: Config,
+    extractor: FeatureExtractor,
+    intel: Arc<HardIntelChecker>,
+    model: OnlineTrainer,
+    bandit: Mutex<LinUCBBandit>,
+    storage: Arc<ClickHouseClient>,
+    redis: RedisClient,
+    stages: Vec<Box<dyn ScoreStage>>,
+    velocity: VelocityTracker,
+    logger: BackgroundLogger,
+    tenants: std::collections::HashMap<String, Tenant>,
+    preload: crate::preload::PreloadProgress,
+    queue_depth: Arc<QueueDepthCache>,
+    pub metrics: Arc<Metrics>,
+    /// Rolling confusion matrix fed by `/feedback`, served at `/quality`.
+    pub quality: crate::metrics::ConfusionMatrix,
+    /// Rolling per-feature distributions, served at `/features/stats`.
+    pub drift: crate::metrics::FeatureDistributions,
+}
+
+/// Resolved per-tenant state: an optional dedicated model plus the
+/// overrides that replace the shared configuration for that tenant.
+pub(crate) struct Tenant {
+    pub(crate) model: Option<StudentModel>,
+    pub(crate) thresholds: ThresholdConfig,
+    pub(crate) allowlist: std::collections::HashSet<String>,
+    pub(crate) blocklist: std::collections::HashSet<String>,
+}
+
+impl ThreatEngine {
+    pub async fn new(config: Config) -> Result<Self, AppError> {
+        let storage = Arc::new(ClickHouseClient::new(&config.clickhouse));
+        if config.clickhouse.skip_startup_check {
+            info!("skipping ClickHouse startup connectivity check");
+        } else {
+            storage.test_connection().await?;
+        }
+        if config.clickhouse.auto_migrate {
+            storage.migrate().await?;
+        }
+
+        let redis = RedisClient::new(&config.redis).await?;
+
+        let intel = Arc::new(HardIntelChecker::new(config.intel.clone()));
+        intel.load_local_lists().await?;
+
+        let model = load_student_model(&config.model);
+        if model_is_untrained(&model) {
+            warn!(
+                policy = ?config.model.untrained_policy,
+                "student model is untrained; applying the configured fallback policy"
+            );
+        }
+        let bandit = load_bandit(&config);
+        let tenants = load_tenants(&config);
+
+        Ok(Self {
+            extractor: FeatureExtractor::new(config.features.clone()),
+            intel,
+            model: OnlineTrainer::new(
+                model,
+                config.model.online_batch_size,
+                config.model.learning_rate,
+            ),
+            bandit: Mutex::new(bandit),
+            storage,
+            redis,
+            stages: crate::pipeline::build(&config.pipeline.stages)?,
+            velocity: VelocityTracker::new(
+                config.features.velocity_window_seconds,
+                config.features.velocity_max_entries,
+            ),
+            logger: BackgroundLogger::new(
+                config.server.logging_queue_capacity,
+                config.server.logging_concurrency,
+            ),
+            tenants,
+            preload: crate::preload::PreloadProgress::default(),
+            queue_depth: Arc::new(QueueDepthCache::default()),
+            metrics: Arc::new(Metrics::default()),
+            quality: crate::metrics::ConfusionMatrix::new(config.quality.window_seconds),
+            drift: crate::metrics::FeatureDistributions::new(config.drift.window_seconds),
+            config,
+        })
+    }
+
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    pub fn intel(&self) -> &Arc<HardIntelChecker> {
+        &self.intel
+    }
+
+    pub fn extractor(&self) -> &FeatureExtractor {
+        &self.extractor
+    }
+
+    pub fn storage(&self) -> &Arc<ClickHouseClient> {
+        &self.storage
+    }
+
+    pub fn redis(&self) -> &RedisClient {
+        &self.redis
+    }
+
+    pub(crate) fn model(&self) -> &OnlineTrainer {
+        &self.model
+    }
+
+    pub(crate) fn bandit(&self) -> &Mutex<LinUCBBandit> {
+        &self.bandit
+    }
+
+    pub(crate) fn velocity(&self) -> &VelocityTracker {
+        &self.velocity
+    }
+
+    pub fn logger(&self) -> &BackgroundLogger {
+        &self.logger
+    }
+
+    pub fn preload(&self) -> &crate::preload::PreloadProgress {
+        &self.preload
+    }
+
+    pub(crate) fn tenant_for(&self, request: &ScoreRequest) -> Option<&Tenant> {
+        tenant_for(&self.tenants, request)
+    }
+
+    pub async fn model_info(&self) -> serde_json::Value {
+        let model = self.model.current().await;
+        serde_json::json!({
+            "version": model.version,
+            "training_samples": model.training_samples,
+            "trained_at": model.trained_at,
+            "weights": model.weights.len(),
+            "model_untrained": model_is_untrained(&model),
+        })
+    }
+
+    pub async fn model_untrained(&self) -> bool {
+        model_is_untrained(&self.model.current().await)
+    }
+
+    /// Score a single domain/URL by running the configured stage pipeline
+    /// and produce a decision.
+    pub async fn score(&self, request: &ScoreRequest) -> Result<ScoreResponse, AppError> {
+        Ok(self.score_timed(request, None).await?.0)
+    }
+
+    /// Like [`score`](Self::score), additionally returning the wall time of
+    /// each pipeline stage (for the `Server-Timing` response header).
+    ///
+    /// `deadline` is the client's remaining latency budget. When it runs out
+    /// mid-pipeline the stages completed so far already hold a usable
+    /// decision, so that partial result is returned (with [`DEADLINE_REASON`]
+    /// attached) instead of an error; a budget that is already spent on
+    /// arrival still gets the in-memory hard-intel answer.
+    pub async fn score_timed(
+        &self,
+        request: &ScoreRequest,
+        deadline: Option<Duration>,
+    ) -> Result<(ScoreResponse, Vec<crate::features::StageTiming>), AppError> {
+        let started = Instant::now();
+        let deadline_at = deadline.map(|deadline| started + deadline);
+        let mut ctx = ScoringContext::new(request);
+        let mut timings = Vec::with_capacity(self.stages.len());
+
+        // Warn once per request here, so the stages can resolve the tenant
+        // silently as often as they need to.
+        if let Some(name) = request.context.get("tenant") {
+            if !self.tenants.contains_key(name) {
+                warn!(tenant = %name, "unknown tenant, using default configuration");
+            }
+        }
+
+        let mut short_circuited = false;
+        let mut deadline_hit = false;
+        for stage in &self.stages {
+            let remaining =
+                deadline_at.map(|deadline_at| deadline_at.saturating_duration_since(Instant::now()));
+            if !stage_admitted(stage.name(), remaining) {
+                deadline_hit = true;
+                break;
+            }
+            let stage_started = Instant::now();
+            let outcome = match remaining {
+                Some(remaining) if !remaining.is_zero() => {
+                    match tokio::time::timeout(remaining, stage.run(self, request, &mut ctx))
+                        .await
+                    {
+                        Ok(outcome) => outcome,
+                        Err(_) => {
+                            timings.push(crate::features::StageTiming {
+                                stage: stage.name(),
+                                duration_ms: stage_started.elapsed().as_secs_f64() * 1000.0,
+                            });
+                            deadline_hit = true;
+                            break;
+                        }
+                    }
+                }
+                _ => stage.run(self, request, &mut ctx).await,
+            };
+            timings.push(crate::features::StageTiming {
+                stage: stage.name(),
+                duration_ms: stage_started.elapsed().as_secs_f64() * 1000.0,
+            });
+            match outcome {
+                Ok(StageOutcome::Continue) => {}
+                Ok(StageOutcome::ShortCircuit) => {
+                    short_circuited = true;
+                    break;
+                }
+                Err(e) => {
+                    let Some(action) =
+                        action_for_engine_error(self.config.server.on_error_action)
+                    else {
+                        return Err(e);
+                    };
+                    error!(
+                        stage = stage.name(),
+                        error = %e,
+                        "scoring stage failed; applying configured on_error_action"
+                    );
+                    ctx.action = action;
+                    ctx.reasons
+                        .push(format!("engine_error: {} stage failed", stage.name()));
+                    // A partial context must not feed the learners.
+                    short_circuited = true;
+                    break;
+                }
+            }
+        }
+        if deadline_hit {
+            self.metrics
+                .deadline_partials
+                .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            ctx.reasons.push(DEADLINE_REASON.to_string());
+            // Like the error path: a partial context must not feed the
+            // learners.
+            short_circuited = true;
+        }
+
+        // Policy-driven enqueues beyond the uncertain band, evaluated on
+        // the final action: evidence collection for BLOCKs, deterministic
+        // sampling for model blind spots.
+        if !ctx.analyzer_enqueued {
+            if let Some(trigger) = policy_enqueue_trigger(
+                &self.config.analyzer.enqueue_policy,
+                ctx.action,
+                &ctx.decision_id,
+            ) {
+                let counter = match trigger {
+                    "all_blocks" => &self.metrics.enqueue_all_blocks,
+                    _ => &self.metrics.enqueue_sampled,
+                };
+                counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                self.enqueue_analyzer_task(
+                    &ctx.decision_id,
+                    &ctx.domain,
+                    request,
+                    ctx.probability,
+                    &ctx.features,
+                    trigger == "all_blocks",
+                );
+                ctx.analyzer_enqueued = true;
+            }
+        }
+
+        // Threat hunting: collect deep-analysis artifacts even for a clean
+        // ALLOW. The decision itself is untouched.
+        if crate::pipeline::forced_analysis_needed(request, &ctx) {
+            self.enqueue_analyzer_task(
+                &ctx.decision_id,
+                &ctx.domain,
+                request,
+                ctx.probability,
+                &ctx.features,
+                true,
+            );
+        }
+
+        // Clients see the most salient reasons first, capped; the decision
+        // log below keeps the full set.
+        let presented = present_reasons(&ctx.reasons, self.config.features.max_reasons);
+        // A short-circuited intel block carries its listing in the reasons
+        // rather than in the (never extracted) features.
+        let hard_intel = ctx.intel_floor.is_some()
+            || ctx.reasons.iter().any(|r| r.starts_with("Listed on"));
+        let response = ScoreResponse {
+            decision_id: ctx.decision_id.clone(),
+            domain: ctx.domain.clone(),
+            action: ctx.action,
+            probability: ctx.probability,
+            confidence: decision_confidence(&ctx.features, hard_intel, ctx.arm.is_some()),
+            reasons: presented,
+            cached: false,
+            processing_time_ms: started.elapsed().as_secs_f64() * 1000.0,
+            features_overridden: !request.feature_overrides.is_empty(),
+            model_version: ctx.model_version.clone(),
+            feature_schema_version: crate::features::FEATURE_SCHEMA_VERSION,
+            features: request
+                .return_features
+                .then(|| ctx.features.to_named_map()),
+        };
+        self.metrics.record_decision(ctx.action);
+        // Distribution monitoring; a short-circuited decision computed no
+        // features, so recording the empty set is a no-op.
+        self.drift.record(&ctx.features);
+
+        // A short-circuited decision (hard-intel block) carries no learner
+        // state, so there is nothing for /feedback to act on.
+        if !short_circuited {
+            let context = DecisionContext {
+                decision_id: ctx.decision_id,
+                domain: ctx.domain,
+                action: ctx.action,
+                probability: ctx.probability,
+                context_vector: ctx.context_vector,
+                feature_vector: ctx.feature_vector,
+                arm: ctx.arm,
+            };
+            let redis = self.redis.clone();
+            self.logger.submit(async move {
+                if let Err(e) = redis.store_decision_context(&context).await {
+                    warn!(error = %e, "failed to store decision context");
+                }
+            });
+        }
+        self.log_decision(request, &response, ctx.features, ctx.reasons);
+
+        Ok((response, timings))
+    }
+
+    /// Apply a feedback signal to the bandit and (optionally) the model.
+    pub async fn handle_feedback(&self, feedback: &FeedbackRequest) -> Result<(), AppError> {
+        if !(-1.0..=1.0).contains(&feedback.reward) || !feedback.reward.is_finite() {
+            return Err(AppError::InvalidRequest(format!(
+                "reward {} is outside [-1, 1]",
+                feedback.reward
+            )));
+        }
+
+        let context = self
+            .redis
+            .get_decision_context(&feedback.decision_id)
+            .await?
+            .ok_or_else(|| {
+                AppError::InvalidRequest(format!(
+                    "unknown decision_id: {}",
+                    feedback.decision_id
+                ))
+            })?;
+
+        if let Some(why) = feedback_inconsistency(feedback, context.action) {
+            if self.config.model.reject_inconsistent_feedback {
+                return Err(AppError::InvalidRequest(why));
+            }
+            warn!(
+                decision_id = %feedback.decision_id,
+                source = feedback.source.as_deref().unwrap_or("unknown"),
+                "inconsistent feedback: {why}"
+            );
+        }
+
+        self.metrics
+            .feedback_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // Ground truth against the original action closes the quality loop.
+        self.quality.record(
+            action_predicts_threat(
+                context.action,
+                self.config.quality.warn_counts_as_threat,
+            ),
+            feedback.actual_threat,
+        );
+
+        // With the bandit disabled the reward is still validated, counted,
+        // and logged above — it just is not folded into bandit state, so
+        // exploration can be re-enabled later without a corrupted posterior.
+        if self.config.bandit.enabled {
+            if let Some(arm) = context.arm {
+                let mut bandit = self.bandit.lock().await;
+                bandit.update(arm, &context.context_vector, feedback.reward as f64);
+                let params = bandit.parameters();
+                drop(bandit);
+                self.persist_bandit(params);
+            }
+        }
+
+        if self.config.model.prior_alpha > 0.0 {
+            let label = if feedback.actual_threat { 1.0 } else { 0.0 };
+            let old = self
+                .redis
+                .get_domain_prior(&context.domain)
+                .await
+                .ok()
+                .flatten()
+                .unwrap_or(NEUTRAL_DOMAIN_PRIOR);
+            let updated = fold_domain_prior(old, label, self.config.model.prior_alpha);
+            if let Err(e) = self
+                .redis
+                .set_domain_prior(&context.domain, updated, self.config.model.prior_ttl_seconds)
+                .await
+            {
+                warn!(error = %e, domain = %context.domain, "failed to persist domain prior");
+            }
+        }
+
+        if self.config.model.online_learning {
+            let label = if feedback.actual_threat { 1.0 } else { 0.0 };
+            if let Some(updated) = self
+                .model
+                .record(context.feature_vector.clone(), label)
+                .await
+            {
+                let path = self.config.model.path.clone();
+                tokio::task::spawn_blocking(move || {
+                    if let Err(e) = updated.save(&path) {
+                        warn!(error = %e, "failed to persist model");
+                    }
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Snapshot the bandit's learned parameters.
+    pub async fn bandit_export(&self) -> LinUCBParameters {
+        self.bandit.lock().await.parameters()
+    }
+
+    /// Wipe all learned bandit state and persist the reset.
+    pub async fn bandit_reset(&self) {
+        let mut bandit = self.bandit.lock().await;
+        bandit.reset();
+        let params = bandit.parameters();
+        drop(bandit);
+        self.persist_bandit(params);
+    }
+
+    /// Replace bandit state from a snapshot, after dimension checks.
+    pub async fn bandit_import(&self, params: LinUCBParameters) -> Result<(), AppError> {
+        if params.dimensions != self.config.bandit.context_dimensions {
+            return Err(AppError::InvalidRequest(format!(
+                "snapshot has {} dimensions, engine is configured for {}",
+                params.dimensions, self.config.bandit.context_dimensions
+            )));
+        }
+        let restored = LinUCBBandit::from_parameters(params)?;
+        let persisted = restored.parameters();
+        *self.bandit.lock().await = restored;
+        self.persist_bandit(persisted);
+        Ok(())
+    }
+
+    fn persist_bandit(&self, params: LinUCBParameters) {
+        let path = self.config.bandit.persist_path.clone();
+        tokio::task::spawn_blocking(move || {
+            if let Ok(data) = serde_json::to_string(&params) {
+                if let Err(e) = std::fs::write(&path, data) {
+                    warn!(error = %e, "failed to persist bandit");
+                }
+            }
+        });
+    }
+
+    /// Build the bandit context from the configured feature list; the bandit
+    /// is sized from that same list, so no padding is involved.
+    pub(crate) fn build_context_vector(&self, features: &FeatureSet) -> Vec<f64> {
+        features.project(&self.config.bandit.context_features)
+    }
+
+    /// `high_priority` marks tasks that must survive backpressure — BLOCK
+    /// evidence and operator-forced analysis; everything else is shed once
+    /// the queue sits at the high-water mark.
+    pub(crate) fn enqueue_analyzer_task(
+        &self,
+        decision_id: &str,
+        domain: &str,
+        request: &ScoreRequest,
+        probability: f32,
+        features: &FeatureSet,
+        high_priority: bool,
+    ) {
+        let task = AnalyzerTask {
+            task_id: Uuid::new_v4().to_string(),
+            decision_id: decision_id.to_string(),
+            domain: domain.to_string(),
+            url: request.url.clone(),
+            probability,
+            // The named map only materializes here, at the queue boundary.
+            features: features.to_named_map(),
+            created_at: Utc::now(),
+            attempts: 0,
+        };
+        let redis = self.redis.clone();
+        let metrics = self.metrics.clone();
+        let dedup_ttl = self.config.analyzer.dedup_window_seconds;
+        let high_water_mark = self.config.analyzer.queue_high_water_mark;
+        let queue_depth = self.queue_depth.clone();
+        self.logger.submit(async move {
+            if high_water_mark > 0 && !high_priority {
+                let depth = match queue_depth.fresh_depth() {
+                    Some(depth) => depth,
+                    None => match redis.get_queue_length().await {
+                        Ok(depth) => {
+                            queue_depth.store(depth);
+                            depth
+                        }
+                        // Depth unknown: fail open, a broken Redis will
+                        // surface on the enqueue itself.
+                        Err(_) => 0,
+                    },
+                };
+                if enqueue_sheds(depth, high_water_mark, high_priority) {
+                    metrics
+                        .analyzer_queue_full
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    warn!(
+                        domain = %task.domain,
+                        depth,
+                        high_water_mark,
+                        "analyzer queue over high-water mark, shedding task"
+                    );
+                    return;
+                }
+            }
+            match redis.enqueue_analyzer_task(&task, dedup_ttl).await {
+                Ok((_, true)) => {
+                    metrics
+                        .analyzer_enqueued
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                Ok((inflight, false)) => {
+                    metrics
+                        .dedup_suppressed
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    debug!(
+                        domain = %task.domain,
+                        inflight_task = %inflight,
+                        "analyzer task suppressed, analysis already in flight"
+                    );
+                }
+                Err(e) => warn!(error = %e, "failed to enqueue analyzer task"),
+            }
+        });
+    }
+
+    /// `reasons` is the full, untruncated set; the response may carry a
+    /// capped view of it.
+    fn log_decision(
+        &self,
+        request: &ScoreRequest,
+        response: &ScoreResponse,
+        features: FeatureSet,
+        reasons: Vec<String>,
+    ) {
+        let sample_rate = self
+            .config
+            .clickhouse
+            .decision_log_sample_rate
+            .get(response.action.as_str())
+            .copied()
+            .unwrap_or(1.0);
+        if !decision_is_sampled(&response.decision_id, sample_rate) {
+            return;
+        }
+        let decision = Decision {
+            decision_id: response.decision_id.clone(),
+            domain: response.domain.clone(),
+            url: request.url.clone(),
+            action: response.action,
+            probability: response.probability,
+            confidence: response.confidence,
+            model_version: response.model_version.clone(),
+            features: features.to_named_map(),
+            reasons,
+            processing_time_ms: response.processing_time_ms,
+            sample_rate,
+            timestamp: Utc::now(),
+        };
+        let storage = self.storage.clone();
+        self.logger.submit(async move {
+            if let Err(e) = storage.log_decision(&decision).await {
+                warn!(error = %e, "failed to log decision");
+            }
+        });
+    }
+}
+
+/// How long a sampled analyzer queue depth stays authoritative before the
+/// next enqueue pays for a fresh LLEN.
+const QUEUE_DEPTH_CACHE: Duration = Duration::from_secs(5);
+
+/// Analyzer queue depth as last sampled from Redis, so the backpressure
+/// check costs a round-trip at most once per [`QUEUE_DEPTH_CACHE`] rather
+/// than once per enqueue. Enqueues between samples are not counted, which
+/// is why the high-water mark is documented as approximate.
+#[derive(Default)]
+pub(crate) struct QueueDepthCache {
+    depth: std::sync::atomic::AtomicU64,
+    sampled_at: std::sync::Mutex<Option<Instant>>,
+}
+
+impl QueueDepthCache {
+    fn fresh_depth(&self) -> Option<u64> {
+        self.fresh_depth_at(Instant::now())
+    }
+
+    fn store(&self, depth: u64) {
+        self.store_at(Instant::now(), depth);
+    }
+
+    pub(crate) fn fresh_depth_at(&self, now: Instant) -> Option<u64> {
+        let sampled_at = self.sampled_at.lock().unwrap();
+        match *sampled_at {
+            Some(at) if now.duration_since(at) < QUEUE_DEPTH_CACHE => {
+                Some(self.depth.load(std::sync::atomic::Ordering::Relaxed))
+            }
+            _ => None,
+        }
+    }
+
+    pub(crate) fn store_at(&self, now: Instant, depth: u64) {
+        self.depth
+            .store(depth, std::sync::atomic::Ordering::Relaxed);
+        *self.sampled_at.lock().unwrap() = Some(now);
+    }
+}
+
+/// Whether an analyzer enqueue is shed under backpressure: only
+/// low-priority tasks, only once the queue sits at the high-water mark,
+/// and never when the mark is 0 (shedding disabled).
+pub(crate) fn enqueue_sheds(depth: u64, high_water_mark: u64, high_priority: bool) -> bool {
+    high_water_mark > 0 && !high_priority && depth >= high_water_mark
+}
+
+/// Whether the uncertainty stage may enqueue for deep analysis; the
+/// band's enqueue interleaves with bandit resolution, so the stage checks
+/// the policy itself rather than routing through
+/// [`policy_enqueue_trigger`].
+pub(crate) fn uncertain_enqueue_enabled(policies: &[crate::config::EnqueuePolicy]) -> bool {
+    policies
+        .iter()
+        .any(|policy| matches!(policy, crate::config::EnqueuePolicy::UncertainOnly))
+}
+
+/// The post-decision enqueue rule that fired for a finished decision, if
+/// any, named for the metrics. Evaluated on the final action, after the
+/// pipeline; `uncertain_only` never fires here (see
+/// [`uncertain_enqueue_enabled`]).
+pub(crate) fn policy_enqueue_trigger(
+    policies: &[crate::config::EnqueuePolicy],
+    action: Action,
+    decision_id: &str,
+) -> Option<&'static str> {
+    use crate::config::EnqueuePolicy;
+    for policy in policies {
+        match policy {
+            EnqueuePolicy::UncertainOnly => {}
+            EnqueuePolicy::AllBlocks if action == Action::Block => return Some("all_blocks"),
+            EnqueuePolicy::SampleAll { rate } if decision_is_sampled(decision_id, *rate) => {
+                return Some("sample_all");
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Stable per-decision seed: the same id always hashes the same, so
+/// sampling and bandit tie-breaking are reproducible across replays.
+pub(crate) fn decision_seed(decision_id: &str) -> u64 {
+    use std::hash::{Hash, Hasher};
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    decision_id.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Deterministic sampling decision keyed on the decision id, so replaying
+/// the same decision stream logs the same subset.
+fn decision_is_sampled(decision_id: &str, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    if rate <= 0.0 {
+        return false;
+    }
+    ((decision_seed(decision_id) % 10_000) as f64 / 10_000.0) < rate
+}
+
+/// Map a cached analyzer verdict onto a decision for the uncertain band.
+/// Fetch failures ("timeout"/"unreachable") carry no signal and fall back
+/// to the bandit.
+pub(crate) fn action_for_deep_verdict(verdict: &str) -> Option<Action> {
+    match verdict {
+        "suspicious" => Some(Action::Warn),
+        "benign" => Some(Action::Allow),
+        _ => None,
+    }
+}
+
+/// Detect feedback whose reward sign contradicts its own label given the
+/// original action — e.g. "threat confirmed" while rewarding the ALLOW that
+/// let it through. Almost always a client bug, so it is surfaced rather
+/// than silently fed to the learners. Returns a description of the
+/// contradiction, or `None` for coherent feedback.
+fn feedback_inconsistency(feedback: &FeedbackRequest, action: Action) -> Option<String> {
+    let contradiction = match (feedback.actual_threat, action) {
+        (true, Action::Allow) => feedback.reward > 0.0,
+        (true, Action::Block) => feedback.reward < 0.0,
+        (false, Action::Allow) => feedback.reward < 0.0,
+        (false, Action::Block) => feedback.reward > 0.0,
+        // WARN was a hedge either way; any reward sign is defensible.
+        (_, Action::Warn) => false,
+    };
+    contradiction.then(|| {
+        format!(
+            "actual_threat={} contradicts reward {:+.2} for a {} decision",
+            feedback.actual_threat, feedback.reward, action
+        )
+    })
+}
+
+/// Resolve a hard-intel match against the confidence gate: a match at or
+/// above `min_block_confidence` blocks outright, a weaker one only WARNs.
+/// The reason carries the confidence so operators can tune the gate.
+pub(crate) fn hard_intel_action(
+    intel: &crate::intel::HardIntelMatch,
+    min_block_confidence: f32,
+) -> (Action, String) {
+    let action = if intel.confidence >= min_block_confidence {
+        Action::Block
+    } else {
+        Action::Warn
+    };
+    let reason = format!(
+        "Listed on {} ({}, confidence {:.2})",
+        intel.source, intel.category, intel.confidence
+    );
+    (action, reason)
+}
+
+/// The probability reported for a hard-intel BLOCK: the configured pin
+/// (`thresholds.hard_intel_block_probability`, clamped to [0, 1]) when one
+/// is set, otherwise the source confidence. The reason string still carries
+/// the raw confidence, so pinning loses nothing for operators.
+pub(crate) fn hard_intel_block_probability(confidence: f32, pinned: Option<f32>) -> f32 {
+    pinned.map_or(confidence, |p| p.clamp(0.0, 1.0))
+}
+
+/// The reputation assigned to a domain with no feedback history.
+pub(crate) const NEUTRAL_DOMAIN_PRIOR: f32 = 0.5;
+
+/// Fold one feedback label (1.0 threat, 0.0 benign) into a domain's EWMA
+/// reputation prior.
+pub(crate) fn fold_domain_prior(old: f32, label: f32, alpha: f32) -> f32 {
+    (alpha * label + (1.0 - alpha) * old).clamp(0.0, 1.0)
+}
+
+/// Blend the model probability with the strongest standalone lexical signal
+/// so a cold model cannot suppress an obvious DGA/homoglyph hit. The learned
+/// per-domain prior then nudges the blend symmetrically around neutral:
+/// hostile feedback history pushes the score up, clean history pulls it
+/// down, with a weight small enough that the prior alone can never flip an
+/// otherwise clear decision. The model also sees `domain_prior` as an
+/// ordinary input once retrained over it; the additive nudge is what makes
+/// feedback bite before that retrain happens.
+pub(crate) fn combine_scores(model_probability: f32, features: &FeatureSet) -> f32 {
+    const PRIOR_WEIGHT: f32 = 0.2;
+    /// How much of the blend survives for an exact popular domain. A soft
+    /// anchor, unlike the allowlist: incidental lexical drift (length,
+    /// entropy) cannot push a top domain into the uncertain band, but a
+    /// genuinely strong signal still comes through scaled.
+    const POPULAR_DAMPING: f32 = 0.3;
+    let lexical = features
+        .value(Feature::DgaScore)
+        .max(features.value(Feature::HomoglyphScore))
+        .max(features.value(Feature::TyposquattingScore));
+    let damping = if features.value(Feature::IsPopularDomain) > 0.0 {
+        POPULAR_DAMPING
+    } else {
+        1.0
+    };
+    let prior_nudge = features
+        .get(Feature::DomainPrior)
+        .map_or(0.0, |prior| PRIOR_WEIGHT * (prior - NEUTRAL_DOMAIN_PRIOR));
+    ((0.7 * model_probability + 0.3 * lexical) * damping + prior_nudge).clamp(0.0, 1.0)
+}
+
+/// How sure the engine is of a decision, independent of how threatening it
+/// judged the domain: a 0.6 probability from a fully observed domain
+/// triages ahead of a 0.6 scored off mostly-defaulted features.
+///
+/// The formula is fixed and simple rather than learned:
+/// `0.6 * coverage + 0.4 * dns`, where coverage is the fraction of the
+/// feature schema actually computed (absent features read as 0.0 in the
+/// model, silently diluting its output) and dns is 1.0 when DNS lookups
+/// produced records. A hard-intel listing floors the result at 0.9 — a
+/// list hit is near-ground truth whatever else was observed — and a
+/// bandit-explored decision scales by 0.7, since the bandit is consulted
+/// precisely when the posterior is wide.
+pub(crate) fn decision_confidence(features: &FeatureSet, hard_intel: bool, explored: bool) -> f32 {
+    let coverage = features.len() as f32 / crate::features::FEATURE_COUNT as f32;
+    let dns = if features.get(Feature::DnsRecordCount).is_some() {
+        1.0
+    } else {
+        0.0
+    };
+    let mut confidence = 0.6 * coverage + 0.4 * dns;
+    if hard_intel {
+        confidence = confidence.max(0.9);
+    }
+    if explored {
+        confidence *= 0.7;
+    }
+    confidence.clamp(0.0, 1.0)
+}
+
+/// The decision substituted for a failed pipeline under the configured
+/// posture; `None` means propagate the error (HTTP 500).
+pub(crate) fn action_for_engine_error(posture: OnErrorAction) -> Option<Action> {
+    match posture {
+        OnErrorAction::Reject => None,
+        OnErrorAction::Allow => Some(Action::Allow),
+        OnErrorAction::Block => Some(Action::Block),
+    }
+}
+
+/// Whether a stage still runs with `remaining` deadline budget. A spent
+/// budget only admits the hard-intel stage: it answers from in-memory
+/// lists, so even an already-expired request gets a fast list verdict.
+pub(crate) fn stage_admitted(stage: &str, remaining: Option<Duration>) -> bool {
+    match remaining {
+        Some(remaining) => !remaining.is_zero() || stage == "hard_intel",
+        None => true,
+    }
+}
+
+/// Which side of the confusion matrix an action sits on when scored
+/// against feedback ground truth. ALLOW predicts benign, BLOCK predicts
+/// a threat; WARN is configurable because an interstitial both flags the
+/// domain and lets the visit proceed.
+pub(crate) fn action_predicts_threat(action: Action, warn_counts_as_threat: bool) -> bool {
+    match action {
+        Action::Allow => false,
+        Action::Warn => warn_counts_as_threat,
+        Action::Block => true,
+    }
+}
+
+/// Salience rank for a reason string; lower sorts first. Operational
+/// markers (partial or errored decisions) outrank everything because
+/// other components key on their presence — the response cache skips any
+/// decision carrying the deadline marker. Threat reasons then run from
+/// the most specific signal down to generic lexical thresholds.
+pub(crate) fn reason_priority(reason: &str) -> usize {
+    if reason == DEADLINE_REASON || reason.starts_with("engine_error") {
+        return 0;
+    }
+    if reason.starts_with("Listed on") {
+        return 1;
+    }
+    if reason.starts_with("deep_verdict_applied") {
+        return 2;
+    }
+    if reason.contains("brand impersonation")
+        || reason.starts_with("combosquatting")
+        || reason.contains("Homoglyph")
+    {
+        return 3;
+    }
+    if reason.contains("typosquatting") {
+        return 4;
+    }
+    if reason.contains("DGA") {
+        return 5;
+    }
+    if reason.starts_with("campaign_suspected")
+        || reason.starts_with("dns_rebinding")
+        || reason.starts_with("suspicious_download")
+    {
+        return 6;
+    }
+    7
+}
+
+/// The client-facing view of a reason list: stable-sorted most salient
+/// first and capped at `cap` entries (0 removes the cap). The decision
+/// log keeps the full set regardless.
+pub(crate) fn present_reasons(reasons: &[String], cap: usize) -> Vec<String> {
+    let mut presented = reasons.to_vec();
+    presented.sort_by_key(|reason| reason_priority(reason));
+    if cap > 0 {
+        presented.truncate(cap);
+    }
+    presented
+}
+
+/// Deterministic replacement for the bandit when `bandit.enabled` is off:
+/// the midpoint of the warn/block thresholds splits the uncertain band, so
+/// its upper half resolves to BLOCK and everything below keeps the plain
+/// threshold action. Monotone in the probability and exploration-free,
+/// which is the point during an incident.
+pub(crate) fn deterministic_uncertain_action(probability: f32, t: &ThresholdConfig) -> Action {
+    let midpoint = (t.warn_threshold + t.block_threshold) / 2.0;
+    if probability >= midpoint {
+        Action::Block
+    } else {
+        action_from_thresholds(probability, t)
+    }
+}
+
+/// A probability is uncertain when it sits within `uncertainty_threshold`
+/// of either action boundary, so the band follows the configured (global
+/// or per-tenant) thresholds instead of hardcoded anchors.
+pub(crate) fn is_uncertain(probability: f32, t: &ThresholdConfig) -> bool {
+    (probability - t.warn_threshold).abs() < t.uncertainty_threshold
+        || (probability - t.block_threshold).abs() < t.uncertainty_threshold
+}
+
+/// Map a probability onto an action with the given (global or per-tenant)
+/// thresholds.
+pub(crate) fn action_from_thresholds(probability: f32, t: &ThresholdConfig) -> Action {
+    if probability >= t.block_threshold {
+        Action::Block
+    } else if probability >= t.warn_threshold {
+        Action::Warn
+    } else {
+        Action::Allow
+    }
+}
+
+/// How `thresholds.private_ip_action` changes a decision for a domain
+/// resolving into private or bogon space, if at all: `warn` only lifts
+/// an ALLOW, `block` forces BLOCK, `none` never intervenes.
+pub(crate) fn private_ip_override(
+    current: Action,
+    policy: crate::config::PrivateIpAction,
+) -> Option<Action> {
+    match policy {
+        crate::config::PrivateIpAction::None => None,
+        crate::config::PrivateIpAction::Warn => {
+            (current == Action::Allow).then_some(Action::Warn)
+        }
+        crate::config::PrivateIpAction::Block => {
+            (current != Action::Block).then_some(Action::Block)
+        }
+    }
+}
+
+/// Resolve the tenant named in the request context, if any; unknown names
+/// resolve to `None` and the shared configuration applies.
+pub(crate) fn tenant_for<'a>(
+    tenants: &'a std::collections::HashMap<String, Tenant>,
+    request: &ScoreRequest,
+) -> Option<&'a Tenant> {
+    tenants.get(request.context.get("tenant")?)
+}
+
+fn load_tenants(config: &Config) -> std::collections::HashMap<String, Tenant> {
+    config
+        .tenants
+        .iter()
+        .map(|(name, tenant)| {
+            let model = tenant.model_path.as_deref().and_then(|path| {
+                match StudentModel::load(path) {
+                    Ok(model) => {
+                        info!(tenant = %name, version = %model.version, "loaded tenant model");
+                        Some(model)
+                    }
+                    Err(e) => {
+                        warn!(
+                            tenant = %name,
+                            error = %e,
+                            "tenant model unavailable, falling back to the shared model"
+                        );
+                        None
+                    }
+                }
+            });
+            let resolved = Tenant {
+                model,
+                thresholds: tenant
+                    .thresholds
+                    .clone()
+                    .unwrap_or_else(|| config.thresholds.clone()),
+                allowlist: tenant.allowlist.iter().map(|d| d.to_lowercase()).collect(),
+                blocklist: tenant.blocklist.iter().map(|d| d.to_lowercase()).collect(),
+            };
+            (name.clone(), resolved)
+        })
+        .collect()
+}
+
+/// A model is untrained when it is the zero-weight default or has never
+/// seen a training sample.
+pub(crate) fn model_is_untrained(model: &StudentModel) -> bool {
+    model.version == "default" || model.training_samples == 0
+}
+
+fn load_student_model(config: &ModelConfig) -> StudentModel {
+    match StudentModel::load(&config.path) {
+        Ok(model) => {
+            info!(version = %model.version, samples = model.training_samples, "loaded student model");
+            model
+        }
+        Err(e) => {
+            warn!(error = %e, "falling back to default student model");
+            StudentModel::default()
+        }
+    }
+}
+
+fn load_bandit(config: &Config) -> LinUCBBandit {
+    if let Ok(data) = std::fs::read_to_string(&config.bandit.persist_path) {
+        match serde_json::from_str(&data)
+            .map_err(AppError::from)
+            .and_then(LinUCBBandit::from_parameters)
+        {
+            Ok(bandit) if bandit.dimensions() == config.bandit.context_dimensions => {
+                info!("restored bandit state");
+                return bandit;
+            }
+            Ok(_) => warn!("persisted bandit has wrong dimensions, starting fresh"),
+            Err(e) => error!(error = %e, "failed to restore bandit state"),
+        }
+    }
+    LinUCBBandit::new(config.bandit.alpha, config.bandit.context_dimensions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contradictory_feedback_is_flagged() {
+        let feedback = |actual_threat: bool, reward: f32| FeedbackRequest {
+            decision_id: "d".to_string(),
+            actual_threat,
+            reward,
+            source: None,
+        };
+
+        // Rewarding the ALLOW of a confirmed threat is contradictory.
+        assert!(feedback_inconsistency(&feedback(true, 1.0), Action::Allow).is_some());
+        // Punishing the BLOCK of a confirmed threat is contradictory.
+        assert!(feedback_inconsistency(&feedback(true, -1.0), Action::Block).is_some());
+        // Coherent combinations pass.
+        assert!(feedback_inconsistency(&feedback(true, -1.0), Action::Allow).is_none());
+        assert!(feedback_inconsistency(&feedback(true, 1.0), Action::Block).is_none());
+        assert!(feedback_inconsistency(&feedback(false, 1.0), Action::Allow).is_none());
+        // WARN accepts any reward sign.
+        assert!(feedback_inconsistency(&feedback(true, 1.0), Action::Warn).is_none());
+    }
+
+    #[test]
+    fn sampling_fraction_holds_and_is_deterministic() {
+        let rate = 0.25;
+        let total = 10_000;
+        let sampled = (0..total)
+            .filter(|i| decision_is_sampled(&format!("decision-{i}"), rate))
+            .count();
+        let fraction = sampled as f64 / total as f64;
+        assert!((fraction - rate).abs() < 0.02, "fraction was {fraction}");
+
+        // Edge rates and reproducibility.
+        assert!(decision_is_sampled("any", 1.0));
+        assert!(!decision_is_sampled("any", 0.0));
+        assert_eq!(
+            decision_is_sampled("abc", 0.5),
+            decision_is_sampled("abc", 0.5)
+        );
+    }
+
+    #[test]
+    fn enqueue_policies_select_the_expected_subsets() {
+        use crate::config::EnqueuePolicy;
+
+        // The default policy keeps the uncertain-band enqueue and nothing
+        // else; dropping it from the list switches the band's enqueue off.
+        let uncertain = [EnqueuePolicy::UncertainOnly];
+        assert!(uncertain_enqueue_enabled(&uncertain));
+        assert!(policy_enqueue_trigger(&uncertain, Action::Block, "d1").is_none());
+
+        let blocks = [EnqueuePolicy::AllBlocks];
+        assert!(!uncertain_enqueue_enabled(&blocks));
+        assert_eq!(
+            policy_enqueue_trigger(&blocks, Action::Block, "d1"),
+            Some("all_blocks")
+        );
+        assert!(policy_enqueue_trigger(&blocks, Action::Allow, "d1").is_none());
+
+        assert_eq!(
+            policy_enqueue_trigger(&[EnqueuePolicy::SampleAll { rate: 1.0 }], Action::Allow, "d1"),
+            Some("sample_all")
+        );
+        assert!(
+            policy_enqueue_trigger(&[EnqueuePolicy::SampleAll { rate: 0.0 }], Action::Allow, "d1")
+                .is_none()
+        );
+
+        // Combined rules OR together: every BLOCK, plus a sample of the rest.
+        let combined = [EnqueuePolicy::AllBlocks, EnqueuePolicy::SampleAll { rate: 1.0 }];
+        assert_eq!(
+            policy_enqueue_trigger(&combined, Action::Block, "d1"),
+            Some("all_blocks")
+        );
+        assert_eq!(
+            policy_enqueue_trigger(&combined, Action::Allow, "d1"),
+            Some("sample_all")
+        );
+
+        // The sampled fraction tracks the rate, deterministic by id.
+        let sample = [EnqueuePolicy::SampleAll { rate: 0.3 }];
+        let hits = (0..1000)
+            .filter(|i| {
+                policy_enqueue_trigger(&sample, Action::Allow, &format!("d{i}")).is_some()
+            })
+            .count();
+        assert!((250..350).contains(&hits), "{hits}");
+    }
+
+    #[test]
+    fn enqueues_shed_past_the_high_water_mark() {
+        // Low-priority tasks are dropped at and above the mark ...
+        assert!(!enqueue_sheds(9_999, 10_000, false));
+        assert!(enqueue_sheds(10_000, 10_000, false));
+        assert!(enqueue_sheds(50_000, 10_000, false));
+        // ... BLOCK-evidence and forced tasks always go through ...
+        assert!(!enqueue_sheds(50_000, 10_000, true));
+        // ... and a zero mark disables shedding entirely.
+        assert!(!enqueue_sheds(u64::MAX, 0, false));
+    }
+
+    #[test]
+    fn queue_depth_sample_expires_after_the_cache_window() {
+        let cache = QueueDepthCache::default();
+        let start = Instant::now();
+        // Never sampled: the next enqueue must pay for an LLEN.
+        assert_eq!(cache.fresh_depth_at(start), None);
+        cache.store_at(start, 12_345);
+        assert_eq!(
+            cache.fresh_depth_at(start + Duration::from_secs(2)),
+            Some(12_345)
+        );
+        assert_eq!(cache.fresh_depth_at(start + QUEUE_DEPTH_CACHE), None);
+    }
+
+    #[test]
+    fn private_resolution_escalates_per_the_configured_action() {
+        use crate::config::PrivateIpAction;
+        // `warn` only lifts a clean ALLOW; a model-driven WARN or BLOCK
+        // already says at least as much.
+        assert_eq!(
+            private_ip_override(Action::Allow, PrivateIpAction::Warn),
+            Some(Action::Warn)
+        );
+        assert_eq!(private_ip_override(Action::Warn, PrivateIpAction::Warn), None);
+        assert_eq!(private_ip_override(Action::Block, PrivateIpAction::Warn), None);
+        // `block` forces the decision regardless of the model.
+        assert_eq!(
+            private_ip_override(Action::Allow, PrivateIpAction::Block),
+            Some(Action::Block)
+        );
+        assert_eq!(private_ip_override(Action::Block, PrivateIpAction::Block), None);
+        // `none` leaves the signal to the model weights entirely.
+        assert_eq!(private_ip_override(Action::Allow, PrivateIpAction::None), None);
+    }
+
+    #[test]
+    fn deep_verdicts_resolve_only_conclusive_outcomes() {
+        assert_eq!(action_for_deep_verdict("suspicious"), Some(Action::Warn));
+        assert_eq!(action_for_deep_verdict("benign"), Some(Action::Allow));
+        assert_eq!(action_for_deep_verdict("timeout"), None);
+        assert_eq!(action_for_deep_verdict("unreachable"), None);
+    }
+
+    #[test]
+    fn tenants_route_to_their_own_models() {
+        let tenant_model = |version: &str, weight: f64| StudentModel {
+            version: version.to_string(),
+            weights: vec![weight],
+            bias: 0.0,
+            training_samples: 100,
+            trained_at: None,
+            feature_names: vec!["dga_score".to_string()],
+        };
+        let tenant = |model: StudentModel| Tenant {
+            model: Some(model),
+            thresholds: ThresholdConfig::default(),
+            allowlist: Default::default(),
+            blocklist: Default::default(),
+        };
+        let tenants = std::collections::HashMap::from([
+            ("acme".to_string(), tenant(tenant_model("acme-1", 8.0))),
+            ("globex".to_string(), tenant(tenant_model("globex-1", -8.0))),
+        ]);
+        let request = |name: &str| ScoreRequest {
+            domain: "example.com".to_string(),
+            url: None,
+            request_id: None,
+            context: std::collections::HashMap::from([(
+                "tenant".to_string(),
+                name.to_string(),
+            )]),
+            feature_overrides: std::collections::HashMap::new(),
+            force_analyze: false,
+            return_features: false,
+        };
+
+        let mut features = FeatureSet::default();
+        features.set(Feature::DgaScore, 1.0);
+        let acme = tenant_for(&tenants, &request("acme")).unwrap();
+        let globex = tenant_for(&tenants, &request("globex")).unwrap();
+        let acme_model = acme.model.as_ref().unwrap();
+        let globex_model = globex.model.as_ref().unwrap();
+        assert_eq!(acme_model.version, "acme-1");
+        assert!(acme_model.predict(&acme_model.vector_for(&features)) > 0.9);
+        assert!(globex_model.predict(&globex_model.vector_for(&features)) < 0.1);
+
+        // Unknown tenants fall back to the shared configuration.
+        assert!(tenant_for(&tenants, &request("initech")).is_none());
+    }
+
+    #[test]
+    fn tenant_thresholds_shift_the_action_split() {
+        let strict = ThresholdConfig {
+            warn_threshold: 0.2,
+            block_threshold: 0.4,
+            ..ThresholdConfig::default()
+        };
+        assert_eq!(action_from_thresholds(0.3, &ThresholdConfig::default()), Action::Allow);
+        assert_eq!(action_from_thresholds(0.3, &strict), Action::Warn);
+        assert_eq!(action_from_thresholds(0.45, &strict), Action::Block);
+    }
+
+    #[test]
+    fn uncertainty_band_tracks_the_configured_thresholds() {
+        let t = ThresholdConfig {
+            warn_threshold: 0.4,
+            block_threshold: 0.9,
+            uncertainty_threshold: 0.05,
+            ..ThresholdConfig::default()
+        };
+        // Inside the band around either boundary.
+        assert!(is_uncertain(0.42, &t));
+        assert!(is_uncertain(0.87, &t));
+        // Exactly at a boundary is the most uncertain point of all.
+        assert!(is_uncertain(0.4, &t));
+        // The band edge itself is certain: the comparison is strict.
+        assert!(!is_uncertain(0.45, &t));
+        assert!(!is_uncertain(0.35, &t));
+        // The old hardcoded anchors (0.5, 0.8) no longer matter once the
+        // thresholds move away from them.
+        assert!(!is_uncertain(0.5, &t));
+        assert!(!is_uncertain(0.8, &t));
+    }
+
+    #[test]
+    fn error_posture_maps_to_the_substituted_action() {
+        use crate::config::OnErrorAction;
+
+        assert_eq!(action_for_engine_error(OnErrorAction::Reject), None);
+        assert_eq!(action_for_engine_error(OnErrorAction::Allow), Some(Action::Allow));
+        assert_eq!(action_for_engine_error(OnErrorAction::Block), Some(Action::Block));
+        // The historical behavior stays the default: errors propagate.
+        assert_eq!(
+            crate::config::ServerConfig::default().on_error_action,
+            OnErrorAction::Reject
+        );
+    }
+
+    #[test]
+    fn overriding_dga_score_drives_the_decision_to_block() {
+        let thresholds = ThresholdConfig::default();
+        let mut features = FeatureSet::default();
+        features.set(Feature::DgaScore, 0.2);
+        let organic = combine_scores(0.8, &features);
+        assert_ne!(action_from_thresholds(organic, &thresholds), Action::Block);
+
+        // The red-team overlay replaces the extracted value before the
+        // blend, so the same model output now crosses the block threshold.
+        features.set(Feature::DgaScore, 1.0);
+        let overridden = combine_scores(0.8, &features);
+        assert_eq!(action_from_thresholds(overridden, &thresholds), Action::Block);
+    }
+
+    #[test]
+    fn reasons_sort_by_salience_and_truncate() {
+        let reasons: Vec<String> = [
+            "High character entropy",
+            "Random-looking domain (possible DGA)",
+            "Possible brand impersonation",
+            "Listed on urlhaus (malware, confidence 0.90)",
+            "Similar to a popular domain (possible typosquatting)",
+            "High-risk TLD",
+        ]
+        .map(String::from)
+        .to_vec();
+        assert_eq!(
+            present_reasons(&reasons, 3),
+            vec![
+                "Listed on urlhaus (malware, confidence 0.90)".to_string(),
+                "Possible brand impersonation".to_string(),
+                "Similar to a popular domain (possible typosquatting)".to_string(),
+            ]
+        );
+        // Cap 0 keeps everything; the sort is stable, so generic reasons
+        // keep their original relative order at the tail.
+        let all = present_reasons(&reasons, 0);
+        assert_eq!(all.len(), reasons.len());
+        assert_eq!(all[all.len() - 2], "High character entropy");
+        assert_eq!(all[all.len() - 1], "High-risk TLD");
+        // The deadline marker always survives the cap: the response cache
+        // keys on it to avoid caching partial decisions.
+        let mut with_deadline = reasons.clone();
+        with_deadline.push(DEADLINE_REASON.to_string());
+        assert_eq!(present_reasons(&with_deadline, 1), vec![DEADLINE_REASON.to_string()]);
+        assert_eq!(crate::config::FeatureConfig::default().max_reasons, 5);
+    }
+
+    #[test]
+    fn popular_domain_anchor_holds_a_borderline_score_at_allow() {
+        let thresholds = ThresholdConfig::default();
+        let mut features = FeatureSet::default();
+
+        // Borderline model output alone lands in the WARN band ...
+        let borderline = combine_scores(0.75, &features);
+        assert_eq!(action_from_thresholds(borderline, &thresholds), Action::Warn);
+
+        // ... but the same output on an exact popular domain stays ALLOW.
+        features.set(Feature::IsPopularDomain, 1.0);
+        let anchored = combine_scores(0.75, &features);
+        assert!(anchored < borderline);
+        assert_eq!(action_from_thresholds(anchored, &thresholds), Action::Allow);
+    }
+
+    #[test]
+    fn repeated_malicious_feedback_erodes_a_domains_allow() {
+        let thresholds = ThresholdConfig::default();
+        let alpha = crate::config::ModelConfig::default().prior_alpha;
+        let mut features = FeatureSet::default();
+
+        // A modestly suspicious domain with no history scores under WARN,
+        // and a neutral prior is indistinguishable from no prior at all.
+        let fresh = combine_scores(0.65, &features);
+        features.set(Feature::DomainPrior, NEUTRAL_DOMAIN_PRIOR);
+        assert_eq!(combine_scores(0.65, &features), fresh);
+        assert_eq!(action_from_thresholds(fresh, &thresholds), Action::Allow);
+
+        // Four rounds of "this really was a threat" feedback ...
+        let mut prior = NEUTRAL_DOMAIN_PRIOR;
+        for _ in 0..4 {
+            let next = fold_domain_prior(prior, 1.0, alpha);
+            assert!(next > prior, "malicious feedback must raise the prior");
+            prior = next;
+        }
+
+        // ... and the same model output no longer clears the ALLOW bar.
+        features.set(Feature::DomainPrior, prior);
+        let repeat = combine_scores(0.65, &features);
+        assert!(repeat > fresh);
+        assert_eq!(action_from_thresholds(repeat, &thresholds), Action::Warn);
+    }
+
+    #[test]
+    fn disabled_bandit_band_resolution_is_deterministic_and_monotone() {
+        let t = ThresholdConfig::default(); // warn 0.5, block 0.8, midpoint 0.65
+        // Below the midpoint the plain threshold action stands ...
+        assert_eq!(deterministic_uncertain_action(0.45, &t), Action::Allow);
+        assert_eq!(deterministic_uncertain_action(0.55, &t), Action::Warn);
+        // ... and the upper half of the band resolves to BLOCK.
+        assert_eq!(deterministic_uncertain_action(0.65, &t), Action::Block);
+        assert_eq!(deterministic_uncertain_action(0.75, &t), Action::Block);
+        // Exploration stays the default posture.
+        assert!(crate::config::BanditConfig::default().enabled);
+    }
+
+    #[test]
+    fn spent_deadline_admits_only_the_hard_intel_stage() {
+        assert!(stage_admitted("hard_intel", Some(Duration::ZERO)));
+        assert!(!stage_admitted("features", Some(Duration::ZERO)));
+        assert!(!stage_admitted("model", Some(Duration::ZERO)));
+        // A live budget or no deadline at all admits everything.
+        assert!(stage_admitted("features", Some(Duration::from_millis(1))));
+        assert!(stage_admitted("model", None));
+    }
+
+    #[test]
+    fn hard_intel_confidence_gate_splits_block_and_warn() {
+        let low = crate::intel::HardIntelMatch {
+            source: "openphish".to_string(),
+            category: "phishing".to_string(),
+            confidence: 0.70,
+            matched: "shady.example".to_string(),
+        };
+        let (action, reason) = hard_intel_action(&low, 0.8);
+        assert_eq!(action, Action::Warn);
+        assert!(reason.contains("0.70"));
+
+        let high = crate::intel::HardIntelMatch {
+            confidence: 0.95,
+            ..low
+        };
+        let (action, reason) = hard_intel_action(&high, 0.8);
+        assert_eq!(action, Action::Block);
+        assert!(reason.contains("0.95"));
+    }
+
+    #[test]
+    fn sparse_features_lower_confidence_at_equal_probability() {
+        // Fully observed: every schema feature computed, DNS included.
+        let mut full = FeatureSet::default();
+        for name in crate::features::FEATURE_NAMES {
+            full.set_named(name, 0.1);
+        }
+        // Mostly defaulted: only a couple of lexical features made it.
+        let mut sparse = FeatureSet::default();
+        sparse.set(Feature::Entropy, 3.0);
+        sparse.set(Feature::DomainLength, 12.0);
+
+        let high = decision_confidence(&full, false, false);
+        let low = decision_confidence(&sparse, false, false);
+        assert!(high > low);
+        assert!(low < 0.1);
+
+        // A listing is near-ground truth even over sparse features, and
+        // exploration discounts an otherwise identical decision.
+        assert!(decision_confidence(&sparse, true, false) >= 0.9);
+        assert!(decision_confidence(&full, false, true) < high);
+    }
+
+    #[test]
+    fn hard_intel_block_probability_pins_or_passes_through() {
+        // Default: the source confidence goes out as reported.
+        assert_eq!(hard_intel_block_probability(0.85, None), 0.85);
+        // Pinned: the configured value replaces it, whatever the source said.
+        assert_eq!(hard_intel_block_probability(0.85, Some(1.0)), 1.0);
+        assert_eq!(hard_intel_block_probability(0.99, Some(0.9)), 0.9);
+        // A misconfigured pin still yields a probability.
+        assert_eq!(hard_intel_block_probability(0.85, Some(1.5)), 1.0);
+        // The default config keeps today's pass-through behavior.
+        assert_eq!(
+            crate::config::ThresholdConfig::default().hard_intel_block_probability,
+            None
+        );
+    }
+}
+
+/// Bind the listener — TLS when configured, plain HTTP otherwise — and
+/// serve the API.
+pub async fn run(engine: Arc<ThreatEngine>) -> Result<(), AppError> {
+    let app = crate::routes::router(engine.clone());
+    let server = &engine.config().server;
+    let addr: std::net::SocketAddr = format!("{}:{}", server.host, server.port)
+        .parse()
+        .map_err(|e| {
+            AppError::Config(config::ConfigError::Message(format!(
+                "invalid server.host/server.port {}:{}: {e}",
+                server.host, server.port
+            )))
+        })?;
+    let result = match engine.config().server.tls.clone() {
+        Some(tls) => {
+            // Load the cert/key up front so a bad path fails at startup,
+            // not at the first handshake. ALPN offers h2 and http/1.1.
+            let rustls = axum_server::tls_rustls::RustlsConfig::from_pem_file(
+                &tls.cert_path,
+                &tls.key_path,
+            )
+            .await
+            .map_err(|e| {
+                AppError::Config(config::ConfigError::Message(format!(
+                    "cannot load TLS certificate {} / key {}: {e}",
+                    tls.cert_path, tls.key_path
+                )))
+            })?;
+            info!(%addr, "listening (TLS)");
+            axum_server::bind_rustls(addr, rustls)
+                .serve(app.into_make_service())
+                .await
+                .map_err(|e| AppError::Internal(format!("server error: {e}")))
+        }
+        None => {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| AppError::Internal(format!("bind to {addr} failed: {e}")))?;
+            info!(%addr, "listening");
+            axum::serve(listener, app)
+                .await
+                .map_err(|e| AppError::Internal(format!("server error: {e}")))
+        }
+    };
+    // Drain queued decision logs and contexts so shutdown does not lose them.
+    engine.logger().shutdown().await;
+    result
+}
@@ -0,0 +1,53 @@
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde_json::json;
+use thiserror::Error;
+
+/// Unified error type for the scoring engine. Everything that can fail on a
+/// request path converges here so handlers can simply `?` and let axum render
+/// a sensible status code.
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("configuration error: {0}")]
+    Config(#[from] config::ConfigError),
+
+    #[error("storage error: {0}")]
+    Storage(#[from] clickhouse::error::Error),
+
+    #[error("redis error: {0}")]
+    Redis(#[from] redis::RedisError),
+
+    #[error("intel error: {0}")]
+    Intel(String),
+
+    #[error("feature extraction error: {0}")]
+    Feature(String),
+
+    #[error("model error: {0}")]
+    Model(String),
+
+    #[error("invalid request: {0}")]
+    InvalidRequest(String),
+
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+
+    #[error("serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+
+    #[error("internal error: {0}")]
+    Internal(String),
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            AppError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        let body = Json(json!({ "error": self.to_string() }));
+        (status, body).into_response()
+    }
+}
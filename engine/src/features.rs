@@ -0,0 +1,2431 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use addr::parse_domain_name;
+use aho_corasick::AhoCorasick;
+use ip_network::IpNetwork;
+use tokio::sync::RwLock;
+use trust_dns_resolver::config::{NameServerConfigGroup, ResolverConfig, ResolverOpts};
+use trust_dns_resolver::error::{ResolveError, ResolveErrorKind};
+use trust_dns_resolver::proto::op::ResponseCode;
+use trust_dns_resolver::TokioAsyncResolver;
+use url::Url;
+
+use crate::config::{FeatureConfig, KeywordMatching};
+use crate::error::AppError;
+use crate::geo::GeoLookup;
+
+/// Version of the feature schema below, reported in score responses so
+/// logged decisions can be attributed to the schema they were scored
+/// under. Bump whenever `FEATURE_NAMES` changes shape or semantics.
+pub const FEATURE_SCHEMA_VERSION: u32 = 10;
+
+/// Declares the canonical feature schema once: the positional [`Feature`]
+/// index, the parallel `FEATURE_NAMES` list, and the name lookup, kept in
+/// lockstep by construction.
+macro_rules! feature_schema {
+    ($($variant:ident => $name:literal),+ $(,)?) => {
+        /// Positional index into the canonical feature schema; each
+        /// discriminant is the feature's position in `FEATURE_NAMES`.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Feature {
+            $($variant),+
+        }
+
+        /// The canonical feature schema. Order matters: vectors are indexed
+        /// by position in this list, and models may declare any subset of
+        /// these names as their own weight schema.
+        pub const FEATURE_NAMES: &[&str] = &[$($name),+];
+
+        impl Feature {
+            /// The feature for a schema name; `None` for unknown names.
+            pub fn from_name(name: &str) -> Option<Feature> {
+                match name {
+                    $($name => Some(Feature::$variant),)+
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+feature_schema! {
+    // Basic lexical features of the domain itself.
+    DomainLength => "domain_length",
+    SldLength => "sld_length",
+    SubdomainCount => "subdomain_count",
+    DigitRatio => "digit_ratio",
+    DashCount => "dash_count",
+    VowelRatio => "vowel_ratio",
+    ConsonantRatio => "consonant_ratio",
+    Entropy => "entropy",
+    SldEntropy => "sld_entropy",
+    TldRisk => "tld_risk",
+    IdnPunycode => "idn_punycode",
+    MaxDigitRun => "max_digit_run",
+    TokenCount => "token_count",
+    LetterDigitAlternations => "letter_digit_alternations",
+    // Repetition over the SLD label: DGA and spam generators love runs
+    // (`aaabbb`) and stuttered n-grams.
+    MaxCharRepeat => "max_char_repeat",
+    RepeatedBigramRatio => "repeated_bigram_ratio",
+    // Intranet hostname: a bare single label, a configured internal
+    // suffix, or a name the PSL cannot parse; see `is_internal_host`.
+    IsInternalHost => "is_internal_host",
+    // Detector scores.
+    HomoglyphScore => "homoglyph_score",
+    TyposquattingScore => "typosquatting_score",
+    DgaScore => "dga_score",
+    SuspiciousKeywordCount => "suspicious_keyword_count",
+    DictionaryWordCount => "dictionary_word_count",
+    BrandImpersonation => "brand_impersonation",
+    Combosquatting => "combosquatting",
+    IsPopularDomain => "is_popular_domain",
+    // Below-gate hard-intel match confidence (synthesized in the engine).
+    HardIntelHit => "hard_intel_hit",
+    // Cluster velocity of newly-seen domains (synthesized in the engine),
+    // as a ratio of the configured campaign threshold.
+    DomainVelocity => "domain_velocity",
+    // Learned per-domain reputation (synthesized in the engine): an EWMA
+    // of feedback labels, 0.5 for domains with no history.
+    DomainPrior => "domain_prior",
+    // URL features. `url_present` is always computed (1.0 when a non-empty
+    // URL came with the request); the rest are only populated — and only
+    // meaningful — when it is 1.0, since an absent feature reads as 0.0 in
+    // the model, indistinguishable from a benign URL.
+    UrlPresent => "url_present",
+    UrlLength => "url_length",
+    PathDepth => "path_depth",
+    QueryParamCount => "query_param_count",
+    UrlEntropy => "url_entropy",
+    HasIpHost => "has_ip_host",
+    HasAtSymbol => "has_at_symbol",
+    UrlKeywordCount => "url_keyword_count",
+    SuspiciousFileExtensions => "suspicious_file_extensions",
+    UsesHttps => "uses_https",
+    // DNS features.
+    DnsRecordCount => "dns_record_count",
+    DnsNxdomain => "dns_nxdomain",
+    ResolvedIpCount => "resolved_ip_count",
+    // Any resolved address in private/loopback/bogon space; enforced by
+    // `thresholds.private_ip_action` as well as feeding the model.
+    ResolvesToPrivate => "resolves_to_private",
+    NsCount => "ns_count",
+    MxPresent => "mx_present",
+    HasSpf => "has_spf",
+    // SPF `all`-qualifier strictness, set alongside `has_spf`; see
+    // `spf_policy_score` for the scale.
+    SpfPolicy => "spf_policy",
+    HasDmarc => "has_dmarc",
+    TtlMin => "ttl_min",
+    DnsRebindingFlag => "dns_rebinding_flag",
+    GeolocationRisk => "geolocation_risk",
+    GeoMismatch => "geo_mismatch",
+    // Request-context features, synthesized per request from the recognized
+    // `context` keys; see `FeatureExtractor::context_features`.
+    SourceIpRisk => "source_ip_risk",
+    ReferrerMismatch => "referrer_mismatch",
+    UaAutomation => "ua_automation",
+    ClientCountryRisk => "client_country_risk",
+    // Analyzer (deep content) features, populated out of band.
+    ResponseTimeMs => "response_time_ms",
+    ContentTypeSuspicious => "content_type_suspicious",
+    FormCount => "form_count",
+    InputFieldCount => "input_field_count",
+    SocialEngineeringIndicators => "social_engineering_indicators",
+    UrgencyLanguage => "urgency_language",
+    TrustIndicatorsMissing => "trust_indicators_missing",
+    JavascriptObfuscated => "javascript_obfuscated",
+    CryptoMiningScripts => "crypto_mining_scripts",
+    RedirectCount => "redirect_count",
+    ExternalResourceRatio => "external_resource_ratio",
+    PageSizeKb => "page_size_kb",
+    TitleBrandMismatch => "title_brand_mismatch",
+    HostMismatch => "host_mismatch",
+    CertSanMismatch => "cert_san_mismatch",
+    // Registration / certificate metadata.
+    DomainAgeDays => "domain_age_days",
+    CertAgeDays => "cert_age_days",
+    RegistrarRisk => "registrar_risk",
+}
+
+/// Number of features in the canonical schema.
+pub const FEATURE_COUNT: usize = FEATURE_NAMES.len();
+
+/// Canonical form of a caller-supplied domain: trimmed, the FQDN trailing
+/// dot removed, lowercased. Every place a domain becomes a cache key —
+/// the response cache, the feature cache, the intel lookup cache — or a
+/// scoring subject must go through this, so `example.com`, `EXAMPLE.com`,
+/// and `example.com.` share one entry and one verdict.
+pub fn normalize_domain(raw: &str) -> String {
+    raw.trim().trim_end_matches('.').to_lowercase()
+}
+
+/// Why a (normalized) domain cannot be a hostname at all, or `None` for a
+/// syntactically plausible name. RFC 1035/1123 label rules: 1-63
+/// characters per label, letters, digits and interior hyphens, 253 total.
+/// IDN is accepted both as punycode (`xn--`) and as raw Unicode letters —
+/// the extractor's punycode and homoglyph features score those — while
+/// underscores, spaces and empty labels are impossible in any hostname.
+/// Existence is a separate question: a well-formed name that resolves
+/// nowhere still gets scored, with its DNS features simply absent.
+pub fn domain_syntax_error(domain: &str) -> Option<String> {
+    if domain.is_empty() {
+        return Some("domain is empty".to_string());
+    }
+    if domain.len() > 253 {
+        return Some(format!("domain exceeds 253 characters ({})", domain.len()));
+    }
+    for label in domain.split('.') {
+        if label.is_empty() {
+            return Some("empty label (leading or consecutive dots)".to_string());
+        }
+        if label.len() > 63 {
+            return Some(format!("label \"{label}\" exceeds 63 characters"));
+        }
+        if label.starts_with('-') || label.ends_with('-') {
+            return Some(format!("label \"{label}\" starts or ends with a hyphen"));
+        }
+        if let Some(c) = label.chars().find(|c| *c != '-' && !c.is_alphanumeric()) {
+            return Some(format!("character {c:?} is not valid in a hostname label"));
+        }
+    }
+    None
+}
+
+/// A dense feature set over the canonical schema: a fixed array indexed by
+/// [`Feature`] position plus a presence mask. This replaces the per-request
+/// `HashMap<String, f32>` that allocated dozens of `String` keys on every
+/// score; the only remaining allocation is the conversion to a named map
+/// at serialization boundaries. "Not computed" stays distinct from 0.0,
+/// which the DNS features rely on (an unset `dns_record_count` means
+/// "could not ask", not "no records").
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeatureSet {
+    values: [f32; FEATURE_COUNT],
+    present: [bool; FEATURE_COUNT],
+}
+
+impl Default for FeatureSet {
+    fn default() -> Self {
+        Self {
+            values: [0.0; FEATURE_COUNT],
+            present: [false; FEATURE_COUNT],
+        }
+    }
+}
+
+impl FeatureSet {
+    pub fn set(&mut self, feature: Feature, value: f32) {
+        self.values[feature as usize] = value;
+        self.present[feature as usize] = true;
+    }
+
+    /// The value when the feature was computed for this request.
+    pub fn get(&self, feature: Feature) -> Option<f32> {
+        self.present[feature as usize].then(|| self.values[feature as usize])
+    }
+
+    /// The value, with absent features reading as 0.0 — the same padding
+    /// the model applies.
+    pub fn value(&self, feature: Feature) -> f32 {
+        self.values[feature as usize]
+    }
+
+    /// Set by schema name, for config- and request-driven paths; returns
+    /// false (and changes nothing) for names outside the schema.
+    pub fn set_named(&mut self, name: &str, value: f32) -> bool {
+        match Feature::from_name(name) {
+            Some(feature) => {
+                self.set(feature, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn get_named(&self, name: &str) -> Option<f32> {
+        Feature::from_name(name).and_then(|feature| self.get(feature))
+    }
+
+    /// Project named features into the `f64` domain of the linear-algebra
+    /// internals (student model, bandit context); absent features pad to
+    /// 0.0. Feature values and probabilities are `f32` everywhere they are
+    /// stored or serialized — this dense array, the wire types, ClickHouse
+    /// columns — and `f64` only inside model/bandit accumulation. This
+    /// widening and the narrowing in `StudentModel::predict` are the two
+    /// sanctioned crossings between the domains.
+    pub fn project(&self, names: &[String]) -> Vec<f64> {
+        names
+            .iter()
+            .map(|name| self.get_named(name).unwrap_or(0.0) as f64)
+            .collect()
+    }
+
+    /// Computed features in schema order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, f32)> + '_ {
+        FEATURE_NAMES
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.present[*index])
+            .map(|(index, name)| (*name, self.values[index]))
+    }
+
+    /// (schema index, value) pairs of the computed features, for consumers
+    /// that keep parallel per-feature arrays.
+    pub fn indexed(&self) -> impl Iterator<Item = (usize, f32)> + '_ {
+        (0..FEATURE_COUNT)
+            .filter(|index| self.present[*index])
+            .map(|index| (index, self.values[index]))
+    }
+
+    /// Values of the computed features, in schema order.
+    pub fn values(&self) -> impl Iterator<Item = f32> + '_ {
+        (0..FEATURE_COUNT)
+            .filter(|index| self.present[*index])
+            .map(|index| self.values[index])
+    }
+
+    /// Number of computed features.
+    pub fn len(&self) -> usize {
+        self.present.iter().filter(|present| **present).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.present.iter().any(|present| *present)
+    }
+
+    /// The named-map view, for logging, responses, and queue payloads —
+    /// everywhere the keys cross a serialization boundary.
+    pub fn to_named_map(&self) -> HashMap<String, f32> {
+        self.iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect()
+    }
+
+    /// Rebuild from a named map (stored decision JSON, analyzer overlays);
+    /// unknown names are dropped.
+    pub fn from_named_map(map: &HashMap<String, f32>) -> Self {
+        let mut features = Self::default();
+        for (name, value) in map {
+            features.set_named(name, *value);
+        }
+        features
+    }
+
+    /// Overlay every computed feature of `other` onto `self`.
+    pub fn extend(&mut self, other: &FeatureSet) {
+        for index in 0..FEATURE_COUNT {
+            if other.present[index] {
+                self.values[index] = other.values[index];
+                self.present[index] = true;
+            }
+        }
+    }
+}
+
+/// Indexing by schema name, mirroring the old map indexing in tests and
+/// debug paths: panics when the name is unknown or the feature was not
+/// computed, exactly as `HashMap` indexing panicked on a missing key.
+impl std::ops::Index<&str> for FeatureSet {
+    type Output = f32;
+
+    fn index(&self, name: &str) -> &f32 {
+        let feature =
+            Feature::from_name(name).unwrap_or_else(|| panic!("unknown feature \"{name}\""));
+        assert!(
+            self.present[feature as usize],
+            "feature \"{name}\" not computed"
+        );
+        &self.values[feature as usize]
+    }
+}
+
+pub const SUSPICIOUS_KEYWORDS: &[&str] = &[
+    "login", "signin", "verify", "account", "secure", "update", "banking",
+    "confirm", "password", "wallet", "invoice", "payment", "alert", "suspend",
+    "unlock", "click", "free", "bonus", "prize", "urgent",
+];
+
+const DICTIONARY_WORDS: &[&str] = &[
+    "account", "bank", "book", "cash", "click", "face", "free", "game", "home",
+    "info", "login", "mail", "money", "news", "online", "pay", "play", "safe",
+    "secure", "shop", "site", "store", "tech", "time", "web", "work",
+];
+
+/// Registrable domains common enough that lexical similarity to them is a
+/// strong typosquatting signal.
+pub const POPULAR_DOMAINS: &[&str] = &[
+    "google.com", "youtube.com", "facebook.com", "amazon.com", "wikipedia.org",
+    "twitter.com", "instagram.com", "microsoft.com", "apple.com", "netflix.com",
+    "paypal.com", "linkedin.com", "reddit.com", "ebay.com", "github.com",
+    "dropbox.com", "adobe.com", "spotify.com", "whatsapp.com", "office.com",
+];
+
+/// TLDs disproportionately used for abuse, with a risk weight.
+const RISKY_TLDS: &[(&str, f32)] = &[
+    ("tk", 1.0),
+    ("ml", 1.0),
+    ("ga", 1.0),
+    ("cf", 1.0),
+    ("gq", 1.0),
+    ("zip", 0.9),
+    ("top", 0.8),
+    ("xyz", 0.7),
+    ("club", 0.7),
+    ("work", 0.7),
+    ("loan", 0.8),
+    ("icu", 0.7),
+];
+
+/// Executable and script extensions weighted by how often they show up in
+/// malware delivery.
+const DANGEROUS_EXTENSIONS: &[(&str, f32)] = &[
+    ("exe", 1.0),
+    ("scr", 1.0),
+    ("pif", 1.0),
+    ("hta", 1.0),
+    ("bat", 0.9),
+    ("cmd", 0.9),
+    ("vbs", 0.9),
+    ("ps1", 0.9),
+    ("jar", 0.8),
+    ("apk", 0.8),
+    ("msi", 0.8),
+    ("js", 0.7),
+    ("dll", 0.7),
+];
+
+/// Document/image extensions that, placed before a dangerous one, mark a
+/// disguised double extension ("invoice.pdf.exe").
+const DECOY_EXTENSIONS: &[&str] = &[
+    "pdf", "doc", "docx", "xls", "xlsx", "ppt", "pptx", "txt", "jpg", "jpeg",
+    "png", "gif", "html",
+];
+
+/// ASCII characters commonly substituted for letters in homoglyph attacks.
+const HOMOGLYPHS: &[(char, char)] = &[
+    ('0', 'o'),
+    ('1', 'l'),
+    ('3', 'e'),
+    ('4', 'a'),
+    ('5', 's'),
+    ('7', 't'),
+    ('8', 'b'),
+    ('@', 'a'),
+    ('$', 's'),
+];
+
+/// Map homoglyph characters back to the letters they imitate.
+fn normalize_homoglyphs(s: &str) -> String {
+    s.chars()
+        .map(|c| {
+            HOMOGLYPHS
+                .iter()
+                .find(|(from, _)| *from == c)
+                .map(|(_, to)| *to)
+                .unwrap_or(c)
+        })
+        .collect()
+}
+
+/// Canonical brands with the alias strings that attribute to them. Every
+/// popular-domain SLD is a brand with itself as its only alias;
+/// `features.brand_aliases_path` layers short forms and localized variants
+/// (`msft` → microsoft) on top. Aliases are stored homoglyph-normalized
+/// and matched against normalized labels, so the table never needs to
+/// enumerate digit substitutions.
+pub struct BrandTable {
+    /// (alias, canonical brand) pairs, aliases normalized.
+    entries: Vec<(String, String)>,
+}
+
+impl BrandTable {
+    /// The table implied by `POPULAR_DOMAINS` alone.
+    pub(crate) fn builtin() -> Self {
+        let entries = POPULAR_DOMAINS
+            .iter()
+            .map(|popular| {
+                let brand = popular.split('.').next().unwrap_or(popular);
+                (brand.to_string(), brand.to_string())
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// The built-in table plus the aliases configured at `path`. Like the
+    /// GeoIP database, an unreadable or malformed file logs and leaves the
+    /// built-in brands in place rather than failing startup.
+    pub(crate) fn load(path: Option<&str>) -> Self {
+        let mut table = Self::builtin();
+        let Some(path) = path else { return table };
+        let parsed: Result<HashMap<String, Vec<String>>, String> =
+            std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_str(&data).map_err(|e| e.to_string()));
+        match parsed {
+            Ok(brands) => {
+                for (brand, aliases) in brands {
+                    table
+                        .entries
+                        .push((normalize_homoglyphs(&brand), brand.clone()));
+                    for alias in aliases {
+                        table
+                            .entries
+                            .push((normalize_homoglyphs(&alias), brand.clone()));
+                    }
+                }
+            }
+            Err(e) => tracing::warn!(
+                error = %e,
+                path,
+                "brand alias table unreadable, using built-in brands only"
+            ),
+        }
+        table
+    }
+
+    /// The canonical brand `label` is an alias of, after homoglyph
+    /// normalization. Exact aliases only — near-misses are the
+    /// typosquatting detector's business.
+    pub(crate) fn canonical(&self, label: &str) -> Option<&str> {
+        let normalized = normalize_homoglyphs(label);
+        self.entries
+            .iter()
+            .find(|(alias, _)| *alias == normalized)
+            .map(|(_, brand)| brand.as_str())
+    }
+
+    /// All (alias, canonical brand) pairs.
+    pub(crate) fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(alias, brand)| (alias.as_str(), brand.as_str()))
+    }
+}
+
+struct CachedFeatures {
+    features: FeatureSet,
+    cached_at: Instant,
+    /// Estimated footprint of this entry, counted against `cache_max_bytes`.
+    bytes: usize,
+}
+
+/// Wall time spent in one extraction stage, for the debug endpoint.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct StageTiming {
+    pub stage: &'static str,
+    pub duration_ms: f64,
+}
+
+pub struct FeatureExtractor {
+    config: FeatureConfig,
+    resolver: Option<TokioAsyncResolver>,
+    /// Resolver pinned to the configured trusted nameservers, queried
+    /// independently of the system resolver for the rebinding cross-check.
+    trusted_resolver: Option<TokioAsyncResolver>,
+    /// GeoIP reader, when a database is configured and readable.
+    geo: Option<Box<dyn GeoLookup>>,
+    /// Canonical brands and their aliases for the impersonation detectors.
+    brands: BrandTable,
+    cache: RwLock<HashMap<String, CachedFeatures>>,
+    /// Estimated bytes held by the cache; mutated under the cache write
+    /// lock, atomic so the metrics endpoint can read it without locking.
+    cache_bytes: std::sync::atomic::AtomicUsize,
+    cache_evictions: std::sync::atomic::AtomicU64,
+    cache_hits: std::sync::atomic::AtomicU64,
+}
+
+impl FeatureExtractor {
+    pub fn new(config: FeatureConfig) -> Self {
+        let resolver = if config.dns_enabled {
+            TokioAsyncResolver::tokio_from_system_conf().ok()
+        } else {
+            None
+        };
+        let trusted_resolver = if config.dns_enabled {
+            trusted_resolver(&config.trusted_resolvers)
+        } else {
+            None
+        };
+        let geo = config.geoip_path.as_deref().and_then(|path| {
+            match crate::geo::MaxmindGeo::open(path) {
+                Ok(reader) => Some(Box::new(reader) as Box<dyn GeoLookup>),
+                Err(e) => {
+                    tracing::warn!(error = %e, "GeoIP disabled, geolocation features stay neutral");
+                    None
+                }
+            }
+        });
+        let brands = BrandTable::load(config.brand_aliases_path.as_deref());
+        Self {
+            config,
+            resolver,
+            trusted_resolver,
+            geo,
+            brands,
+            cache: RwLock::new(HashMap::new()),
+            cache_bytes: std::sync::atomic::AtomicUsize::new(0),
+            cache_evictions: std::sync::atomic::AtomicU64::new(0),
+            cache_hits: std::sync::atomic::AtomicU64::new(0),
+        }
+    }
+
+    /// The brand table the impersonation detectors run against.
+    pub(crate) fn brands(&self) -> &BrandTable {
+        &self.brands
+    }
+
+    /// The canonical brand the domain's first label resolves to after
+    /// homoglyph normalization, for reason attribution.
+    pub(crate) fn impersonated_brand(&self, domain: &str) -> Option<&str> {
+        let sld = domain.split('.').next().unwrap_or(domain);
+        self.brands.canonical(sld)
+    }
+
+    /// Estimated bytes currently held by the feature cache.
+    pub fn cache_bytes(&self) -> usize {
+        self.cache_bytes.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Entries evicted to stay within the count or byte bounds.
+    pub fn cache_evictions(&self) -> u64 {
+        self.cache_evictions
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Extractions served from the cache.
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits.load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Extract the full feature set for a domain (and optionally a URL).
+    pub async fn extract(
+        &self,
+        domain: &str,
+        url: Option<&str>,
+    ) -> Result<FeatureSet, AppError> {
+        {
+            let cache = self.cache.read().await;
+            if let Some(cached) = cache.get(domain) {
+                if cached.cached_at.elapsed()
+                    < Duration::from_secs(self.config.cache_ttl_seconds)
+                {
+                    self.cache_hits
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    return Ok(cached.features.clone());
+                }
+            }
+        }
+
+        let mut features = FeatureSet::default();
+        self.extract_basic_features(domain, &mut features)?;
+        self.extract_homoglyph_features(domain, &mut features);
+        self.extract_typosquatting_features(domain, &mut features);
+        self.extract_combosquatting_features(domain, &mut features);
+        self.extract_dga_features(domain, &mut features);
+
+        features.set(
+            Feature::SuspiciousKeywordCount,
+            count_suspicious_keywords(domain, self.config.keyword_matching) as f32,
+        );
+        features.set(Feature::DictionaryWordCount, count_dictionary_words(domain) as f32);
+
+        // An empty URL is host-only scoring just like an absent one; the
+        // marker lets the model tell "no URL data" from a benign URL.
+        let url = url.filter(|u| !u.trim().is_empty());
+        features.set(Feature::UrlPresent, if url.is_some() { 1.0 } else { 0.0 });
+        if let Some(url) = url {
+            self.extract_url_features(url, &mut features)?;
+        }
+
+        if self.resolver.is_some() {
+            self.extract_dns_features(domain, &mut features).await;
+        }
+
+        sanitize_features(&mut features);
+
+        use std::sync::atomic::Ordering;
+
+        let bytes = estimated_entry_bytes(domain);
+        let mut cache = self.cache.write().await;
+        if let Some(replaced) = cache.remove(domain) {
+            self.cache_bytes.fetch_sub(replaced.bytes, Ordering::Relaxed);
+        }
+        // Evict the oldest entries until both the count bound and the byte
+        // budget hold. The incoming entry is always admitted, even when it
+        // alone exceeds the budget.
+        while !cache.is_empty()
+            && (cache.len() >= self.config.cache_max_entries
+                || self.cache_bytes.load(Ordering::Relaxed) + bytes
+                    > self.config.cache_max_bytes)
+        {
+            if let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, v)| v.cached_at)
+                .map(|(k, _)| k.clone())
+            {
+                if let Some(evicted) = cache.remove(&oldest) {
+                    self.cache_bytes.fetch_sub(evicted.bytes, Ordering::Relaxed);
+                    self.cache_evictions.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+        self.cache_bytes.fetch_add(bytes, Ordering::Relaxed);
+        cache.insert(
+            domain.to_string(),
+            CachedFeatures {
+                features: features.clone(),
+                cached_at: Instant::now(),
+                bytes,
+            },
+        );
+
+        Ok(features)
+    }
+
+    /// Overlay out-of-band feature values (analyzer results) onto the cached
+    /// entry for a domain so subsequent scores pick them up.
+    pub async fn merge_features(&self, domain: &str, updates: HashMap<String, f32>) {
+        let mut cache = self.cache.write().await;
+        // Only overlay onto a full cached extraction; a partial entry would
+        // otherwise be served as the complete feature set.
+        if let Some(cached) = cache.get_mut(domain) {
+            // The entry footprint is fixed, so no byte re-accounting: names
+            // outside the schema are dropped rather than stored.
+            for (name, value) in &updates {
+                cached.features.set_named(name, *value);
+            }
+            cached.cached_at = Instant::now();
+        }
+    }
+
+    /// Map recognized request-context keys onto features. These describe
+    /// who is asking rather than the domain itself, so like velocity they
+    /// are synthesized per request instead of cached with the domain.
+    /// Recognized keys — everything else in the map is ignored:
+    /// - `source_ip`: the client address, fed through the same country-risk
+    ///   table as hosting IPs (`source_ip_risk`).
+    /// - `referrer`: a URL whose registrable domain is compared against the
+    ///   scored domain (`referrer_mismatch`).
+    /// - `user_agent`: matched against common automation agents
+    ///   (`ua_automation`).
+    /// - `client_country`: an ISO 3166-1 alpha-2 code fed through the
+    ///   country-risk table (`client_country_risk`).
+    pub fn context_features(
+        &self,
+        context: &HashMap<String, String>,
+        domain: &str,
+    ) -> FeatureSet {
+        let mut features = FeatureSet::default();
+        if let (Some(ip), Some(geo)) = (
+            context.get("source_ip").and_then(|v| v.parse().ok()),
+            &self.geo,
+        ) {
+            let risk = geo
+                .country_code(ip)
+                .and_then(|code| self.config.geoip_country_risk.get(&code).copied())
+                .unwrap_or(0.0);
+            features.set(Feature::SourceIpRisk, risk);
+        }
+        if let Some(referrer) = context.get("referrer") {
+            features.set(Feature::ReferrerMismatch, referrer_mismatch(referrer, domain));
+        }
+        if let Some(agent) = context.get("user_agent") {
+            features.set(Feature::UaAutomation, ua_automation(agent));
+        }
+        if let Some(code) = context.get("client_country") {
+            let risk = self
+                .config
+                .geoip_country_risk
+                .get(&code.to_uppercase())
+                .copied()
+                .unwrap_or(0.0);
+            features.set(Feature::ClientCountryRisk, risk);
+        }
+        features
+    }
+
+    /// Like [`extract`](Self::extract), but bypasses the cache and records
+    /// the wall time of each stage. Only used by the debug endpoint, so the
+    /// timing overhead never touches the hot path.
+    pub async fn extract_traced(
+        &self,
+        domain: &str,
+        url: Option<&str>,
+    ) -> Result<(FeatureSet, Vec<StageTiming>), AppError> {
+        let mut features = FeatureSet::default();
+        let mut timings = Vec::new();
+
+        let mut time_stage = |stage: &'static str, started: Instant| {
+            timings.push(StageTiming {
+                stage,
+                duration_ms: started.elapsed().as_secs_f64() * 1000.0,
+            });
+        };
+
+        let started = Instant::now();
+        self.extract_basic_features(domain, &mut features)?;
+        features.set(
+            Feature::SuspiciousKeywordCount,
+            count_suspicious_keywords(domain, self.config.keyword_matching) as f32,
+        );
+        features.set(Feature::DictionaryWordCount, count_dictionary_words(domain) as f32);
+        time_stage("basic", started);
+
+        let started = Instant::now();
+        self.extract_homoglyph_features(domain, &mut features);
+        time_stage("homoglyph", started);
+
+        let started = Instant::now();
+        self.extract_typosquatting_features(domain, &mut features);
+        time_stage("typo", started);
+
+        let started = Instant::now();
+        self.extract_combosquatting_features(domain, &mut features);
+        time_stage("combosquat", started);
+
+        let started = Instant::now();
+        self.extract_dga_features(domain, &mut features);
+        time_stage("dga", started);
+
+        let url = url.filter(|u| !u.trim().is_empty());
+        features.set(Feature::UrlPresent, if url.is_some() { 1.0 } else { 0.0 });
+        if let Some(url) = url {
+            let started = Instant::now();
+            self.extract_url_features(url, &mut features)?;
+            time_stage("url", started);
+        }
+
+        if self.resolver.is_some() {
+            let started = Instant::now();
+            self.extract_dns_features(domain, &mut features).await;
+            time_stage("dns", started);
+        }
+
+        sanitize_features(&mut features);
+
+        Ok((features, timings))
+    }
+
+    fn extract_basic_features(
+        &self,
+        domain: &str,
+        features: &mut FeatureSet,
+    ) -> Result<(), AppError> {
+        let internal = is_internal_host(domain, &self.config.internal_suffixes);
+        features.set(Feature::IsInternalHost, if internal { 1.0 } else { 0.0 });
+        // Intranet hostnames have no recognized public suffix, so the PSL
+        // parse cannot be a hard requirement: fall back to the first label
+        // and keep scoring instead of failing the request.
+        let sld = parse_domain_name(domain)
+            .ok()
+            .and_then(|parsed| parsed.root())
+            .unwrap_or(domain);
+        let sld_label = sld.split('.').next().unwrap_or(sld);
+
+        let total_chars = domain.len() as f32;
+        let numeric_count = domain.chars().filter(|c| c.is_ascii_digit()).count() as f32;
+        let vowel_count = domain
+            .chars()
+            .filter(|c| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u'))
+            .count() as f32;
+        let consonant_count = domain
+            .chars()
+            .filter(|c| c.is_ascii_alphabetic() && !matches!(c, 'a' | 'e' | 'i' | 'o' | 'u'))
+            .count() as f32;
+
+        features.set(Feature::DomainLength, total_chars);
+        features.set(Feature::SldLength, sld_label.len() as f32);
+        features.set(
+            Feature::SubdomainCount,
+            domain.matches('.').count().saturating_sub(1) as f32,
+        );
+        // Guard the ratio denominators: an empty domain or a vowel-free
+        // label must not push NaN/Inf into the model.
+        features.set(
+            Feature::DigitRatio,
+            if total_chars > 0.0 { numeric_count / total_chars } else { 0.0 },
+        );
+        features.set(Feature::DashCount, domain.matches('-').count() as f32);
+        features.set(
+            Feature::VowelRatio,
+            if total_chars > 0.0 { vowel_count / total_chars } else { 0.0 },
+        );
+        features.set(
+            Feature::ConsonantRatio,
+            if vowel_count > 0.0 { consonant_count / vowel_count } else { 0.0 },
+        );
+        // Two entropies, kept apart on purpose: `entropy` covers the full
+        // FQDN (dots and TLD included) and stays as a model input for
+        // compatibility; `sld_entropy` covers only the registrable label,
+        // so `www.` prefixes and long TLDs cannot skew it.
+        features.set(Feature::Entropy, calculate_entropy(domain));
+        features.set(Feature::SldEntropy, calculate_entropy(sld_label));
+
+        let tld = domain.rsplit('.').next().unwrap_or("");
+        let tld_risk = RISKY_TLDS
+            .iter()
+            .find(|(t, _)| *t == tld)
+            .map(|(_, r)| *r)
+            .unwrap_or(0.0);
+        features.set(Feature::TldRisk, tld_risk);
+        // Exact membership in the popular list, as a soft anchor: unlike
+        // the allowlist it never short-circuits, it just gives the score
+        // blend something to hold top domains down with when incidental
+        // lexical values (length, entropy) drift mid-range.
+        features.set(
+            Feature::IsPopularDomain,
+            if is_popular_domain(domain) { 1.0 } else { 0.0 },
+        );
+        features.set(
+            Feature::IdnPunycode,
+            if domain.split('.').any(|l| l.starts_with("xn--")) {
+                1.0
+            } else {
+                0.0
+            },
+        );
+
+        // Structural patterns typical of algorithmically generated domains:
+        // long digit runs, many hyphen-separated tokens, and letter/digit
+        // alternation (`a1b2c3`).
+        features.set(Feature::MaxDigitRun, max_digit_run(sld_label) as f32);
+        features.set(
+            Feature::TokenCount,
+            sld_label.split('-').filter(|t| !t.is_empty()).count() as f32,
+        );
+        features.set(
+            Feature::LetterDigitAlternations,
+            letter_digit_alternations(sld_label) as f32,
+        );
+        // Repetition signals, single-pass over the same label.
+        features.set(Feature::MaxCharRepeat, max_char_repeat(sld_label) as f32);
+        features.set(
+            Feature::RepeatedBigramRatio,
+            repeated_bigram_ratio(sld_label),
+        );
+        Ok(())
+    }
+
+    fn extract_homoglyph_features(&self, domain: &str, features: &mut FeatureSet) {
+        let sld = domain.split('.').next().unwrap_or(domain);
+        let normalized = normalize_homoglyphs(sld);
+        let confusable = sld
+            .chars()
+            .filter(|c| HOMOGLYPHS.iter().any(|(from, _)| from == c))
+            .count();
+
+        let mut score = if sld.is_empty() {
+            0.0
+        } else {
+            confusable as f32 / sld.len() as f32
+        };
+        let mut impersonation = 0.0;
+        // `normalized != sld` keeps a brand (or alias) spelled straight
+        // from counting as its own impersonation.
+        if normalized != sld && self.brands.canonical(sld).is_some() {
+            score = 1.0;
+            impersonation = 1.0;
+        }
+        features.set(Feature::HomoglyphScore, score);
+        features.set(Feature::BrandImpersonation, impersonation);
+    }
+
+    fn extract_typosquatting_features(&self, domain: &str, features: &mut FeatureSet) {
+        let sld = domain.split('.').next().unwrap_or(domain);
+        let mut best = 0.0f32;
+        for (alias, _) in self.brands.aliases() {
+            if sld == alias {
+                // Exact match is the brand itself, not a typo of it.
+                best = 0.0;
+                break;
+            }
+            let distance = strsim::levenshtein(sld, alias);
+            if distance <= 2 && alias.len() >= 4 {
+                let score = 1.0 - distance as f32 / 3.0;
+                if score > best {
+                    best = score;
+                }
+            }
+        }
+        features.set(Feature::TyposquattingScore, best);
+    }
+
+    /// `paypal.com.security-check.tk` and friends: the brand name is intact
+    /// (so typosquatting's edit distance never fires) but it sits as a
+    /// token next to a throwaway TLD. Scored by the TLD's risk weight.
+    fn extract_combosquatting_features(&self, domain: &str, features: &mut FeatureSet) {
+        let score =
+            combosquatting_brand(domain, &self.brands).map_or(0.0, |(_, tld_risk)| tld_risk);
+        features.set(Feature::Combosquatting, score);
+    }
+
+    fn extract_dga_features(&self, domain: &str, features: &mut FeatureSet) {
+        // The DGA heuristic judges the registrable label alone: entropy of
+        // the full FQDN would penalize `www.` prefixes and long TLDs.
+        let sld = registrable_label(domain);
+        let entropy = calculate_entropy(sld);
+        let max_consonant_run = longest_consonant_run(sld);
+        let digit_ratio = features.value(Feature::DigitRatio);
+        let dictionary_words = count_dictionary_words(sld) as f32;
+
+        let mut score = 0.0;
+        if entropy > 3.5 {
+            score += 0.4;
+        }
+        if max_consonant_run >= 4 {
+            score += 0.3;
+        }
+        if digit_ratio > 0.3 {
+            score += 0.2;
+        }
+        if dictionary_words == 0.0 && sld.len() >= 10 {
+            score += 0.1;
+        }
+        features.set(Feature::DgaScore, score.min(1.0));
+    }
+
+    fn extract_url_features(
+        &self,
+        url: &str,
+        features: &mut FeatureSet,
+    ) -> Result<(), AppError> {
+        // Decode once up front so percent-encoded phishing keywords
+        // (`%6cogin`) remain visible to the keyword scan.
+        let decoded = percent_encoding::percent_decode_str(url)
+            .decode_utf8_lossy()
+            .into_owned();
+
+        let parsed = match Url::parse(url)
+            .or_else(|_| Url::parse(&decoded))
+            .ok()
+            .or_else(|| normalize_idn_url(url).and_then(|u| Url::parse(&u).ok()))
+        {
+            Some(p) => p,
+            None => {
+                // Unparseable URLs fall back to host-only scoring: the
+                // domain features already stand, and we keep the cheap
+                // lexical URL signals instead of failing the request.
+                features.set(Feature::UrlLength, url.len() as f32);
+                features.set(Feature::UrlEntropy, calculate_entropy(url));
+                features.set(
+                    Feature::UrlKeywordCount,
+                    count_suspicious_keywords(&decoded, self.config.keyword_matching) as f32,
+                );
+                return Ok(());
+            }
+        };
+
+        features.set(Feature::UrlLength, url.len() as f32);
+        features.set(
+            Feature::PathDepth,
+            parsed
+                .path_segments()
+                .map(|s| s.filter(|p| !p.is_empty()).count())
+                .unwrap_or(0) as f32,
+        );
+        features.set(Feature::QueryParamCount, parsed.query_pairs().count() as f32);
+        features.set(Feature::UrlEntropy, calculate_entropy(url));
+        features.set(
+            Feature::HasIpHost,
+            match parsed.host() {
+                Some(url::Host::Ipv4(_)) | Some(url::Host::Ipv6(_)) => 1.0,
+                _ => 0.0,
+            },
+        );
+        features.set(
+            Feature::HasAtSymbol,
+            if url.contains('@') { 1.0 } else { 0.0 },
+        );
+        features.set(
+            Feature::UrlKeywordCount,
+            count_suspicious_keywords(&decoded, self.config.keyword_matching) as f32,
+        );
+        features.set(
+            Feature::UsesHttps,
+            if parsed.scheme() == "https" { 1.0 } else { 0.0 },
+        );
+        let mut extension_risk = parsed
+            .path_segments()
+            .and_then(|s| s.last())
+            .map(file_extension_risk)
+            .unwrap_or(0.0);
+        // Attackers hide the real filename in query parameters
+        // ("download?file=invoice.pdf.exe"), so scan those too.
+        for (key, value) in parsed.query_pairs() {
+            extension_risk = extension_risk
+                .max(file_extension_risk(&key))
+                .max(file_extension_risk(&value));
+        }
+        features.set(Feature::SuspiciousFileExtensions, extension_risk);
+        Ok(())
+    }
+
+    async fn extract_dns_features(&self, domain: &str, features: &mut FeatureSet) {
+        let resolver = match &self.resolver {
+            Some(r) => r,
+            None => return,
+        };
+        let timeout = Duration::from_millis(self.config.dns_timeout_ms);
+        let mut record_count = 0.0f32;
+        let mut resolved_ips: Vec<std::net::IpAddr> = Vec::new();
+        // Set when any lookup failed for infrastructure reasons (SERVFAIL,
+        // REFUSED, timeout); a partial answer set must not read as "few
+        // records", so the count stays unset (neutral) in that case.
+        let mut infrastructure_failure = false;
+
+        match tokio::time::timeout(timeout, resolver.lookup_ip(domain)).await {
+            Ok(Ok(ips)) => {
+                resolved_ips.extend(ips.iter());
+                features.set(Feature::ResolvedIpCount, resolved_ips.len() as f32);
+                // Cheap single-answer classification; the rebinding
+                // cross-check below covers the two-resolver variants.
+                features.set(
+                    Feature::ResolvesToPrivate,
+                    if resolved_ips.iter().any(is_non_public) { 1.0 } else { 0.0 },
+                );
+                record_count += resolved_ips.len() as f32;
+            }
+            Ok(Err(e)) => match note_lookup_failure(domain, "a", Some(&e)) {
+                DnsErrorClass::NxDomain => {
+                    // Authoritative absence is a real signal, distinct from
+                    // a resolver that could not answer.
+                    features.set(Feature::DnsNxdomain, 1.0);
+                    features.set(Feature::ResolvedIpCount, 0.0);
+                }
+                DnsErrorClass::Infrastructure => infrastructure_failure = true,
+            },
+            Err(_) => {
+                note_lookup_failure(domain, "a", None);
+                infrastructure_failure = true;
+            }
+        }
+
+        // Rebinding cross-check: ask the pinned trusted resolvers the same
+        // question and compare answers. A public-facing name resolving into
+        // a private, loopback, or link-local range — or two resolvers that
+        // agree on nothing — are the classic rebinding shapes.
+        let mut trusted_ips: Vec<std::net::IpAddr> = Vec::new();
+        if let Some(trusted) = &self.trusted_resolver {
+            if let Ok(Ok(ips)) = tokio::time::timeout(timeout, trusted.lookup_ip(domain)).await {
+                trusted_ips.extend(ips.iter());
+            }
+        }
+        match rebinding_reason(&resolved_ips, &trusted_ips) {
+            Some(reason) => {
+                tracing::warn!(domain, %reason, "DNS rebinding indicators");
+                features.set(Feature::DnsRebindingFlag, 1.0);
+            }
+            None => {
+                features.set(Feature::DnsRebindingFlag, 0.0);
+            }
+        }
+
+        if let Some(geo) = &self.geo {
+            let tld = domain.rsplit('.').next().unwrap_or("");
+            let (risk, mismatch) = crate::geo::geolocation_features(
+                &resolved_ips,
+                tld,
+                geo.as_ref(),
+                &self.config.geoip_country_risk,
+            );
+            features.set(Feature::GeolocationRisk, risk);
+            features.set(Feature::GeoMismatch, mismatch);
+        }
+
+        match tokio::time::timeout(timeout, resolver.mx_lookup(domain)).await {
+            Ok(Ok(mx)) => {
+                let count = mx.iter().count();
+                features.set(Feature::MxPresent, if count > 0 { 1.0 } else { 0.0 });
+                record_count += count as f32;
+            }
+            Ok(Err(e)) => match note_lookup_failure(domain, "mx", Some(&e)) {
+                // Genuinely no mail setup, as opposed to "could not ask".
+                DnsErrorClass::NxDomain => {
+                    features.set(Feature::MxPresent, 0.0);
+                }
+                DnsErrorClass::Infrastructure => infrastructure_failure = true,
+            },
+            Err(_) => {
+                note_lookup_failure(domain, "mx", None);
+                infrastructure_failure = true;
+            }
+        }
+
+        match tokio::time::timeout(timeout, resolver.txt_lookup(domain)).await {
+            Ok(Ok(txt)) => {
+                let records: Vec<Vec<u8>> = txt
+                    .iter()
+                    .map(|record| {
+                        reassemble_txt_record(record.txt_data().iter().map(|part| &part[..]))
+                    })
+                    .collect();
+                let scan = scan_txt_records(records.iter().map(|record| &record[..]), b"v=spf1");
+                if scan.found {
+                    features.set(Feature::HasSpf, 1.0);
+                }
+                if let Some(index) = scan.first_match {
+                    features.set(Feature::SpfPolicy, spf_policy_score(&records[index]));
+                }
+                if scan.truncated {
+                    tracing::warn!(domain, counted = scan.counted, "oversized TXT answer set truncated");
+                }
+                record_count += scan.counted as f32;
+            }
+            Ok(Err(e)) => {
+                if note_lookup_failure(domain, "txt", Some(&e)) == DnsErrorClass::Infrastructure {
+                    infrastructure_failure = true;
+                }
+            }
+            Err(_) => {
+                note_lookup_failure(domain, "txt", None);
+                infrastructure_failure = true;
+            }
+        }
+
+        let dmarc_name = format!("_dmarc.{domain}");
+        match tokio::time::timeout(timeout, resolver.txt_lookup(dmarc_name)).await {
+            Ok(Ok(txt)) => {
+                let records: Vec<Vec<u8>> = txt
+                    .iter()
+                    .map(|record| {
+                        reassemble_txt_record(record.txt_data().iter().map(|part| &part[..]))
+                    })
+                    .collect();
+                let scan = scan_txt_records(records.iter().map(|record| &record[..]), b"v=DMARC1");
+                if scan.found {
+                    features.set(Feature::HasDmarc, 1.0);
+                }
+                if scan.truncated {
+                    tracing::warn!(domain, counted = scan.counted, "oversized DMARC answer set truncated");
+                }
+            }
+            // Missing `_dmarc` is the common case and `has_dmarc` simply
+            // stays unset; the class is still logged for debugging.
+            Ok(Err(e)) => {
+                note_lookup_failure(domain, "dmarc_txt", Some(&e));
+            }
+            Err(_) => {
+                note_lookup_failure(domain, "dmarc_txt", None);
+            }
+        }
+
+        if !infrastructure_failure {
+            features.set(Feature::DnsRecordCount, record_count.min(MAX_DNS_RECORD_COUNT));
+        }
+    }
+}
+
+/// Rewrite the authority of a URL to its IDNA (punycode) form so hosts with
+/// raw Unicode labels survive parsing.
+fn normalize_idn_url(url: &str) -> Option<String> {
+    let (scheme, rest) = url.split_once("://")?;
+    let (authority, tail) = match rest.find(['/', '?', '#']) {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, ""),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((h, p)) if p.chars().all(|c| c.is_ascii_digit()) => (h, Some(p)),
+        _ => (authority, None),
+    };
+    let ascii_host = idna::domain_to_ascii(host).ok()?;
+    Some(match port {
+        Some(port) => format!("{scheme}://{ascii_host}:{port}{tail}"),
+        None => format!("{scheme}://{ascii_host}{tail}"),
+    })
+}
+
+/// Build a resolver pinned to the given nameserver IPs. Returns `None`
+/// when the list is empty or holds nothing parseable, which disables the
+/// rebinding cross-check rather than failing startup.
+fn trusted_resolver(nameservers: &[String]) -> Option<TokioAsyncResolver> {
+    let ips: Vec<std::net::IpAddr> = nameservers
+        .iter()
+        .filter_map(|ns| ns.parse().ok())
+        .collect();
+    if ips.is_empty() {
+        return None;
+    }
+    let group = NameServerConfigGroup::from_ips_clear(&ips, 53, true);
+    Some(TokioAsyncResolver::tokio(
+        ResolverConfig::from_parts(None, vec![], group),
+        ResolverOpts::default(),
+    ))
+}
+
+/// Approximate heap footprint of one cache entry: the domain key, map
+/// bookkeeping, and the fixed-size feature array. Deliberately rough — it
+/// only needs to scale with real usage, not match the allocator byte for
+/// byte.
+fn estimated_entry_bytes(domain: &str) -> usize {
+    const ENTRY_OVERHEAD: usize = 64;
+    domain.len() + ENTRY_OVERHEAD + std::mem::size_of::<FeatureSet>()
+}
+
+/// Resolver failure classes that matter for scoring: a name that provably
+/// does not exist is a signal about the domain, while an infrastructure
+/// failure says nothing and must leave the DNS features neutral.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DnsErrorClass {
+    /// NXDOMAIN, or NOERROR with an empty answer: authoritative absence.
+    NxDomain,
+    /// SERVFAIL, REFUSED, timeout, transport errors.
+    Infrastructure,
+}
+
+fn classify_resolve_error(error: &ResolveError) -> DnsErrorClass {
+    match error.kind() {
+        ResolveErrorKind::NoRecordsFound { response_code, .. } => match response_code {
+            ResponseCode::NXDomain | ResponseCode::NoError => DnsErrorClass::NxDomain,
+            _ => DnsErrorClass::Infrastructure,
+        },
+        _ => DnsErrorClass::Infrastructure,
+    }
+}
+
+/// Classify one failed lookup (`None` error means the timeout fired) and
+/// log the class so operators can tell absence from resolver trouble.
+fn note_lookup_failure(
+    domain: &str,
+    lookup: &'static str,
+    error: Option<&ResolveError>,
+) -> DnsErrorClass {
+    let class = match error {
+        Some(error) => classify_resolve_error(error),
+        None => DnsErrorClass::Infrastructure,
+    };
+    tracing::debug!(domain, lookup, class = ?class, "DNS lookup failed");
+    class
+}
+
+/// Caps on TXT answer processing. A hostile authoritative server can
+/// return thousands of records of arbitrary size; the prefixes we look
+/// for (`v=spf1`, `v=DMARC1`) sit at the start of well-formed records,
+/// so nothing real is lost by bounding the scan.
+const MAX_TXT_RECORDS: usize = 64;
+const MAX_TXT_RECORD_BYTES: usize = 512;
+
+/// Upper bound for `dns_record_count`, so one pathological answer set
+/// cannot push the feature far outside the range the model trained on.
+const MAX_DNS_RECORD_COUNT: f32 = 256.0;
+
+/// Reassemble one TXT record's character-strings into its logical value.
+/// TXT values longer than 255 bytes arrive split into multiple strings
+/// that RFC 7208 §3.3 says to concatenate without separators, so matching
+/// only the first string misses any record whose prefix straddles a
+/// split. Capped one byte past `MAX_TXT_RECORD_BYTES` so the scan still
+/// sees the record as oversized.
+fn reassemble_txt_record<'a>(strings: impl Iterator<Item = &'a [u8]>) -> Vec<u8> {
+    let mut record = Vec::new();
+    for part in strings {
+        let room = (MAX_TXT_RECORD_BYTES + 1).saturating_sub(record.len());
+        if room == 0 {
+            break;
+        }
+        record.extend_from_slice(&part[..part.len().min(room)]);
+    }
+    record
+}
+
+/// Case-insensitive prefix match on a (bounded view of a) TXT record;
+/// `v=spf1` and `V=SPF1` are the same declaration on the wire.
+fn txt_prefix_matches(data: &[u8], prefix: &[u8]) -> bool {
+    let head = &data[..data.len().min(MAX_TXT_RECORD_BYTES)];
+    head.len() >= prefix.len() && head[..prefix.len()].eq_ignore_ascii_case(prefix)
+}
+
+/// What scanning a TXT answer set for a prefix found, under the caps.
+struct TxtScan {
+    found: bool,
+    /// Index of the first matching record, for callers that go on to
+    /// parse it.
+    first_match: Option<usize>,
+    /// Records counted toward `dns_record_count`; at most
+    /// `MAX_TXT_RECORDS`.
+    counted: usize,
+    /// True when a cap cut the scan short: more records than
+    /// `MAX_TXT_RECORDS`, or a record longer than `MAX_TXT_RECORD_BYTES`.
+    truncated: bool,
+}
+
+fn scan_txt_records<'a>(records: impl Iterator<Item = &'a [u8]>, prefix: &[u8]) -> TxtScan {
+    let mut scan = TxtScan {
+        found: false,
+        first_match: None,
+        counted: 0,
+        truncated: false,
+    };
+    for (index, data) in records.enumerate() {
+        if scan.counted == MAX_TXT_RECORDS {
+            scan.truncated = true;
+            break;
+        }
+        scan.counted += 1;
+        if data.len() > MAX_TXT_RECORD_BYTES {
+            scan.truncated = true;
+        }
+        if txt_prefix_matches(data, prefix) {
+            scan.found = true;
+            scan.first_match.get_or_insert(index);
+        }
+    }
+    scan
+}
+
+/// The `all` qualifier of a matched SPF record as a strictness score:
+/// hardfail `-all` 1.0, softfail `~all` 0.75, neutral `?all` 0.5, and
+/// 0.25 for pass-all `+all` or a record with no `all` term at all
+/// (redirect-only setups look like that). A later `all` term wins, like
+/// it does in evaluation order.
+fn spf_policy_score(record: &[u8]) -> f32 {
+    let text = String::from_utf8_lossy(&record[..record.len().min(MAX_TXT_RECORD_BYTES)])
+        .to_ascii_lowercase();
+    let mut policy = 0.25;
+    for term in text.split_ascii_whitespace() {
+        match term {
+            "-all" => policy = 1.0,
+            "~all" => policy = 0.75,
+            "?all" => policy = 0.5,
+            "+all" | "all" => policy = 0.25,
+            _ => {}
+        }
+    }
+    policy
+}
+
+/// Address ranges a public-facing domain has no business resolving into.
+const NON_PUBLIC_RANGES: &[&str] = &[
+    "0.0.0.0/8",
+    "10.0.0.0/8",
+    "100.64.0.0/10",
+    "127.0.0.0/8",
+    "169.254.0.0/16",
+    "172.16.0.0/12",
+    "192.168.0.0/16",
+    "::1/128",
+    "fc00::/7",
+    "fe80::/10",
+];
+
+fn is_non_public(ip: &std::net::IpAddr) -> bool {
+    NON_PUBLIC_RANGES.iter().any(|range| {
+        range
+            .parse::<IpNetwork>()
+            .map(|network| network.contains(*ip))
+            .unwrap_or(false)
+    })
+}
+
+/// Why a pair of resolver answer sets looks like DNS rebinding, if it
+/// does. Any non-public address is the strongest signal; two non-empty
+/// answers with no address in common is the time-split variant, where the
+/// attacker flips the record between queries. An empty side (the trusted
+/// resolver timed out) never flags on its own.
+fn rebinding_reason(
+    primary: &[std::net::IpAddr],
+    trusted: &[std::net::IpAddr],
+) -> Option<String> {
+    if let Some(ip) = primary.iter().chain(trusted).find(|ip| is_non_public(ip)) {
+        return Some(format!("resolves to non-public address {ip}"));
+    }
+    if !primary.is_empty()
+        && !trusted.is_empty()
+        && primary.iter().all(|ip| !trusted.contains(ip))
+    {
+        return Some("system and trusted resolvers returned disjoint answers".to_string());
+    }
+    None
+}
+
+/// The registrable (SLD) label of a domain per the PSL, falling back to
+/// the first label when the name does not parse.
+fn registrable_label(domain: &str) -> &str {
+    let root = parse_domain_name(domain)
+        .ok()
+        .and_then(|parsed| parsed.root())
+        .unwrap_or(domain);
+    root.split('.').next().unwrap_or(root)
+}
+
+/// Whether the name is an intranet host rather than a public domain: a
+/// bare single label (`intranet`), a name under one of the configured
+/// internal suffixes (`buildbot.corp`), or a name the PSL cannot parse at
+/// all. Internal hosts never reach the public-web model; the pipeline
+/// resolves them with `thresholds.internal_host_action`.
+pub(crate) fn is_internal_host(domain: &str, internal_suffixes: &[String]) -> bool {
+    if !domain.contains('.') {
+        return true;
+    }
+    let tld = domain.rsplit('.').next().unwrap_or("");
+    if internal_suffixes.iter().any(|s| s.eq_ignore_ascii_case(tld)) {
+        return true;
+    }
+    parse_domain_name(domain).is_err()
+}
+
+/// Whether the registrable domain is exactly one of the popular domains.
+/// `www.google.com` counts; `google.com.evil.tk` does not — its
+/// registrable domain is `evil.tk`.
+fn is_popular_domain(domain: &str) -> bool {
+    let root = parse_domain_name(domain)
+        .ok()
+        .and_then(|parsed| parsed.root())
+        .unwrap_or(domain);
+    POPULAR_DOMAINS.contains(&root)
+}
+
+/// The brand a domain combosquats, with the offending TLD's risk weight:
+/// a brand alias appearing as a token (split on `.` and `-`, homoglyph-
+/// normalized) somewhere other than the registrable label itself, under a
+/// risky TLD. The brand's own domain never matches; vetted brand
+/// properties elsewhere are handled by the intel allowlist in the
+/// pipeline. The returned name is always the canonical brand, whichever
+/// alias matched.
+pub(crate) fn combosquatting_brand<'a>(
+    domain: &str,
+    brands: &'a BrandTable,
+) -> Option<(&'a str, f32)> {
+    let tld = domain.rsplit('.').next().unwrap_or("");
+    let tld_risk = RISKY_TLDS.iter().find(|(t, _)| *t == tld).map(|(_, w)| *w)?;
+    let apex = normalize_homoglyphs(registrable_label(domain));
+    let tokens: Vec<String> = domain.split(['.', '-']).map(normalize_homoglyphs).collect();
+    brands.aliases().find_map(|(alias, brand)| {
+        if apex == alias || apex == brand {
+            return None;
+        }
+        tokens
+            .iter()
+            .any(|token| token == alias)
+            .then_some((brand, tld_risk))
+    })
+}
+
+/// 1.0 when the referrer's registrable domain differs from the scored
+/// domain's — typical of phishing reached from mail or chat rather than
+/// from the impersonated site itself. An unparseable referrer is neutral:
+/// absence of evidence either way.
+fn referrer_mismatch(referrer: &str, domain: &str) -> f32 {
+    let Some(host) = Url::parse(referrer)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+    else {
+        return 0.0;
+    };
+    let root = |name: &str| {
+        parse_domain_name(name)
+            .ok()
+            .and_then(|parsed| parsed.root().map(str::to_string))
+            .unwrap_or_else(|| name.to_string())
+    };
+    if root(&host) == root(domain) {
+        0.0
+    } else {
+        1.0
+    }
+}
+
+/// Substrings marking the common automation and scripting user agents.
+const AUTOMATION_AGENTS: &[&str] = &[
+    "curl",
+    "wget",
+    "python-requests",
+    "go-http-client",
+    "libwww",
+    "headless",
+    "phantomjs",
+];
+
+fn ua_automation(agent: &str) -> f32 {
+    let lowered = agent.to_lowercase();
+    AUTOMATION_AGENTS
+        .iter()
+        .any(|marker| lowered.contains(marker)) as u8 as f32
+}
+
+/// Shannon entropy over the characters of the input string.
+pub fn calculate_entropy(s: &str) -> f32 {
+    if s.is_empty() {
+        return 0.0;
+    }
+    let mut counts: HashMap<char, usize> = HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_insert(0) += 1;
+    }
+    let len = s.chars().count() as f32;
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f32 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+pub fn count_suspicious_keywords(text: &str, matching: KeywordMatching) -> usize {
+    let lower = text.to_lowercase();
+    SUSPICIOUS_KEYWORDS
+        .iter()
+        .filter(|keyword| keyword_occurs(&lower, keyword, matching))
+        .count()
+}
+
+/// Does `keyword` occur in the (lowercased) haystack under the matching
+/// mode? Whole-word requires the neighboring characters to be non-word
+/// characters, so `login-44321.top` still counts `login` while
+/// `clickhouse.example.com` no longer counts `click`.
+fn keyword_occurs(haystack: &str, keyword: &str, matching: KeywordMatching) -> bool {
+    match matching {
+        KeywordMatching::Substring => haystack.contains(keyword),
+        KeywordMatching::WholeWord => {
+            let bytes = haystack.as_bytes();
+            let is_word = |b: u8| b.is_ascii_alphanumeric() || b == b'_';
+            haystack.match_indices(keyword).any(|(start, matched)| {
+                let end = start + matched.len();
+                (start == 0 || !is_word(bytes[start - 1]))
+                    && (end == bytes.len() || !is_word(bytes[end]))
+            })
+        }
+    }
+}
+
+/// Aho-Corasick automaton over `DICTIONARY_WORDS`, built once: a single
+/// pass over the label finds every occurrence of every word, instead of
+/// one substring scan per dictionary entry.
+fn dictionary_automaton() -> &'static AhoCorasick {
+    static AUTOMATON: OnceLock<AhoCorasick> = OnceLock::new();
+    AUTOMATON
+        .get_or_init(|| AhoCorasick::new(DICTIONARY_WORDS).expect("static dictionary patterns"))
+}
+
+/// Segment the registrable label into dictionary words by greedy
+/// longest-match word-break, left to right: at each position take the
+/// longest dictionary word starting there, or skip one character. So
+/// `facebook` segments as `face`+`book`, `freemoney` as `free`+`money`,
+/// and a random string like `xqzpl` yields nothing — unlike a naive
+/// substring scan, a word only counts where the label actually breaks
+/// into it.
+pub fn segment_dictionary_words(domain: &str) -> Vec<&'static str> {
+    let sld = registrable_label(domain);
+    // Longest word starting at each byte offset, from one automaton pass.
+    let mut best_at: Vec<Option<(usize, usize)>> = vec![None; sld.len()];
+    for hit in dictionary_automaton().find_overlapping_iter(sld) {
+        let slot = &mut best_at[hit.start()];
+        if slot.map_or(true, |(end, _)| hit.end() > end) {
+            *slot = Some((hit.end(), hit.pattern().as_usize()));
+        }
+    }
+    let mut words = Vec::new();
+    let mut i = 0;
+    while i < sld.len() {
+        match best_at[i] {
+            Some((end, pattern)) => {
+                words.push(DICTIONARY_WORDS[pattern]);
+                i = end;
+            }
+            None => i += 1,
+        }
+    }
+    words
+}
+
+pub fn count_dictionary_words(domain: &str) -> usize {
+    segment_dictionary_words(domain).len()
+}
+
+/// Length of the longest run of consecutive digits.
+fn max_digit_run(s: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in s.chars() {
+        if c.is_ascii_digit() {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Length of the longest run of the same character (`aaabbb` → 3).
+fn max_char_repeat(s: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    let mut previous = None;
+    for c in s.chars() {
+        current = if previous == Some(c) { current + 1 } else { 1 };
+        longest = longest.max(current);
+        previous = Some(c);
+    }
+    longest
+}
+
+/// Fraction of the string's bigrams that occur more than once, so a
+/// stuttered generator output (`abcabcabc`) scores high while natural
+/// words, whose bigrams are mostly distinct, stay near zero. 0.0 for
+/// strings with fewer than two characters.
+fn repeated_bigram_ratio(s: &str) -> f32 {
+    let chars: Vec<char> = s.chars().collect();
+    if chars.len() < 2 {
+        return 0.0;
+    }
+    let mut counts: HashMap<(char, char), u32> = HashMap::new();
+    for window in chars.windows(2) {
+        *counts.entry((window[0], window[1])).or_insert(0) += 1;
+    }
+    let repeated: u32 = counts.values().filter(|&&count| count > 1).sum();
+    repeated as f32 / (chars.len() - 1) as f32
+}
+
+/// Number of letter→digit / digit→letter transitions.
+fn letter_digit_alternations(s: &str) -> usize {
+    s.chars()
+        .zip(s.chars().skip(1))
+        .filter(|(a, b)| {
+            (a.is_ascii_alphabetic() && b.is_ascii_digit())
+                || (a.is_ascii_digit() && b.is_ascii_alphabetic())
+        })
+        .count()
+}
+
+fn longest_consonant_run(s: &str) -> usize {
+    let mut longest = 0;
+    let mut current = 0;
+    for c in s.chars() {
+        if c.is_ascii_alphabetic() && !matches!(c, 'a' | 'e' | 'i' | 'o' | 'u') {
+            current += 1;
+            longest = longest.max(current);
+        } else {
+            current = 0;
+        }
+    }
+    longest
+}
+
+/// Default per-feature thresholds above which a reason string is emitted.
+/// Operators can override any entry via `features.reason_thresholds`.
+const DEFAULT_REASON_THRESHOLDS: &[(&str, f32, &str)] = &[
+    (
+        "homoglyph_score",
+        0.5,
+        "Homoglyph characters resembling a known brand",
+    ),
+    (
+        "typosquatting_score",
+        0.6,
+        "Similar to a popular domain (possible typosquatting)",
+    ),
+    ("dga_score", 0.7, "Random-looking domain (possible DGA)"),
+    ("entropy", 4.5, "High character entropy"),
+    (
+        "suspicious_keyword_count",
+        1.0,
+        "Multiple phishing keywords present",
+    ),
+    ("tld_risk", 0.7, "High-risk TLD"),
+    ("has_ip_host", 0.0, "URL uses a raw IP address"),
+    (
+        "dns_rebinding_flag",
+        0.0,
+        "dns_rebinding: answers include private addresses or disagree across resolvers",
+    ),
+    (
+        "resolves_to_private",
+        0.0,
+        "resolves_to_private: domain answers with private or bogon addresses",
+    ),
+    ("brand_impersonation", 0.5, "Possible brand impersonation"),
+    (
+        "domain_velocity",
+        1.0,
+        "campaign_suspected: burst of similar newly-seen domains",
+    ),
+    (
+        "suspicious_file_extensions",
+        0.6,
+        "suspicious_download: executable or disguised file extension",
+    ),
+];
+
+/// Human-readable explanations for the strongest signals in a feature set.
+/// `overrides` replaces the default threshold for the named features.
+pub fn generate_reasons(
+    features: &FeatureSet,
+    overrides: &HashMap<String, f32>,
+) -> Vec<String> {
+    let mut reasons = Vec::new();
+    for (name, default_threshold, message) in DEFAULT_REASON_THRESHOLDS {
+        let threshold = overrides.get(*name).copied().unwrap_or(*default_threshold);
+        if features.get_named(name).unwrap_or(0.0) > threshold {
+            reasons.push((*message).to_string());
+        }
+    }
+    reasons
+}
+
+/// Risk that a filename (or URL path segment) is a malware download, from
+/// its extension. A dangerous extension hidden behind a decoy document
+/// extension ("invoice.pdf.exe") maxes the score regardless of which
+/// executable type it is.
+pub fn file_extension_risk(filename: &str) -> f32 {
+    let name = filename
+        .rsplit('/')
+        .next()
+        .unwrap_or(filename)
+        .to_ascii_lowercase();
+    let tokens: Vec<&str> = name.split('.').collect();
+    if tokens.len() < 2 {
+        return 0.0;
+    }
+    let last = tokens[tokens.len() - 1];
+    let Some(&(_, risk)) = DANGEROUS_EXTENSIONS.iter().find(|(ext, _)| *ext == last) else {
+        return 0.0;
+    };
+    if tokens.len() >= 3 && DECOY_EXTENSIONS.contains(&tokens[tokens.len() - 2]) {
+        return 1.0;
+    }
+    risk
+}
+
+/// Replace NaN/Inf feature values with 0.0 so degenerate inputs cannot feed
+/// garbage into the linear model. Each replacement is logged once per call.
+pub fn sanitize_features(features: &mut FeatureSet) {
+    for index in 0..FEATURE_COUNT {
+        if features.present[index] && !features.values[index].is_finite() {
+            tracing::warn!(
+                feature = %FEATURE_NAMES[index],
+                value = %features.values[index],
+                "feature_sanitized"
+            );
+            features.values[index] = 0.0;
+        }
+    }
+}
+
+/// Project the feature set onto the fixed `FEATURE_NAMES` ordering, with
+/// absent features padded as 0.0.
+pub fn features_to_vector(features: &FeatureSet) -> Vec<f64> {
+    features.values.iter().map(|v| *v as f64).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entropy_of_uniform_string_is_high() {
+        assert!(calculate_entropy("abcdefghij") > 3.0);
+        assert!(calculate_entropy("aaaaaaaaaa") < 0.1);
+    }
+
+    #[test]
+    fn full_fqdn_entropy_exceeds_the_label_entropy() {
+        // Dots, the `www.` prefix, and the TLD all add characters the DGA
+        // heuristic should not be judging.
+        assert!(calculate_entropy("www.google.com") > calculate_entropy("google"));
+        assert_eq!(registrable_label("www.google.com"), "google");
+        assert_eq!(registrable_label("google"), "google");
+    }
+
+    #[tokio::test]
+    async fn repetition_features_separate_runs_from_natural_words() {
+        assert_eq!(max_char_repeat("aaabbb"), 3);
+        assert_eq!(max_char_repeat(""), 0);
+        assert!(repeated_bigram_ratio("abcabcabc") > 0.9);
+        assert_eq!(repeated_bigram_ratio("a"), 0.0);
+
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let repetitive = extractor.extract("aaaa1111.com", None).await.unwrap();
+        assert_eq!(repetitive["max_char_repeat"], 4.0);
+        assert!(repetitive["repeated_bigram_ratio"] > 0.8);
+
+        let normal = extractor.extract("example.com", None).await.unwrap();
+        assert_eq!(normal["max_char_repeat"], 1.0);
+        assert_eq!(normal["repeated_bigram_ratio"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn intranet_hostnames_score_instead_of_erroring() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        // `corp` is a default internal suffix and a bare label has no
+        // suffix at all; both used to fail the PSL parse with a 500.
+        let features = extractor.extract("buildbot.corp", None).await.unwrap();
+        assert_eq!(features["is_internal_host"], 1.0);
+        assert_eq!(features["sld_length"], "buildbot".len() as f32);
+        let features = extractor.extract("intranet", None).await.unwrap();
+        assert_eq!(features["is_internal_host"], 1.0);
+        // Public names stay on the ordinary path.
+        let features = extractor.extract("example.com", None).await.unwrap();
+        assert_eq!(features["is_internal_host"], 0.0);
+    }
+
+    #[test]
+    fn dictionary_segmentation_breaks_labels_into_words() {
+        assert_eq!(segment_dictionary_words("freemoney.example"), vec!["free", "money"]);
+        assert_eq!(segment_dictionary_words("facebook.com"), vec!["face", "book"]);
+        assert_eq!(count_dictionary_words("freemoney.example"), 2);
+        // Random strings segment into nothing; the old substring scan
+        // could be fooled by an embedded fragment.
+        assert_eq!(count_dictionary_words("xqzpl.example"), 0);
+        // Longest match wins: `online` is one word, not `on`+line noise.
+        assert_eq!(segment_dictionary_words("onlinebank.example"), vec!["online", "bank"]);
+    }
+
+    #[tokio::test]
+    async fn sld_entropy_covers_only_the_registrable_label() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor.extract("www.google.com", None).await.unwrap();
+        assert_eq!(features["sld_entropy"], calculate_entropy("google"));
+        assert!(features["entropy"] > features["sld_entropy"]);
+    }
+
+    /// Stub resolver error carrying the given response code.
+    fn resolve_error(code: ResponseCode) -> ResolveError {
+        ResolveErrorKind::NoRecordsFound {
+            query: Box::new(trust_dns_resolver::proto::op::Query::new()),
+            soa: None,
+            negative_ttl: None,
+            response_code: code,
+            trusted: false,
+        }
+        .into()
+    }
+
+    #[test]
+    fn resolver_error_classes_split_absence_from_infrastructure() {
+        let class = |code| classify_resolve_error(&resolve_error(code));
+        assert_eq!(class(ResponseCode::NXDomain), DnsErrorClass::NxDomain);
+        // NOERROR with no answer records is also authoritative absence.
+        assert_eq!(class(ResponseCode::NoError), DnsErrorClass::NxDomain);
+        assert_eq!(class(ResponseCode::ServFail), DnsErrorClass::Infrastructure);
+        assert_eq!(class(ResponseCode::Refused), DnsErrorClass::Infrastructure);
+        assert_eq!(
+            classify_resolve_error(&ResolveErrorKind::Timeout.into()),
+            DnsErrorClass::Infrastructure
+        );
+        // A timed-out future (no resolver error at all) is infrastructure.
+        assert_eq!(
+            note_lookup_failure("example.com", "a", None),
+            DnsErrorClass::Infrastructure
+        );
+    }
+
+    #[test]
+    fn loopback_answer_from_a_stubbed_resolver_flags_rebinding() {
+        let public: Vec<std::net::IpAddr> = vec!["93.184.216.34".parse().unwrap()];
+        let loopback: Vec<std::net::IpAddr> = vec!["127.0.0.1".parse().unwrap()];
+        let reason = rebinding_reason(&public, &loopback).unwrap();
+        assert!(reason.contains("127.0.0.1"));
+        // RFC 1918 and link-local ranges flag regardless of which resolver
+        // returned them.
+        assert!(rebinding_reason(&["192.168.1.10".parse().unwrap()], &[]).is_some());
+        assert!(rebinding_reason(&[], &["169.254.0.7".parse().unwrap()]).is_some());
+        assert!(rebinding_reason(&public, &public).is_none());
+    }
+
+    #[test]
+    fn disjoint_resolver_answers_are_flagged_as_suspicious() {
+        let primary: Vec<std::net::IpAddr> = vec!["93.184.216.34".parse().unwrap()];
+        let other: Vec<std::net::IpAddr> = vec!["198.51.100.7".parse().unwrap()];
+        assert!(rebinding_reason(&primary, &other).is_some());
+        // A partially overlapping answer (round-robin rotation) does not.
+        let rotated = vec![primary[0], other[0]];
+        assert!(rebinding_reason(&primary, &rotated).is_none());
+        // Nor does an empty trusted answer (resolver timed out).
+        assert!(rebinding_reason(&primary, &[]).is_none());
+    }
+
+    #[test]
+    fn private_answers_are_classified_as_resolves_to_private() {
+        // The shapes a stubbed resolver would hand back: RFC1918, a bogon
+        // range, and an ordinary public address.
+        let private: std::net::IpAddr = "10.0.0.1".parse().unwrap();
+        let bogon: std::net::IpAddr = "100.64.1.2".parse().unwrap();
+        let public: std::net::IpAddr = "93.184.216.34".parse().unwrap();
+        assert!(is_non_public(&private));
+        assert!(is_non_public(&bogon));
+        assert!(!is_non_public(&public));
+        // One private address among public ones is enough to flag the
+        // answer set, exactly as `extract_dns_features` computes it.
+        assert!([public, private].iter().any(is_non_public));
+        assert!(![public].iter().any(is_non_public));
+    }
+
+    #[test]
+    fn oversized_txt_answer_sets_are_capped() {
+        // A hostile authoritative server answering with thousands of
+        // records only ever contributes a bounded count.
+        let records: Vec<Vec<u8>> = (0..5_000).map(|i| format!("blob-{i}").into_bytes()).collect();
+        let scan = scan_txt_records(records.iter().map(|r| &r[..]), b"v=spf1");
+        assert!(!scan.found);
+        assert_eq!(scan.counted, MAX_TXT_RECORDS);
+        assert!(scan.truncated);
+        assert!((scan.counted as f32) <= MAX_DNS_RECORD_COUNT);
+    }
+
+    #[test]
+    fn txt_prefix_is_found_within_the_per_record_byte_cap() {
+        // The prefix sits at the start of a well-formed record, so a
+        // megabyte of trailing junk neither hides it nor gets scanned.
+        let mut huge = b"v=spf1 include:example.com ".to_vec();
+        huge.extend(std::iter::repeat(b'a').take(1 << 20));
+        let records = [huge, b"v=DMARC1; p=reject".to_vec()];
+
+        let scan = scan_txt_records(records.iter().map(|r| &r[..]), b"v=spf1");
+        assert!(scan.found);
+        assert_eq!(scan.counted, 2);
+        // The oversized record itself trips the truncation note.
+        assert!(scan.truncated);
+
+        let scan = scan_txt_records(records.iter().map(|r| &r[..]), b"v=DMARC1");
+        assert!(scan.found);
+    }
+
+    #[test]
+    fn split_txt_records_reassemble_before_matching() {
+        // Long TXT values arrive as multiple 255-byte character-strings
+        // concatenated without separators; the prefix can straddle a split.
+        let record =
+            reassemble_txt_record([&b"v=sp"[..], b"f1 include:one.example ", b"-all"].into_iter());
+        assert_eq!(&record[..], b"v=spf1 include:one.example -all");
+        let records = [record, b"unrelated".to_vec()];
+        let scan = scan_txt_records(records.iter().map(|r| &r[..]), b"v=spf1");
+        assert!(scan.found);
+        assert_eq!(scan.first_match, Some(0));
+
+        // The declaration is matched case-insensitively.
+        let shouty = [b"V=SPF1 -ALL".to_vec()];
+        assert!(scan_txt_records(shouty.iter().map(|r| &r[..]), b"v=spf1").found);
+        let dmarc = [b"v=dmarc1; p=reject".to_vec()];
+        assert!(scan_txt_records(dmarc.iter().map(|r| &r[..]), b"v=DMARC1").found);
+
+        // Reassembly stops just past the per-record byte cap, so the scan
+        // still notices the record was oversized without buffering it all.
+        let huge = reassemble_txt_record(std::iter::repeat(&[b'a'; 255][..]).take(1_000));
+        assert_eq!(huge.len(), MAX_TXT_RECORD_BYTES + 1);
+        let records = [huge];
+        assert!(scan_txt_records(records.iter().map(|r| &r[..]), b"v=spf1").truncated);
+    }
+
+    #[test]
+    fn spf_policy_grades_the_all_qualifier() {
+        assert_eq!(spf_policy_score(b"v=spf1 include:x.example -all"), 1.0);
+        assert_eq!(spf_policy_score(b"v=spf1 ip4:192.0.2.0/24 ~all"), 0.75);
+        assert_eq!(spf_policy_score(b"v=spf1 ?all"), 0.5);
+        assert_eq!(spf_policy_score(b"v=spf1 +all"), 0.25);
+        // No `all` term at all (redirect-only) reads as permissive.
+        assert_eq!(spf_policy_score(b"v=spf1 redirect=_spf.example.com"), 0.25);
+        // Qualifiers are case-insensitive like the rest of the record.
+        assert_eq!(spf_policy_score(b"V=SPF1 -ALL"), 1.0);
+    }
+
+    #[tokio::test]
+    async fn byte_budget_evicts_before_the_entry_count_does() {
+        // Learn the footprint of one entry first; the estimate is not a
+        // contract, so the budget is derived rather than hardcoded.
+        let probe = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        probe.extract("aaaaaaaaaa.example", None).await.unwrap();
+        let entry_bytes = probe.cache_bytes();
+        assert!(entry_bytes > 0);
+
+        // Room for one entry and a half: the second insert must evict the
+        // first even though the count bound is nowhere near.
+        let budget = entry_bytes * 3 / 2;
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            cache_max_bytes: budget,
+            ..FeatureConfig::default()
+        });
+        extractor.extract("aaaaaaaaaa.example", None).await.unwrap();
+        assert_eq!(extractor.cache_evictions(), 0);
+        extractor.extract("bbbbbbbbbb.example", None).await.unwrap();
+        assert_eq!(extractor.cache_evictions(), 1);
+        assert!(extractor.cache_bytes() <= budget);
+    }
+
+    #[test]
+    fn lowered_reason_threshold_surfaces_reason() {
+        let mut features = FeatureSet::default();
+        features.set(Feature::DgaScore, 0.5);
+
+        let reasons = generate_reasons(&features, &HashMap::new());
+        assert!(reasons.is_empty());
+
+        let overrides = HashMap::from([("dga_score".to_string(), 0.4)]);
+        let reasons = generate_reasons(&features, &overrides);
+        assert_eq!(reasons, vec!["Random-looking domain (possible DGA)"]);
+    }
+
+    #[test]
+    fn vector_matches_schema_length() {
+        let features = FeatureSet::default();
+        assert_eq!(features_to_vector(&features).len(), FEATURE_NAMES.len());
+    }
+
+    #[test]
+    fn absent_features_stay_distinct_from_zero() {
+        let mut features = FeatureSet::default();
+        assert!(features.is_empty());
+        assert_eq!(features.get(Feature::DnsRecordCount), None);
+
+        features.set(Feature::DnsRecordCount, 0.0);
+        assert_eq!(features.get(Feature::DnsRecordCount), Some(0.0));
+        assert_eq!(features.len(), 1);
+
+        // The named-map view only carries computed features, and survives
+        // the round trip back; unknown names are dropped on the way in.
+        let mut map = features.to_named_map();
+        assert_eq!(map.len(), 1);
+        map.insert("not_a_feature".to_string(), 1.0);
+        assert_eq!(FeatureSet::from_named_map(&map), features);
+    }
+
+    #[tokio::test]
+    async fn alternating_domain_scores_high_alternation() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor.extract("a1b2c3d4.com", None).await.unwrap();
+        assert!(features["letter_digit_alternations"] >= 7.0);
+
+        let benign = extractor.extract("example.com", None).await.unwrap();
+        assert_eq!(benign["letter_digit_alternations"], 0.0);
+        assert_eq!(benign["max_digit_run"], 0.0);
+        assert_eq!(benign["token_count"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn idn_url_does_not_abort_extraction() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor
+            .extract("example.com", Some("https://пример.example.com/страница"))
+            .await
+            .unwrap();
+        assert!(features["url_length"] > 0.0);
+        assert_eq!(features["uses_https"], 1.0);
+    }
+
+    #[tokio::test]
+    async fn equivalent_spellings_share_one_cache_entry() {
+        assert_eq!(normalize_domain("  ExAmPle.COM.  "), "example.com");
+
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        for raw in ["Example.COM.", "  example.com", "EXAMPLE.com"] {
+            extractor.extract(&normalize_domain(raw), None).await.unwrap();
+        }
+        // The first spelling populated the one shared entry; the other two
+        // were cache hits against it.
+        assert_eq!(extractor.cache_hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn url_present_marks_host_only_scoring() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        // Distinct domains: the cache is keyed by domain, so the same name
+        // would serve the first extraction for both.
+        let with_url = extractor
+            .extract("one.example", Some("https://one.example/login"))
+            .await
+            .unwrap();
+        assert_eq!(with_url["url_present"], 1.0);
+
+        let host_only = extractor.extract("two.example", None).await.unwrap();
+        assert_eq!(host_only["url_present"], 0.0);
+        // URL features stay absent, not zero-computed.
+        assert!(host_only.get(Feature::UrlLength).is_none());
+
+        // An empty or blank URL is host-only scoring too.
+        let blank = extractor.extract("three.example", Some("  ")).await.unwrap();
+        assert_eq!(blank["url_present"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn percent_encoded_keywords_are_counted() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor
+            .extract("example.com", Some("http://example.com/%6cogin/verify"))
+            .await
+            .unwrap();
+        assert!(features["url_keyword_count"] >= 2.0);
+    }
+
+    #[test]
+    fn whole_word_matching_ignores_embedded_keywords() {
+        assert_eq!(
+            count_suspicious_keywords("clickhouse.example.com", KeywordMatching::WholeWord),
+            0
+        );
+        assert_eq!(
+            count_suspicious_keywords("clickhouse.example.com", KeywordMatching::Substring),
+            1
+        );
+        // Separators and case still behave as boundaries-and-fold.
+        assert_eq!(
+            count_suspicious_keywords("secure-Login.example.com", KeywordMatching::WholeWord),
+            2
+        );
+        assert_eq!(
+            count_suspicious_keywords("login44321.example.com", KeywordMatching::Substring),
+            1
+        );
+    }
+
+    #[test]
+    fn double_extension_maxes_file_risk() {
+        assert_eq!(file_extension_risk("file.pdf.exe"), 1.0);
+        assert_eq!(file_extension_risk("report.pdf"), 0.0);
+        assert!(file_extension_risk("setup.msi") > 0.5);
+        assert_eq!(file_extension_risk("readme"), 0.0);
+    }
+
+    #[tokio::test]
+    async fn query_disguised_download_is_flagged() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor
+            .extract(
+                "downloads.example",
+                Some("https://downloads.example/get?file=invoice.pdf.exe"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(features["suspicious_file_extensions"], 1.0);
+
+        let benign = extractor
+            .extract("docs.example", Some("https://docs.example/report.pdf"))
+            .await
+            .unwrap();
+        assert_eq!(benign["suspicious_file_extensions"], 0.0);
+    }
+
+    #[test]
+    fn sanitize_replaces_non_finite_values() {
+        let mut features = FeatureSet::default();
+        features.set(Feature::ConsonantRatio, f32::INFINITY);
+        features.set(Feature::DigitRatio, f32::NAN);
+        features.set(Feature::Entropy, 2.5);
+        sanitize_features(&mut features);
+        assert_eq!(features["consonant_ratio"], 0.0);
+        assert_eq!(features["digit_ratio"], 0.0);
+        assert_eq!(features["entropy"], 2.5);
+    }
+
+    #[tokio::test]
+    async fn degenerate_domains_produce_finite_features() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        // Single character: no dot, no digits.
+        let features = extractor.extract("a", None).await.unwrap();
+        assert!(features.values().all(|v| v.is_finite()));
+
+        // Vowel-free domain used to divide by a zero vowel count.
+        let features = extractor.extract("xkcd.xyz", None).await.unwrap();
+        assert!(features.values().all(|v| v.is_finite()));
+        assert_eq!(features["consonant_ratio"], 0.0);
+    }
+
+    #[tokio::test]
+    async fn extracts_basic_features() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor.extract("paypa1.com", None).await.unwrap();
+        assert!(features["homoglyph_score"] > 0.5);
+        assert!(features["domain_length"] > 0.0);
+    }
+
+    #[test]
+    fn combosquatting_flags_brand_tokens_under_risky_tlds() {
+        let brands = BrandTable::builtin();
+        // Brand in a subdomain, brand hyphenated into the apex label, and
+        // brand buried mid-token-stream all match.
+        let (brand, risk) = combosquatting_brand("paypal.com.security-check.tk", &brands).unwrap();
+        assert_eq!(brand, "paypal");
+        assert_eq!(risk, 1.0);
+        assert_eq!(
+            combosquatting_brand("paypal-com.tk", &brands).map(|(b, _)| b),
+            Some("paypal")
+        );
+        assert_eq!(
+            combosquatting_brand("secure-amazon-login.top", &brands).map(|(b, _)| b),
+            Some("amazon")
+        );
+        // The brand itself, its real subdomains, and the brand as the apex
+        // label of a risky TLD are not combosquats.
+        assert!(combosquatting_brand("paypal.com", &brands).is_none());
+        assert!(combosquatting_brand("checkout.paypal.com", &brands).is_none());
+        assert!(combosquatting_brand("paypal.tk", &brands).is_none());
+        // No brand token, or a safe TLD, stays clean.
+        assert!(combosquatting_brand("totally-benign.example", &brands).is_none());
+        assert!(combosquatting_brand("paypal.com.security-check.org", &brands).is_none());
+    }
+
+    #[test]
+    fn brand_aliases_attribute_to_the_canonical_brand() {
+        let path = std::env::temp_dir().join(format!("brands-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"microsoft": ["msft", "ms"], "google": ["goog"]}"#).unwrap();
+        let table = BrandTable::load(path.to_str());
+        std::fs::remove_file(&path).ok();
+
+        // An alias match — here in a combosquat — attributes to the
+        // canonical brand, not the alias that matched.
+        assert_eq!(
+            combosquatting_brand("msft-login.tk", &table),
+            Some(("microsoft", 1.0))
+        );
+        assert_eq!(table.canonical("msft"), Some("microsoft"));
+        // Homoglyph-normalized spellings of an alias land on the brand too.
+        assert_eq!(table.canonical("m5ft"), Some("microsoft"));
+        // Built-in brands survive the merge; unknown labels stay clean.
+        assert_eq!(table.canonical("paypal"), Some("paypal"));
+        assert_eq!(table.canonical("msftx"), None);
+        // Without a configured table only the built-in brands exist, and a
+        // missing file falls back to them instead of failing startup.
+        assert!(BrandTable::builtin().canonical("msft").is_none());
+        assert!(BrandTable::load(Some("/nonexistent/brands.json"))
+            .canonical("google")
+            .is_some());
+    }
+
+    #[test]
+    fn impossible_hostnames_are_rejected_with_the_violation() {
+        // Plausible names pass: plain, punycode and raw-Unicode IDN, bare
+        // intranet labels, and names that merely do not exist — existence
+        // is not syntax's business.
+        assert_eq!(domain_syntax_error("example.com"), None);
+        assert_eq!(domain_syntax_error("xn--bcher-kva.example"), None);
+        assert_eq!(domain_syntax_error("münchen.de"), None);
+        assert_eq!(domain_syntax_error("intranet"), None);
+        assert_eq!(domain_syntax_error("no-such-name-x9q4kz.example"), None);
+
+        // Impossible ones are refused with the specific violation.
+        assert!(domain_syntax_error("").unwrap().contains("empty"));
+        assert!(domain_syntax_error("a..b.com").unwrap().contains("empty label"));
+        assert!(domain_syntax_error("a_b.com").unwrap().contains("'_'"));
+        assert!(domain_syntax_error("has space.com").unwrap().contains("' '"));
+        assert!(domain_syntax_error("-lead.com").unwrap().contains("hyphen"));
+        assert!(domain_syntax_error("trail-.com").unwrap().contains("hyphen"));
+        assert!(domain_syntax_error(&format!("{}.com", "a".repeat(64)))
+            .unwrap()
+            .contains("63"));
+        let oversized = format!("{}com", "abc.".repeat(64));
+        assert!(domain_syntax_error(&oversized).unwrap().contains("253"));
+    }
+
+    #[test]
+    fn popularity_requires_an_exact_registrable_match() {
+        assert!(is_popular_domain("google.com"));
+        assert!(is_popular_domain("www.google.com"));
+        // A popular name embedded in someone else's registrable domain
+        // must not inherit the anchor.
+        assert!(!is_popular_domain("google.com.evil.tk"));
+        assert!(!is_popular_domain("notgoogle.com"));
+    }
+
+    #[tokio::test]
+    async fn combosquat_feature_carries_the_tld_weight() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        let features = extractor
+            .extract("paypal.com.security-check.tk", None)
+            .await
+            .unwrap();
+        assert_eq!(features["combosquatting"], 1.0);
+        let clean = extractor.extract("example.com", None).await.unwrap();
+        assert_eq!(clean["combosquatting"], 0.0);
+    }
+
+    #[test]
+    fn known_bad_source_ip_raises_the_model_score() {
+        struct StubGeo;
+        impl GeoLookup for StubGeo {
+            fn country_code(&self, ip: std::net::IpAddr) -> Option<String> {
+                (ip == "203.0.113.9".parse::<std::net::IpAddr>().unwrap())
+                    .then(|| "XX".to_string())
+            }
+        }
+        let mut extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            geoip_country_risk: HashMap::from([("XX".to_string(), 0.9)]),
+            ..FeatureConfig::default()
+        });
+        extractor.geo = Some(Box::new(StubGeo));
+
+        let context = HashMap::from([
+            ("source_ip".to_string(), "203.0.113.9".to_string()),
+            ("unrecognized".to_string(), "ignored".to_string()),
+        ]);
+        let features = extractor.context_features(&context, "login.example");
+        assert_eq!(features["source_ip_risk"], 0.9);
+        // Unknown keys contribute nothing.
+        assert_eq!(features.len(), 1);
+
+        // A model weighting the feature scores the request strictly higher.
+        let model = crate::model::StudentModel {
+            version: "v1".to_string(),
+            weights: vec![4.0],
+            bias: 0.0,
+            training_samples: 1,
+            trained_at: None,
+            feature_names: vec!["source_ip_risk".to_string()],
+        };
+        let hostile = model.predict(&model.vector_for(&features));
+        let neutral = model.predict(&model.vector_for(&FeatureSet::default()));
+        assert!(hostile > neutral);
+    }
+
+    #[test]
+    fn referrer_and_user_agent_context_map_to_features() {
+        // Cross-registrable-domain referrer is a mismatch; same root and
+        // unparseable referrers are neutral.
+        assert_eq!(
+            referrer_mismatch("https://mail.google.com/inbox", "accounts-google.top"),
+            1.0
+        );
+        assert_eq!(
+            referrer_mismatch("https://www.example.com/a", "login.example.com"),
+            0.0
+        );
+        assert_eq!(referrer_mismatch("not a url", "example.com"), 0.0);
+
+        assert_eq!(ua_automation("python-requests/2.31"), 1.0);
+        assert_eq!(
+            ua_automation("Mozilla/5.0 (Windows NT 10.0; rv:128.0) Firefox/128.0"),
+            0.0
+        );
+    }
+}
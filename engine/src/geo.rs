@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use maxminddb::geoip2;
+
+use crate::error::AppError;
+
+/// Country resolution for an IP address. Abstracted behind a trait so the
+/// feature computation can be tested without a MaxMind database on disk.
+pub trait GeoLookup: Send + Sync {
+    /// ISO 3166-1 alpha-2 country code for the address, if known.
+    fn country_code(&self, ip: IpAddr) -> Option<String>;
+}
+
+/// GeoLookup backed by a MaxMind GeoLite2 Country/City database file.
+pub struct MaxmindGeo {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+impl MaxmindGeo {
+    pub fn open(path: &str) -> Result<Self, AppError> {
+        let reader = maxminddb::Reader::open_readfile(path)
+            .map_err(|e| AppError::Feature(format!("cannot open GeoIP database {path}: {e}")))?;
+        Ok(Self { reader })
+    }
+}
+
+impl GeoLookup for MaxmindGeo {
+    fn country_code(&self, ip: IpAddr) -> Option<String> {
+        let country: geoip2::Country = self.reader.lookup(ip).ok()?;
+        country
+            .country
+            .and_then(|c| c.iso_code)
+            .map(|code| code.to_string())
+    }
+}
+
+/// Compute `geolocation_risk` and `geo_mismatch` for a set of resolved IPs.
+///
+/// `geolocation_risk` is the maximum configured risk over the hosting
+/// countries; countries without a configured entry contribute nothing.
+/// `geo_mismatch` fires when the domain has a two-letter ccTLD and none of
+/// the hosting countries match it.
+pub fn geolocation_features(
+    ips: &[IpAddr],
+    tld: &str,
+    geo: &dyn GeoLookup,
+    country_risk: &HashMap<String, f32>,
+) -> (f32, f32) {
+    let countries: Vec<String> = ips.iter().filter_map(|ip| geo.country_code(*ip)).collect();
+    let risk = countries
+        .iter()
+        .filter_map(|code| country_risk.get(code).copied())
+        .fold(0.0f32, f32::max);
+
+    let mismatch = if tld.len() == 2 && tld.chars().all(|c| c.is_ascii_alphabetic()) {
+        let cctld_country = tld.to_uppercase();
+        if !countries.is_empty() && !countries.iter().any(|c| *c == cctld_country) {
+            1.0
+        } else {
+            0.0
+        }
+    } else {
+        0.0
+    };
+    (risk, mismatch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct StubGeo(HashMap<IpAddr, &'static str>);
+
+    impl GeoLookup for StubGeo {
+        fn country_code(&self, ip: IpAddr) -> Option<String> {
+            self.0.get(&ip).map(|c| c.to_string())
+        }
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn risk_is_max_over_hosting_countries() {
+        let geo = StubGeo(HashMap::from([
+            (ip("192.0.2.1"), "US"),
+            (ip("192.0.2.2"), "RU"),
+        ]));
+        let risk_map = HashMap::from([("US".to_string(), 0.1), ("RU".to_string(), 0.8)]);
+        let (risk, _) = geolocation_features(
+            &[ip("192.0.2.1"), ip("192.0.2.2")],
+            "com",
+            &geo,
+            &risk_map,
+        );
+        assert_eq!(risk, 0.8);
+    }
+
+    #[test]
+    fn unknown_countries_are_neutral() {
+        let geo = StubGeo(HashMap::new());
+        let (risk, mismatch) =
+            geolocation_features(&[ip("192.0.2.1")], "com", &geo, &HashMap::new());
+        assert_eq!(risk, 0.0);
+        assert_eq!(mismatch, 0.0);
+    }
+
+    #[test]
+    fn cctld_hosting_mismatch_is_flagged() {
+        let geo = StubGeo(HashMap::from([(ip("192.0.2.1"), "RU")]));
+        let (_, mismatch) =
+            geolocation_features(&[ip("192.0.2.1")], "de", &geo, &HashMap::new());
+        assert_eq!(mismatch, 1.0);
+
+        let geo = StubGeo(HashMap::from([(ip("192.0.2.1"), "DE")]));
+        let (_, mismatch) =
+            geolocation_features(&[ip("192.0.2.1")], "de", &geo, &HashMap::new());
+        assert_eq!(mismatch, 0.0);
+    }
+}
@@ -0,0 +1,298 @@
+use base64::Engine as _;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+use crate::error::AppError;
+
+const UPDATE_ENDPOINT: &str =
+    "https://safebrowsing.googleapis.com/v4/threatListUpdates:fetch";
+const FULL_HASHES_ENDPOINT: &str = "https://safebrowsing.googleapis.com/v4/fullHashes:find";
+
+const THREAT_TYPES: &[&str] = &["MALWARE", "SOCIAL_ENGINEERING", "UNWANTED_SOFTWARE"];
+
+/// Local Google Safe Browsing (Update API) hash-prefix database.
+///
+/// Four-byte hash prefixes from the GSB threat lists are kept in memory as a
+/// sorted `Vec<u32>`, so the hot path is a handful of SHA-256 computations
+/// plus binary searches. Only when a prefix matches do we confirm against the
+/// full-hash API, which keeps nearly all checks off the network.
+pub struct GsbPrefixStore {
+    api_key: String,
+    http: reqwest::Client,
+    /// Sorted big-endian u32 prefixes across all tracked lists.
+    prefixes: RwLock<Vec<u32>>,
+    /// Per-threat-type client state tokens for incremental updates.
+    client_states: RwLock<std::collections::HashMap<String, String>>,
+}
+
+impl GsbPrefixStore {
+    pub fn new(api_key: String, http: reqwest::Client) -> Self {
+        Self {
+            api_key,
+            http,
+            prefixes: RwLock::new(Vec::new()),
+            client_states: RwLock::new(std::collections::HashMap::new()),
+        }
+    }
+
+    pub async fn prefix_count(&self) -> usize {
+        self.prefixes.read().await.len()
+    }
+
+    /// Fetch threat list updates and rebuild the local prefix set.
+    pub async fn update(&self) -> Result<(), AppError> {
+        let states = self.client_states.read().await.clone();
+        let requests: Vec<_> = THREAT_TYPES
+            .iter()
+            .map(|threat_type| {
+                json!({
+                    "threatType": threat_type,
+                    "platformType": "ANY_PLATFORM",
+                    "threatEntryType": "URL",
+                    "state": states.get(*threat_type).cloned().unwrap_or_default(),
+                    "constraints": { "supportedCompressions": ["RAW"] }
+                })
+            })
+            .collect();
+        let body = json!({
+            "client": { "clientId": "garuda", "clientVersion": "1.0" },
+            "listUpdateRequests": requests,
+        });
+
+        let endpoint = format!("{}?key={}", UPDATE_ENDPOINT, self.api_key);
+        let response: serde_json::Value = self
+            .http
+            .post(&endpoint)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB update fetch failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB update parse failed: {e}")))?;
+
+        let updates = response
+            .get("listUpdateResponses")
+            .and_then(|u| u.as_array())
+            .cloned()
+            .unwrap_or_default();
+
+        let mut prefixes = self.prefixes.write().await;
+        let mut client_states = self.client_states.write().await;
+        for update in updates {
+            let threat_type = update
+                .get("threatType")
+                .and_then(|t| t.as_str())
+                .unwrap_or_default()
+                .to_string();
+            if update.get("responseType").and_then(|r| r.as_str()) == Some("FULL_UPDATE") {
+                prefixes.clear();
+            }
+            for addition in update
+                .get("additions")
+                .and_then(|a| a.as_array())
+                .into_iter()
+                .flatten()
+            {
+                if let Some(raw) = addition
+                    .get("rawHashes")
+                    .and_then(|r| r.get("rawHashes"))
+                    .and_then(|r| r.as_str())
+                {
+                    let decoded = base64::engine::general_purpose::STANDARD
+                        .decode(raw)
+                        .map_err(|e| AppError::Intel(format!("bad rawHashes: {e}")))?;
+                    for chunk in decoded.chunks_exact(4) {
+                        prefixes.push(u32::from_be_bytes([
+                            chunk[0], chunk[1], chunk[2], chunk[3],
+                        ]));
+                    }
+                }
+            }
+            if let Some(state) = update.get("newClientState").and_then(|s| s.as_str()) {
+                client_states.insert(threat_type, state.to_string());
+            }
+        }
+        prefixes.sort_unstable();
+        prefixes.dedup();
+        info!(count = prefixes.len(), "GSB prefix database updated");
+        Ok(())
+    }
+
+    /// Hot-path check: does any URL expression's hash prefix appear in the
+    /// local database?
+    pub async fn check_prefixes(&self, url: &str) -> bool {
+        let prefixes = self.prefixes.read().await;
+        if prefixes.is_empty() {
+            return false;
+        }
+        url_expressions(url)
+            .iter()
+            .any(|expr| prefixes.binary_search(&hash_prefix(expr)).is_ok())
+    }
+
+    /// Confirm a prefix hit against the full-hash API. Returns the threat
+    /// type when the full hash matches.
+    pub async fn find_full_hash(&self, url: &str) -> Result<Option<String>, AppError> {
+        let expressions = url_expressions(url);
+        let matched_prefixes: Vec<String> = {
+            let prefixes = self.prefixes.read().await;
+            expressions
+                .iter()
+                .filter(|expr| prefixes.binary_search(&hash_prefix(expr)).is_ok())
+                .map(|expr| {
+                    let hash = Sha256::digest(expr.as_bytes());
+                    base64::engine::general_purpose::STANDARD.encode(&hash[..4])
+                })
+                .collect()
+        };
+        if matched_prefixes.is_empty() {
+            return Ok(None);
+        }
+
+        let body = json!({
+            "client": { "clientId": "garuda", "clientVersion": "1.0" },
+            "clientStates": [],
+            "threatInfo": {
+                "threatTypes": THREAT_TYPES,
+                "platformTypes": ["ANY_PLATFORM"],
+                "threatEntryTypes": ["URL"],
+                "threatEntries": matched_prefixes
+                    .iter()
+                    .map(|p| json!({ "hash": p }))
+                    .collect::<Vec<_>>(),
+            }
+        });
+        let endpoint = format!("{}?key={}", FULL_HASHES_ENDPOINT, self.api_key);
+        let response: serde_json::Value = self
+            .http
+            .post(&endpoint)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB full-hash fetch failed: {e}")))?
+            .json()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB full-hash parse failed: {e}")))?;
+
+        let full_hashes: std::collections::HashSet<String> = expressions
+            .iter()
+            .map(|expr| {
+                base64::engine::general_purpose::STANDARD
+                    .encode(Sha256::digest(expr.as_bytes()))
+            })
+            .collect();
+        for m in response
+            .get("matches")
+            .and_then(|m| m.as_array())
+            .into_iter()
+            .flatten()
+        {
+            let hash = m
+                .get("threat")
+                .and_then(|t| t.get("hash"))
+                .and_then(|h| h.as_str())
+                .unwrap_or_default();
+            if full_hashes.contains(hash) {
+                let threat_type = m
+                    .get("threatType")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("UNKNOWN");
+                return Ok(Some(threat_type.to_lowercase()));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Spawn the periodic update loop.
+    pub fn start_update_task(self: &std::sync::Arc<Self>, interval_seconds: u64) {
+        let store = std::sync::Arc::clone(self);
+        tokio::spawn(async move {
+            let mut ticker =
+                tokio::time::interval(std::time::Duration::from_secs(interval_seconds));
+            loop {
+                ticker.tick().await;
+                if let Err(e) = store.update().await {
+                    warn!(error = %e, "GSB prefix update failed");
+                }
+            }
+        });
+    }
+}
+
+/// First four bytes of the expression's SHA-256 as a big-endian u32.
+fn hash_prefix(expression: &str) -> u32 {
+    let hash = Sha256::digest(expression.as_bytes());
+    u32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// The host-suffix / path-prefix expressions GSB hashes for a URL.
+///
+/// This follows the spec's shape (up to five host suffixes, the exact path
+/// with query, and up to four path prefixes) over an already-normalized URL.
+fn url_expressions(url: &str) -> Vec<String> {
+    let parsed = match url::Url::parse(url) {
+        Ok(p) => p,
+        Err(_) => return vec![url.trim_end_matches('/').to_string()],
+    };
+    let host = parsed.host_str().unwrap_or_default().to_lowercase();
+    let path = parsed.path().to_string();
+    let query = parsed.query().map(|q| format!("?{q}"));
+
+    let mut hosts = vec![host.clone()];
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() > 2 {
+        for start in (labels.len().saturating_sub(5))..(labels.len() - 1) {
+            let suffix = labels[start..].join(".");
+            if suffix != host && suffix.contains('.') {
+                hosts.push(suffix);
+            }
+        }
+    }
+
+    let mut paths = vec!["/".to_string()];
+    let mut prefix = String::new();
+    for segment in path.split('/').filter(|s| !s.is_empty()).take(3) {
+        prefix = format!("{prefix}/{segment}");
+        paths.push(format!("{prefix}/"));
+    }
+    if path != "/" {
+        paths.push(path.clone());
+    }
+    if let Some(query) = query {
+        paths.push(format!("{path}{query}"));
+    }
+    paths.dedup();
+
+    let mut expressions = Vec::new();
+    for host in &hosts {
+        for path in &paths {
+            expressions.push(format!("{host}{path}"));
+        }
+    }
+    expressions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn prefix_lookup_hits_and_misses() {
+        let store = GsbPrefixStore::new(String::new(), reqwest::Client::new());
+        let expr = "evil.example/";
+        store.prefixes.write().await.push(hash_prefix(expr));
+        assert!(store.check_prefixes("http://evil.example/").await);
+        assert!(!store.check_prefixes("http://good.example/").await);
+    }
+
+    #[test]
+    fn expressions_cover_host_suffixes_and_path_prefixes() {
+        let exprs = url_expressions("http://a.b.example.com/p1/p2?q=1");
+        assert!(exprs.contains(&"a.b.example.com/p1/p2?q=1".to_string()));
+        assert!(exprs.contains(&"example.com/".to_string()));
+        assert!(exprs.contains(&"a.b.example.com/p1/".to_string()));
+    }
+}
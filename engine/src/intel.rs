@@ -0,0 +1,1265 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::sync::RwLock;
+use tracing::{error, info, warn};
+
+use crate::config::{FeedFormat, FeedSourceConfig, IntelConfig, IntelException};
+use crate::error::AppError;
+use crate::gsb::GsbPrefixStore;
+
+const GSB_ENDPOINT: &str = "https://safebrowsing.googleapis.com/v4/threatMatches:find";
+
+/// How often the refresh loop checks whether a source is due.
+const REFRESH_POLL_SECONDS: u64 = 60;
+
+/// A hit against a hard intelligence source (blocklist feed, GSB, ...).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HardIntelMatch {
+    pub source: String,
+    pub category: String,
+    pub confidence: f32,
+    /// The list entry that matched.
+    pub matched: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntelStatistics {
+    pub sources: HashMap<String, SourceStats>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SourceStats {
+    pub size: usize,
+    pub last_refresh: Option<DateTime<Utc>>,
+    /// Current refresh interval after failure backoff; `None` for local
+    /// lists, which are never re-fetched.
+    pub refresh_interval_seconds: Option<u64>,
+}
+
+/// Per-source refresh scheduling state for the backoff loop.
+struct FeedSchedule {
+    consecutive_failures: u32,
+    next_due: Instant,
+}
+
+/// Consecutive-failure circuit breaker for external reputation calls.
+///
+/// After `failure_threshold` consecutive failures the breaker opens and
+/// callers short-circuit. Once the cooldown elapses a single half-open probe
+/// is allowed through; success closes the breaker, failure re-opens it.
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: std::sync::Mutex<BreakerState>,
+}
+
+#[derive(Default)]
+struct BreakerState {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            cooldown,
+            state: std::sync::Mutex::new(BreakerState::default()),
+        }
+    }
+
+    /// May a call proceed? True while closed, and for half-open probes.
+    pub fn allow(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        match state.opened_at {
+            None => true,
+            Some(opened_at) => opened_at.elapsed() >= self.cooldown,
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.state.lock().unwrap().opened_at.is_some()
+    }
+
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.opened_at = None;
+    }
+
+    pub fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        if state.consecutive_failures >= self.failure_threshold {
+            state.opened_at = Some(Instant::now());
+        }
+    }
+}
+
+/// Checks domains against local blocklist feeds and external reputation APIs.
+pub struct HardIntelChecker {
+    config: IntelConfig,
+    http: reqwest::Client,
+    /// Per-source sets of blocklisted domains.
+    blocklists: RwLock<HashMap<String, HashSet<String>>>,
+    allowlist: RwLock<HashSet<String>>,
+    /// TTL'd, capacity-bounded lookup cache; stores both hits and misses.
+    cache: moka::future::Cache<String, Option<HardIntelMatch>>,
+    last_refresh: RwLock<HashMap<String, DateTime<Utc>>>,
+    refresh_schedules: RwLock<HashMap<String, FeedSchedule>>,
+    /// Local GSB hash-prefix database, when enabled in config.
+    gsb_prefixes: Option<Arc<GsbPrefixStore>>,
+    gsb_breaker: CircuitBreaker,
+    /// Runtime master switch for external reputation calls; togglable via
+    /// `/admin/flags` without a restart, e.g. during a quota crunch.
+    external_lookups: std::sync::atomic::AtomicBool,
+}
+
+impl HardIntelChecker {
+    pub fn new(config: IntelConfig) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(5))
+            .user_agent("garuda-engine/1.0")
+            .build()
+            .expect("failed to build HTTP client");
+        let gsb_prefixes = if config.gsb_local_database && !config.gsb_api_key.is_empty() {
+            Some(Arc::new(GsbPrefixStore::new(
+                config.gsb_api_key.clone(),
+                http.clone(),
+            )))
+        } else {
+            None
+        };
+        Self {
+            gsb_breaker: CircuitBreaker::new(
+                config.gsb_failure_threshold,
+                Duration::from_secs(config.gsb_cooldown_seconds),
+            ),
+            cache: moka::future::Cache::builder()
+                .max_capacity(config.cache_max_entries)
+                .time_to_live(Duration::from_secs(config.cache_ttl_seconds))
+                .build(),
+            external_lookups: std::sync::atomic::AtomicBool::new(config.external_lookups),
+            config,
+            http,
+            blocklists: RwLock::new(HashMap::new()),
+            allowlist: RwLock::new(HashSet::new()),
+            last_refresh: RwLock::new(HashMap::new()),
+            refresh_schedules: RwLock::new(HashMap::new()),
+            gsb_prefixes,
+        }
+    }
+
+    /// Load the operator-provided local lists from disk.
+    pub async fn load_local_lists(&self) -> Result<(), AppError> {
+        if let Some(path) = &self.config.allowlist_path {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| AppError::Intel(format!("cannot read allowlist {path}: {e}")))?;
+            let mut allowlist = self.allowlist.write().await;
+            for line in content.lines() {
+                let line = line.trim();
+                if !line.is_empty() && !line.starts_with('#') {
+                    allowlist.insert(line.to_lowercase());
+                }
+            }
+            info!(count = allowlist.len(), "loaded allowlist");
+        }
+        if let Some(path) = &self.config.blocklist_path {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| AppError::Intel(format!("cannot read blocklist {path}: {e}")))?;
+            let mut set = HashSet::new();
+            for line in content.lines() {
+                let line = line.trim();
+                if !line.is_empty() && !line.starts_with('#') {
+                    set.insert(line.to_lowercase());
+                }
+            }
+            info!(count = set.len(), "loaded local blocklist");
+            self.blocklists.write().await.insert("local".to_string(), set);
+            self.last_refresh
+                .write()
+                .await
+                .insert("local".to_string(), Utc::now());
+        }
+        Ok(())
+    }
+
+    /// Check the domain against the in-memory lists only. When a domain is
+    /// on several lists the configured precedence decides: the allowlist
+    /// wins if `allowlist_overrides_blocklist` is set, and among blocklist
+    /// hits the highest-ranked source in `source_priority` is returned.
+    ///
+    /// With `suffix_matching` enabled, list entries also cover subdomains:
+    /// the queried name and its parent suffixes down to the registrable
+    /// domain (per the PSL, so `com` never matches) are all candidates.
+    pub async fn check_local_lists(&self, domain: &str) -> Option<HardIntelMatch> {
+        self.local_matches(domain).await.into_iter().next()
+    }
+
+    /// Every local-list hit for the domain (or a matching parent
+    /// candidate), in source-priority order. The scoring path only wants
+    /// the winner via `check_local_lists`; `/intel/lookup` reports them
+    /// all.
+    pub async fn local_matches(&self, domain: &str) -> Vec<HardIntelMatch> {
+        let candidates = self.match_candidates(domain);
+        if self.config.allowlist_overrides_blocklist && self.is_allowlisted(domain).await {
+            return Vec::new();
+        }
+        let blocklists = self.blocklists.read().await;
+        let mut hits: Vec<(&String, &String)> = blocklists
+            .iter()
+            .filter_map(|(source, set)| {
+                candidates
+                    .iter()
+                    .find(|c| set.contains(*c))
+                    .map(|matched| (source, matched))
+            })
+            .filter(|(source, matched)| {
+                if self.is_excepted(matched, source) {
+                    tracing::info!(
+                        entry = %matched,
+                        source = %source,
+                        "hard-intel hit suppressed by configured exception"
+                    );
+                    false
+                } else {
+                    true
+                }
+            })
+            .collect();
+        // Sources missing from the priority list rank last, name-ordered so
+        // the winner is deterministic.
+        hits.sort_by(|(a, _), (b, _)| {
+            self.source_rank(a)
+                .cmp(&self.source_rank(b))
+                .then_with(|| a.cmp(b))
+        });
+        hits.into_iter()
+            .map(|(source, matched)| HardIntelMatch {
+                source: source.clone(),
+                category: source_category(source).to_string(),
+                confidence: source_confidence(source),
+                matched: matched.clone(),
+            })
+            .collect()
+    }
+
+    /// Whether the domain (or a matching parent candidate) is on the
+    /// allowlist.
+    pub async fn is_allowlisted(&self, domain: &str) -> bool {
+        let candidates = self.match_candidates(domain);
+        let allowlist = self.allowlist.read().await;
+        candidates.iter().any(|c| allowlist.contains(c))
+    }
+
+    /// Whether a configured exception silences `source`'s hit on the list
+    /// entry `matched`.
+    fn is_excepted(&self, matched: &str, source: &str) -> bool {
+        self.config.exceptions.iter().any(|exception| {
+            exception.domain == matched
+                && exception.source.as_deref().map_or(true, |s| s == source)
+        })
+    }
+
+    /// The names a list entry may match for `domain`: the domain itself,
+    /// plus (when suffix matching is on) each parent suffix down to the
+    /// registrable domain.
+    fn match_candidates(&self, domain: &str) -> Vec<String> {
+        let mut candidates = vec![domain.to_string()];
+        // An IP literal can also be covered by a CIDR feed entry; every
+        // enclosing network (most specific first) is one set lookup away.
+        if let Ok(addr) = domain.parse::<std::net::Ipv4Addr>() {
+            for prefix in (0..=32).rev() {
+                candidates.push(format!(
+                    "{}/{prefix}",
+                    std::net::Ipv4Addr::from(u32::from(addr) & prefix_mask(prefix))
+                ));
+            }
+            return candidates;
+        }
+        if !self.config.suffix_matching {
+            return candidates;
+        }
+        let root = addr::parse_domain_name(domain)
+            .ok()
+            .and_then(|parsed| parsed.root().map(|r| r.to_string()));
+        if let Some(root) = root {
+            let mut current = domain.to_string();
+            while current != root {
+                match current.split_once('.') {
+                    Some((_, parent)) => {
+                        current = parent.to_string();
+                        candidates.push(current.clone());
+                    }
+                    None => break,
+                }
+            }
+        }
+        candidates
+    }
+
+    fn source_rank(&self, source: &str) -> usize {
+        self.config
+            .source_priority
+            .iter()
+            .position(|s| s == source)
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Query the live Google Safe Browsing Lookup API for a URL.
+    pub async fn check_google_safe_browsing(
+        &self,
+        url: &str,
+    ) -> Result<Option<HardIntelMatch>, AppError> {
+        let body = json!({
+            "client": { "clientId": "garuda", "clientVersion": "1.0" },
+            "threatInfo": {
+                "threatTypes": ["MALWARE", "SOCIAL_ENGINEERING", "UNWANTED_SOFTWARE"],
+                "platformTypes": ["ANY_PLATFORM"],
+                "threatEntryTypes": ["URL"],
+                "threatEntries": [{ "url": url }]
+            }
+        });
+        let endpoint = format!("{}?key={}", GSB_ENDPOINT, self.config.gsb_api_key);
+        let response = self
+            .http
+            .post(&endpoint)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB request failed: {e}")))?;
+        let parsed: serde_json::Value = response
+            .json()
+            .await
+            .map_err(|e| AppError::Intel(format!("GSB response parse failed: {e}")))?;
+
+        if let Some(matches) = parsed.get("matches").and_then(|m| m.as_array()) {
+            if let Some(first) = matches.first() {
+                let threat_type = first
+                    .get("threatType")
+                    .and_then(|t| t.as_str())
+                    .unwrap_or("UNKNOWN");
+                return Ok(Some(HardIntelMatch {
+                    source: "google_safe_browsing".to_string(),
+                    category: threat_type.to_lowercase(),
+                    confidence: 0.95,
+                    matched: url.to_string(),
+                }));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Local lists first, then (when configured) the external APIs. Results
+    /// are cached per domain.
+    pub async fn check_comprehensive(
+        &self,
+        domain: &str,
+        url: Option<&str>,
+    ) -> Option<HardIntelMatch> {
+        if let Some(cached) = self.cache.get(domain).await {
+            return cached;
+        }
+
+        let mut result = self.check_local_lists(domain).await;
+
+        if result.is_none()
+            && self.external_lookups_enabled()
+            && !self.config.gsb_api_key.is_empty()
+            && self.gsb_breaker.allow()
+        {
+            let target = url
+                .map(|u| u.to_string())
+                .unwrap_or_else(|| format!("http://{domain}/"));
+            if let Some(prefixes) = &self.gsb_prefixes {
+                // Local prefix database: only a prefix hit pays for the
+                // full-hash confirmation round-trip.
+                if prefixes.check_prefixes(&target).await {
+                    match prefixes.find_full_hash(&target).await {
+                        Ok(Some(threat_type)) => {
+                            self.gsb_breaker.record_success();
+                            result = Some(HardIntelMatch {
+                                source: "google_safe_browsing".to_string(),
+                                category: threat_type,
+                                confidence: 0.95,
+                                matched: target.clone(),
+                            });
+                        }
+                        Ok(None) => self.gsb_breaker.record_success(),
+                        Err(e) => {
+                            self.gsb_breaker.record_failure();
+                            warn!(domain, error = %e, "GSB full-hash check failed");
+                        }
+                    }
+                }
+            } else {
+                match self.check_google_safe_browsing(&target).await {
+                    Ok(gsb) => {
+                        self.gsb_breaker.record_success();
+                        result = gsb;
+                    }
+                    Err(e) => {
+                        self.gsb_breaker.record_failure();
+                        warn!(domain, error = %e, "GSB check failed");
+                    }
+                }
+            }
+        }
+
+        self.cache.insert(domain.to_string(), result.clone()).await;
+        result
+    }
+
+    /// Refresh every configured remote feed unconditionally (startup).
+    pub async fn refresh_all(&self) {
+        for feed in &self.config.feeds {
+            self.refresh_one(feed).await;
+        }
+    }
+
+    /// Refresh the feeds whose per-source schedule is due, so a failing
+    /// source backs off without delaying the healthy ones.
+    pub async fn refresh_due_feeds(&self) {
+        let now = Instant::now();
+        for feed in &self.config.feeds {
+            let due = {
+                let schedules = self.refresh_schedules.read().await;
+                schedules
+                    .get(&feed.name)
+                    .map(|schedule| schedule.next_due <= now)
+                    .unwrap_or(true)
+            };
+            if due {
+                self.refresh_one(feed).await;
+            }
+        }
+    }
+
+    async fn refresh_one(&self, feed: &FeedSourceConfig) {
+        let result = self.refresh_feed(feed).await;
+        if let Err(e) = &result {
+            error!(source = %feed.name, error = %e, "feed refresh failed");
+        }
+        self.record_refresh_outcome(&feed.name, result.is_ok()).await;
+    }
+
+    /// Reschedule a source after a refresh attempt: each consecutive
+    /// failure doubles its interval up to the configured cap, and a
+    /// success resets it to the base interval.
+    async fn record_refresh_outcome(&self, source: &str, success: bool) {
+        let mut schedules = self.refresh_schedules.write().await;
+        let schedule = schedules
+            .entry(source.to_string())
+            .or_insert(FeedSchedule {
+                consecutive_failures: 0,
+                next_due: Instant::now(),
+            });
+        if success {
+            schedule.consecutive_failures = 0;
+        } else {
+            schedule.consecutive_failures += 1;
+        }
+        let interval = backoff_interval(
+            self.config.refresh_interval_seconds,
+            self.config.refresh_max_interval_seconds,
+            schedule.consecutive_failures,
+        );
+        schedule.next_due = Instant::now() + Duration::from_secs(interval);
+    }
+
+    /// The source's current refresh interval, after backoff.
+    async fn current_refresh_interval(&self, source: &str) -> u64 {
+        let failures = self
+            .refresh_schedules
+            .read()
+            .await
+            .get(source)
+            .map(|schedule| schedule.consecutive_failures)
+            .unwrap_or(0);
+        backoff_interval(
+            self.config.refresh_interval_seconds,
+            self.config.refresh_max_interval_seconds,
+            failures,
+        )
+    }
+
+    /// Fetch one configured feed (and its checksum document, when one is
+    /// configured) and swap in its parsed entry set.
+    async fn refresh_feed(&self, feed: &FeedSourceConfig) -> Result<(), AppError> {
+        // Raw bytes, not text: the hash must cover exactly what the server
+        // sent, before any lossy UTF-8 conversion or decompression.
+        let body = self
+            .http
+            .get(&feed.url)
+            // Invite compression; `apply_feed_body` inflates whatever
+            // arrives, so a server that ignores the header costs nothing.
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip")
+            .send()
+            .await
+            .map_err(|e| AppError::Intel(format!("{} fetch failed: {e}", feed.name)))?
+            .bytes()
+            .await
+            .map_err(|e| AppError::Intel(format!("{} read failed: {e}", feed.name)))?;
+        let checksum_body = match &feed.checksum_url {
+            Some(url) => Some(
+                self.http
+                    .get(url)
+                    .send()
+                    .await
+                    .map_err(|e| {
+                        AppError::Intel(format!("{} checksum fetch failed: {e}", feed.name))
+                    })?
+                    .text()
+                    .await
+                    .map_err(|e| {
+                        AppError::Intel(format!("{} checksum read failed: {e}", feed.name))
+                    })?,
+            ),
+            None => None,
+        };
+        self.apply_feed_body(feed, &body, checksum_body.as_deref()).await
+    }
+
+    /// Verify the body against the published checksum (when the feed has
+    /// one) and swap in the parsed set. A mismatch fails the refresh, so a
+    /// tampered or truncated download never replaces the previous set and
+    /// the source backs off like any other failure.
+    async fn apply_feed_body(
+        &self,
+        feed: &FeedSourceConfig,
+        body: &[u8],
+        checksum_body: Option<&str>,
+    ) -> Result<(), AppError> {
+        if let Some(checksum_body) = checksum_body {
+            let expected = expected_sha256(checksum_body).ok_or_else(|| {
+                AppError::Intel(format!("{} checksum document holds no SHA-256", feed.name))
+            })?;
+            let actual = sha256_hex(body);
+            if actual != expected {
+                return Err(AppError::Intel(format!(
+                    "{} checksum mismatch: expected {expected}, got {actual};                      keeping the previous set",
+                    feed.name
+                )));
+            }
+            info!(source = %feed.name, "feed checksum verified");
+        }
+        let body = decompress_feed(body);
+        let set = parse_feed(&feed.format, &String::from_utf8_lossy(&body));
+        info!(source = %feed.name, count = set.len(), "refreshed feed");
+        self.blocklists
+            .write()
+            .await
+            .insert(feed.name.clone(), set);
+        self.last_refresh
+            .write()
+            .await
+            .insert(feed.name.clone(), Utc::now());
+        Ok(())
+    }
+
+    /// Spawn the background refresh loop. Each source keeps its own
+    /// schedule; the loop just polls for whichever is due next.
+    pub fn start_refresh_task(self: &Arc<Self>) {
+        if let Some(prefixes) = &self.gsb_prefixes {
+            prefixes.start_update_task(self.config.gsb_update_interval_seconds);
+        }
+        let checker = Arc::clone(self);
+        let poll = REFRESH_POLL_SECONDS.min(checker.config.refresh_interval_seconds.max(1));
+        tokio::spawn(async move {
+            checker.refresh_all().await;
+            loop {
+                tokio::time::sleep(Duration::from_secs(poll)).await;
+                checker.refresh_due_feeds().await;
+            }
+        });
+    }
+
+    /// Whether the GSB circuit breaker is currently open. Exposed for the
+    /// `garuda_gsb_circuit_open` gauge.
+    pub fn gsb_circuit_open(&self) -> bool {
+        self.gsb_breaker.is_open()
+    }
+
+    /// Whether external reputation calls are currently allowed.
+    pub fn external_lookups_enabled(&self) -> bool {
+        self.external_lookups
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    /// Flip the external-lookup switch at runtime. The lookup cache is
+    /// dropped on a change so verdicts reflect the new policy immediately
+    /// rather than after cache TTL.
+    pub fn set_external_lookups(&self, enabled: bool) {
+        let previous = self
+            .external_lookups
+            .swap(enabled, std::sync::atomic::Ordering::Relaxed);
+        if previous != enabled {
+            self.cache.invalidate_all();
+            info!(enabled, "external intel lookups toggled");
+        }
+    }
+
+    pub async fn get_statistics(&self) -> IntelStatistics {
+        let blocklists = self.blocklists.read().await;
+        let last_refresh = self.last_refresh.read().await;
+        let mut stats = IntelStatistics::default();
+        for (source, set) in blocklists.iter() {
+            let refresh_interval_seconds =
+                if self.config.feeds.iter().any(|feed| &feed.name == source) {
+                    Some(self.current_refresh_interval(source).await)
+                } else {
+                    None
+                };
+            stats.sources.insert(
+                source.clone(),
+                SourceStats {
+                    size: set.len(),
+                    last_refresh: last_refresh.get(source).copied(),
+                    refresh_interval_seconds,
+                },
+            );
+        }
+        stats
+    }
+
+    /// The per-source statistics as Prometheus gauges for /metrics:
+    /// list sizes (a feed dropping to zero means a broken fetch) and
+    /// seconds since the last successful refresh (a growing age means the
+    /// feed went stale). Sources are emitted name-ordered so consecutive
+    /// scrapes diff cleanly.
+    pub async fn statistics_gauges(&self) -> String {
+        let stats = self.get_statistics().await;
+        if stats.sources.is_empty() {
+            return String::new();
+        }
+        let mut sources: Vec<_> = stats.sources.into_iter().collect();
+        sources.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let mut out = String::from("# TYPE garuda_intel_list_size gauge\n");
+        for (source, source_stats) in &sources {
+            out.push_str(&format!(
+                "garuda_intel_list_size{{source=\"{source}\"}} {}\n",
+                source_stats.size
+            ));
+        }
+        out.push_str("# TYPE garuda_intel_last_refresh_age_seconds gauge\n");
+        for (source, source_stats) in &sources {
+            if let Some(last_refresh) = source_stats.last_refresh {
+                let age = (Utc::now() - last_refresh).num_seconds().max(0);
+                out.push_str(&format!(
+                    "garuda_intel_last_refresh_age_seconds{{source=\"{source}\"}} {age}\n"
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Parse a fetched feed body into its set of list entries.
+///
+/// Unparseable lines are skipped rather than failing the refresh: feeds
+/// routinely carry banners, comments, and the odd malformed row.
+pub(crate) fn parse_feed(format: &FeedFormat, body: &str) -> HashSet<String> {
+    let mut set = HashSet::new();
+    match format {
+        FeedFormat::Hostfile => {
+            for line in data_lines(body) {
+                let mut parts = line.split_whitespace();
+                if matches!(parts.next(), Some("127.0.0.1" | "0.0.0.0")) {
+                    if let Some(domain) = parts.next() {
+                        set.insert(domain.to_lowercase());
+                    }
+                }
+            }
+        }
+        FeedFormat::PlainDomainList => {
+            for line in data_lines(body) {
+                let host = url::Url::parse(line)
+                    .ok()
+                    .and_then(|url| url.host_str().map(str::to_string));
+                set.insert(host.unwrap_or_else(|| line.to_string()).to_lowercase());
+            }
+        }
+        FeedFormat::Csv { domain_column } => {
+            for line in data_lines(body).skip(1) {
+                if let Some(field) = line.split(',').nth(*domain_column) {
+                    let field = field.trim().trim_matches('"');
+                    if !field.is_empty() {
+                        set.insert(field.to_lowercase());
+                    }
+                }
+            }
+        }
+        FeedFormat::JsonArray { pointer } => {
+            let Ok(doc) = serde_json::from_str::<serde_json::Value>(body) else {
+                return set;
+            };
+            if let Some(entries) = doc.pointer(pointer).and_then(|v| v.as_array()) {
+                for entry in entries {
+                    if let Some(domain) = entry.as_str() {
+                        set.insert(domain.trim().to_lowercase());
+                    }
+                }
+            }
+        }
+        FeedFormat::Cidr => {
+            for line in data_lines(body) {
+                // DROP-style feeds suffix entries with `; SBLnnn`.
+                let entry = line.split([';', '#']).next().unwrap_or("").trim();
+                if let Some(network) = canonical_cidr(entry) {
+                    set.insert(network);
+                }
+            }
+        }
+    }
+    set
+}
+
+/// Nested gzip layers inflated before giving up; 2 covers the observed
+/// double-compressed feeds (a `.gz` artifact served with
+/// `Content-Encoding: gzip` on top) with headroom, while still bounding a
+/// hostile bomb of deeply nested layers.
+const MAX_GZIP_LAYERS: usize = 4;
+
+/// Transparently inflate a gzip-compressed feed body. Detection keys on
+/// the gzip magic number in the payload itself rather than on the
+/// `Content-Encoding` header or a `.gz` URL suffix — both manifest as the
+/// same leading bytes, and sniffing also gets mislabeled feeds right in
+/// either direction. Applied layer by layer, so a double-compressed body
+/// comes out plaintext too. A body that carries the magic but fails to
+/// inflate is passed through as-is: the line parser skips what it cannot
+/// read, exactly as for any other malformed feed.
+fn decompress_feed(body: &[u8]) -> Vec<u8> {
+    use std::io::Read;
+    const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+    let mut current = body.to_vec();
+    for _ in 0..MAX_GZIP_LAYERS {
+        if !current.starts_with(&GZIP_MAGIC) {
+            break;
+        }
+        let mut inflated = Vec::new();
+        match flate2::read::GzDecoder::new(current.as_slice()).read_to_end(&mut inflated) {
+            Ok(_) => current = inflated,
+            Err(e) => {
+                warn!(error = %e, "feed body has the gzip magic but does not inflate; parsing raw");
+                break;
+            }
+        }
+    }
+    current
+}
+
+/// Non-empty, non-comment lines, trimmed.
+fn data_lines(body: &str) -> impl Iterator<Item = &str> {
+    body.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+}
+
+/// Canonical `network/prefix` form of an IPv4 CIDR entry (host bits masked
+/// off), or `None` when the line is not one.
+fn canonical_cidr(entry: &str) -> Option<String> {
+    let (addr, prefix) = entry.split_once('/')?;
+    let addr: std::net::Ipv4Addr = addr.trim().parse().ok()?;
+    let prefix: u32 = prefix.trim().parse().ok()?;
+    if prefix > 32 {
+        return None;
+    }
+    Some(format!(
+        "{}/{prefix}",
+        std::net::Ipv4Addr::from(u32::from(addr) & prefix_mask(prefix))
+    ))
+}
+
+/// The expected SHA-256 out of a published checksum document: the first
+/// 64-character hex token, so both a bare digest and the common
+/// `<digest>  <filename>` sidecar layout parse.
+fn expected_sha256(body: &str) -> Option<String> {
+    body.split_whitespace()
+        .find(|token| token.len() == 64 && token.chars().all(|c| c.is_ascii_hexdigit()))
+        .map(str::to_ascii_lowercase)
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    use sha2::{Digest, Sha256};
+    Sha256::digest(bytes)
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn prefix_mask(prefix: u32) -> u32 {
+    match prefix {
+        0 => 0,
+        p => u32::MAX << (32 - p),
+    }
+}
+
+/// Refresh interval for a source after `failures` consecutive failures:
+/// the base interval doubled per failure, capped.
+fn backoff_interval(base: u64, cap: u64, failures: u32) -> u64 {
+    let factor = 1u64 << failures.min(20);
+    base.saturating_mul(factor).min(cap.max(base))
+}
+
+fn source_confidence(source: &str) -> f32 {
+    match source {
+        "local" => 0.95,
+        "urlhaus" => 0.9,
+        "openphish" => 0.85,
+        _ => 0.8,
+    }
+}
+
+fn source_category(source: &str) -> &'static str {
+    match source {
+        "urlhaus" => "malware",
+        "openphish" => "phishing",
+        _ => "blocklist",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn intel_cache_respects_capacity_bound() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            cache_max_entries: 8,
+            ..IntelConfig::default()
+        });
+        for i in 0..100 {
+            checker
+                .check_comprehensive(&format!("domain{i}.example"), None)
+                .await;
+        }
+        checker.cache.run_pending_tasks().await;
+        assert!(checker.cache.entry_count() <= 8);
+    }
+
+    #[tokio::test]
+    async fn disabled_external_lookups_fall_back_to_local_only() {
+        // A configured key would normally send unlisted domains to GSB;
+        // with the runtime flag off no call may be attempted.
+        let checker = HardIntelChecker::new(IntelConfig {
+            gsb_api_key: "test-key".to_string(),
+            gsb_failure_threshold: 1,
+            ..IntelConfig::default()
+        });
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["evil.com".to_string()]));
+        checker.set_external_lookups(false);
+        assert!(!checker.external_lookups_enabled());
+
+        // Local lists still answer ...
+        assert!(checker.check_comprehensive("evil.com", None).await.is_some());
+        // ... and an unlisted domain returns clean without touching GSB:
+        // with a threshold of 1, any attempted (and failing) call would
+        // have opened the breaker.
+        assert!(checker
+            .check_comprehensive("unlisted.example", None)
+            .await
+            .is_none());
+        assert!(!checker.gsb_circuit_open());
+
+        checker.set_external_lookups(true);
+        assert!(checker.external_lookups_enabled());
+    }
+
+    #[tokio::test]
+    async fn local_list_exact_match() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["evil.com".to_string()]));
+        assert!(checker.check_local_lists("evil.com").await.is_some());
+        assert!(checker.check_local_lists("good.com").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn statistics_gauges_report_sizes_and_refresh_age() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker.blocklists.write().await.insert(
+            "abuse_ch".to_string(),
+            HashSet::from(["a.example".to_string(), "b.example".to_string()]),
+        );
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("openphish".to_string(), HashSet::new());
+        checker.last_refresh.write().await.insert(
+            "abuse_ch".to_string(),
+            Utc::now() - chrono::Duration::seconds(3600),
+        );
+
+        let gauges = checker.statistics_gauges().await;
+        assert!(
+            gauges.contains("garuda_intel_list_size{source=\"abuse_ch\"} 2"),
+            "{gauges}"
+        );
+        // An empty set still exports: zero is exactly the alertable state.
+        assert!(
+            gauges.contains("garuda_intel_list_size{source=\"openphish\"} 0"),
+            "{gauges}"
+        );
+        let age: i64 = gauges
+            .lines()
+            .find_map(|line| {
+                line.strip_prefix("garuda_intel_last_refresh_age_seconds{source=\"abuse_ch\"} ")
+            })
+            .expect("age gauge for the refreshed source")
+            .parse()
+            .unwrap();
+        assert!((3600..3700).contains(&age), "{age}");
+        // A source that never refreshed exports no age sample at all,
+        // rather than a fake zero that would mask staleness.
+        assert!(
+            !gauges.contains("last_refresh_age_seconds{source=\"openphish\""),
+            "{gauges}"
+        );
+    }
+
+    #[tokio::test]
+    async fn lookup_reports_the_listed_source_with_its_metadata() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("openphish".to_string(), HashSet::from(["evil.com".to_string()]));
+        let matches = checker.local_matches("evil.com").await;
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].source, "openphish");
+        assert_eq!(matches[0].category, "phishing");
+        assert_eq!(matches[0].matched, "evil.com");
+        assert!(checker.local_matches("good.com").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn listed_apex_catches_subdomain_but_not_sibling() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["evil.com".to_string()]));
+
+        let hit = checker.check_local_lists("login.evil.com").await.unwrap();
+        assert_eq!(hit.matched, "evil.com");
+        assert!(checker.check_local_lists("notevil.com").await.is_none());
+        // The public suffix itself never becomes a candidate.
+        assert!(checker.check_local_lists("example.com").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn strict_exact_mode_ignores_subdomains() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            suffix_matching: false,
+            ..IntelConfig::default()
+        });
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["evil.com".to_string()]));
+        assert!(checker.check_local_lists("login.evil.com").await.is_none());
+        assert!(checker.check_local_lists("evil.com").await.is_some());
+    }
+
+    #[tokio::test]
+    async fn blocklist_wins_when_allowlist_override_disabled() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            allowlist_overrides_blocklist: false,
+            ..IntelConfig::default()
+        });
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["cdn.com".to_string()]));
+        checker.allowlist.write().await.insert("cdn.com".to_string());
+        let hit = checker.check_local_lists("cdn.com").await.unwrap();
+        assert_eq!(hit.source, "local");
+    }
+
+    #[tokio::test]
+    async fn exception_silences_one_feed_but_not_another() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            exceptions: vec![IntelException {
+                domain: "cdn-apex.example".to_string(),
+                source: Some("urlhaus".to_string()),
+            }],
+            ..IntelConfig::default()
+        });
+        checker.blocklists.write().await.insert(
+            "urlhaus".to_string(),
+            HashSet::from(["cdn-apex.example".to_string()]),
+        );
+        // The noisy feed's hit is suppressed.
+        assert!(checker.check_local_lists("cdn-apex.example").await.is_none());
+
+        // The same entry on another feed still matches.
+        checker.blocklists.write().await.insert(
+            "openphish".to_string(),
+            HashSet::from(["cdn-apex.example".to_string()]),
+        );
+        let hit = checker.check_local_lists("cdn-apex.example").await.unwrap();
+        assert_eq!(hit.source, "openphish");
+    }
+
+    #[tokio::test]
+    async fn highest_priority_source_wins_multi_list_conflicts() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            source_priority: vec!["openphish".to_string(), "urlhaus".to_string()],
+            ..IntelConfig::default()
+        });
+        let mut blocklists = checker.blocklists.write().await;
+        blocklists.insert("urlhaus".to_string(), HashSet::from(["both.com".to_string()]));
+        blocklists.insert("openphish".to_string(), HashSet::from(["both.com".to_string()]));
+        drop(blocklists);
+
+        let hit = checker.check_local_lists("both.com").await.unwrap();
+        assert_eq!(hit.source, "openphish");
+    }
+
+    #[test]
+    fn breaker_opens_after_consecutive_failures_and_probes_after_cooldown() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(0));
+        assert!(breaker.allow());
+
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(!breaker.is_open());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        // Zero cooldown: the breaker stays open but lets a probe through.
+        assert!(breaker.allow());
+        breaker.record_failure();
+        assert!(breaker.is_open());
+
+        breaker.record_success();
+        assert!(!breaker.is_open());
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn open_breaker_blocks_until_cooldown() {
+        let breaker = CircuitBreaker::new(1, Duration::from_secs(3600));
+        breaker.record_failure();
+        assert!(breaker.is_open());
+        assert!(!breaker.allow());
+    }
+
+    #[test]
+    fn hostfile_feed_parses_sinkhole_lines() {
+        let body = "# banner\n127.0.0.1 Evil.Example\n0.0.0.0 other.example\n::1 localhost\n";
+        let set = parse_feed(&FeedFormat::Hostfile, body);
+        assert_eq!(
+            set,
+            HashSet::from(["evil.example".to_string(), "other.example".to_string()])
+        );
+    }
+
+    #[test]
+    fn plain_list_takes_hosts_from_full_urls() {
+        let body = "https://phish.example/login?next=/\nBare.Example\n# comment\n";
+        let set = parse_feed(&FeedFormat::PlainDomainList, body);
+        assert_eq!(
+            set,
+            HashSet::from(["phish.example".to_string(), "bare.example".to_string()])
+        );
+    }
+
+    #[test]
+    fn csv_feed_skips_the_header_and_reads_the_configured_column() {
+        let body = "id,domain,category\n1,\"Evil.Example\",phishing\n2,bad.example,malware\n3,,\n";
+        let set = parse_feed(&FeedFormat::Csv { domain_column: 1 }, body);
+        assert_eq!(
+            set,
+            HashSet::from(["evil.example".to_string(), "bad.example".to_string()])
+        );
+    }
+
+    #[test]
+    fn json_array_feed_follows_the_pointer() {
+        let body = r#"{"meta":{"count":2},"data":{"domains":["Evil.Example","bad.example",7]}}"#;
+        let format = FeedFormat::JsonArray {
+            pointer: "/data/domains".to_string(),
+        };
+        assert_eq!(
+            parse_feed(&format, body),
+            HashSet::from(["evil.example".to_string(), "bad.example".to_string()])
+        );
+        // A root-level array is addressed by the empty pointer; garbage
+        // bodies yield an empty set instead of an error.
+        let root = FeedFormat::JsonArray {
+            pointer: String::new(),
+        };
+        assert_eq!(
+            parse_feed(&root, r#"["a.example"]"#),
+            HashSet::from(["a.example".to_string()])
+        );
+        assert!(parse_feed(&root, "not json").is_empty());
+    }
+
+    #[test]
+    fn cidr_feed_canonicalizes_networks() {
+        let body = "203.0.113.37/24 ; SBL123\nnot-a-network\n198.51.100.0/33\n10.0.0.0/8\n";
+        let set = parse_feed(&FeedFormat::Cidr, body);
+        assert_eq!(
+            set,
+            HashSet::from(["203.0.113.0/24".to_string(), "10.0.0.0/8".to_string()])
+        );
+    }
+
+    #[test]
+    fn checksum_document_layouts_all_parse() {
+        let digest = sha256_hex(b"feed body");
+        // Bare digest, sidecar `<digest>  <filename>`, and uppercase all
+        // normalize to the same value.
+        assert_eq!(expected_sha256(&digest).as_deref(), Some(digest.as_str()));
+        assert_eq!(
+            expected_sha256(&format!("{}  hostfile.txt\n", digest.to_uppercase())).as_deref(),
+            Some(digest.as_str())
+        );
+        assert_eq!(expected_sha256("an html error page"), None);
+    }
+
+    #[tokio::test]
+    async fn tampered_feed_is_rejected_and_the_old_set_is_kept() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        let feed = FeedSourceConfig {
+            name: "urlhaus".to_string(),
+            url: String::new(),
+            format: FeedFormat::PlainDomainList,
+            checksum_url: Some(String::new()),
+        };
+        let genuine = b"phish.example\n";
+        let checksum = sha256_hex(genuine);
+
+        checker
+            .apply_feed_body(&feed, genuine, Some(&checksum))
+            .await
+            .unwrap();
+        let current = || async {
+            checker.blocklists.read().await.get("urlhaus").cloned().unwrap()
+        };
+        assert_eq!(current().await, HashSet::from(["phish.example".to_string()]));
+
+        // A body that no longer matches the published hash is rejected and
+        // the previously loaded set survives untouched.
+        let err = checker
+            .apply_feed_body(&feed, b"injected.example\n", Some(&checksum))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("checksum mismatch"));
+        assert_eq!(current().await, HashSet::from(["phish.example".to_string()]));
+
+        // An unparseable checksum document is just as fatal.
+        assert!(checker
+            .apply_feed_body(&feed, genuine, Some("<html>oops</html>"))
+            .await
+            .is_err());
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder =
+            flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[tokio::test]
+    async fn gzipped_hostfile_feed_inflates_before_parsing() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        let feed = FeedSourceConfig {
+            name: "urlhaus".to_string(),
+            url: String::new(),
+            format: FeedFormat::Hostfile,
+            checksum_url: None,
+        };
+        let fixture = b"# banner\n0.0.0.0 evil.example\n127.0.0.1 other.example\n";
+
+        // Single and double compression both come out as the same set.
+        for body in [gzip(fixture), gzip(&gzip(fixture))] {
+            checker.apply_feed_body(&feed, &body, None).await.unwrap();
+            assert_eq!(
+                checker.blocklists.read().await.get("urlhaus").cloned().unwrap(),
+                HashSet::from(["evil.example".to_string(), "other.example".to_string()])
+            );
+        }
+    }
+
+    #[test]
+    fn mislabeled_or_corrupt_gzip_parses_raw() {
+        let plain = b"0.0.0.0 evil.example\n";
+        // A plaintext body (e.g. labeled gzip by a misconfigured server)
+        // passes through untouched.
+        assert_eq!(decompress_feed(plain), plain.to_vec());
+        // The magic with garbage behind it falls back to the raw bytes,
+        // which the line parser then skips.
+        let corrupt = [0x1f, 0x8b, 0xde, 0xad, 0xbe, 0xef];
+        assert_eq!(decompress_feed(&corrupt), corrupt.to_vec());
+    }
+
+    #[tokio::test]
+    async fn failed_refreshes_widen_the_source_interval() {
+        let checker = HardIntelChecker::new(IntelConfig {
+            refresh_interval_seconds: 100,
+            refresh_max_interval_seconds: 1000,
+            ..IntelConfig::default()
+        });
+        assert_eq!(checker.current_refresh_interval("urlhaus").await, 100);
+
+        checker.record_refresh_outcome("urlhaus", false).await;
+        assert_eq!(checker.current_refresh_interval("urlhaus").await, 200);
+        checker.record_refresh_outcome("urlhaus", false).await;
+        assert_eq!(checker.current_refresh_interval("urlhaus").await, 400);
+        // The backoff saturates at the cap ...
+        for _ in 0..10 {
+            checker.record_refresh_outcome("urlhaus", false).await;
+        }
+        assert_eq!(checker.current_refresh_interval("urlhaus").await, 1000);
+        // ... other sources are unaffected, and a success resets to base.
+        assert_eq!(checker.current_refresh_interval("openphish").await, 100);
+        checker.record_refresh_outcome("urlhaus", true).await;
+        assert_eq!(checker.current_refresh_interval("urlhaus").await, 100);
+    }
+
+    #[tokio::test]
+    async fn listed_network_covers_its_ip_literals() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker.blocklists.write().await.insert(
+            "drop".to_string(),
+            HashSet::from(["203.0.113.0/24".to_string()]),
+        );
+        let hit = checker.check_local_lists("203.0.113.9").await.unwrap();
+        assert_eq!(hit.matched, "203.0.113.0/24");
+        assert!(checker.check_local_lists("203.0.114.9").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn allowlist_suppresses_match() {
+        let checker = HardIntelChecker::new(IntelConfig::default());
+        checker
+            .blocklists
+            .write()
+            .await
+            .insert("local".to_string(), HashSet::from(["cdn.com".to_string()]));
+        checker.allowlist.write().await.insert("cdn.com".to_string());
+        assert!(checker.check_local_lists("cdn.com").await.is_none());
+    }
+}
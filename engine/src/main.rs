@@ -0,0 +1,69 @@
+mod analyzer;
+mod background;
+mod bandit;
+mod config;
+mod engine;
+mod error;
+mod features;
+mod geo;
+mod gsb;
+mod intel;
+mod metrics;
+mod model;
+mod models;
+mod pipeline;
+mod preload;
+mod redis_client;
+mod rescore;
+mod routes;
+mod selftest;
+mod storage;
+mod velocity;
+
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::engine::ThreatEngine;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            tracing_subscriber::EnvFilter::try_from_default_env()
+                .unwrap_or_else(|_| "info".into()),
+        )
+        .init();
+
+    let config_path = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| "config.toml".to_string());
+    let config = Config::load(&config_path)?;
+
+    // The runtime is built by hand so `server.workers` actually applies;
+    // 0 keeps tokio's default of one worker per core.
+    let mut builder = tokio::runtime::Builder::new_multi_thread();
+    if config.server.workers > 0 {
+        builder.worker_threads(config.server.workers);
+    }
+    let effective_workers = if config.server.workers > 0 {
+        config.server.workers
+    } else {
+        std::thread::available_parallelism().map_or(1, |n| n.get())
+    };
+    let runtime = builder.enable_all().build()?;
+    tracing::info!(workers = effective_workers, "async runtime started");
+
+    runtime.block_on(async {
+        let engine = Arc::new(ThreatEngine::new(config).await?);
+        selftest::run(&engine).await?;
+        engine.intel().start_refresh_task();
+        analyzer::spawn_worker(engine.clone());
+        preload::spawn_preload(engine.clone());
+        rescore::spawn_rescore(engine.clone());
+        engine
+            .storage()
+            .start_health_probe(engine.config().clickhouse.health_probe_interval_seconds);
+
+        engine::run(engine).await
+    })?;
+    Ok(())
+}
@@ -0,0 +1,496 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::DriftConfig;
+use crate::features::{FeatureSet, FEATURE_COUNT, FEATURE_NAMES};
+use crate::models::Action;
+
+/// Process-local counters exposed in Prometheus text format at /metrics.
+#[derive(Default)]
+pub struct Metrics {
+    pub requests_total: AtomicU64,
+    pub allow_total: AtomicU64,
+    pub warn_total: AtomicU64,
+    pub block_total: AtomicU64,
+    pub cache_hits: AtomicU64,
+    pub errors_total: AtomicU64,
+    pub feedback_total: AtomicU64,
+    pub analyzer_enqueued: AtomicU64,
+    /// Analyzer enqueues by trigger (`analyzer.enqueue_policy` rule or the
+    /// uncertain band); `analyzer_enqueued` counts them all.
+    pub enqueue_uncertain: AtomicU64,
+    pub enqueue_all_blocks: AtomicU64,
+    pub enqueue_sampled: AtomicU64,
+    /// Analyzer tasks suppressed because one for the domain was in flight.
+    pub dedup_suppressed: AtomicU64,
+    /// Low-priority analyzer tasks shed because the queue sat at or above
+    /// `analyzer.queue_high_water_mark`.
+    pub analyzer_queue_full: AtomicU64,
+    pub hard_intel_hits: AtomicU64,
+    /// Decisions that fell inside the uncertainty band. Divided by
+    /// `requests_total` this gives the uncertain fraction, which drives
+    /// analyzer enqueue volume.
+    pub uncertain_total: AtomicU64,
+    /// Decisions cut short by a client-supplied `X-Deadline-Ms` budget.
+    pub deadline_partials: AtomicU64,
+    /// Cached verdicts whose action changed when the background re-scoring
+    /// pass scored them again (e.g. a remediated domain left the feeds).
+    pub rescore_flips: AtomicU64,
+}
+
+impl Metrics {
+    pub fn record_decision(&self, action: Action) {
+        self.requests_total.fetch_add(1, Ordering::Relaxed);
+        match action {
+            Action::Allow => self.allow_total.fetch_add(1, Ordering::Relaxed),
+            Action::Warn => self.warn_total.fetch_add(1, Ordering::Relaxed),
+            Action::Block => self.block_total.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let counters = [
+            ("garuda_requests_total", &self.requests_total),
+            ("garuda_decisions_allow_total", &self.allow_total),
+            ("garuda_decisions_warn_total", &self.warn_total),
+            ("garuda_decisions_block_total", &self.block_total),
+            ("garuda_cache_hits_total", &self.cache_hits),
+            ("garuda_errors_total", &self.errors_total),
+            ("garuda_feedback_total", &self.feedback_total),
+            ("garuda_analyzer_enqueued_total", &self.analyzer_enqueued),
+            ("garuda_analyzer_enqueue_uncertain_total", &self.enqueue_uncertain),
+            ("garuda_analyzer_enqueue_all_blocks_total", &self.enqueue_all_blocks),
+            ("garuda_analyzer_enqueue_sampled_total", &self.enqueue_sampled),
+            ("garuda_analyzer_dedup_suppressed_total", &self.dedup_suppressed),
+            ("garuda_analyzer_queue_full_total", &self.analyzer_queue_full),
+            ("garuda_hard_intel_hits_total", &self.hard_intel_hits),
+            ("garuda_decisions_uncertain_total", &self.uncertain_total),
+            ("garuda_deadline_partials_total", &self.deadline_partials),
+            ("garuda_rescore_flips_total", &self.rescore_flips),
+        ];
+        for (name, counter) in counters {
+            out.push_str(&format!("# TYPE {name} counter\n"));
+            out.push_str(&format!("{name} {}\n", counter.load(Ordering::Relaxed)));
+        }
+        out
+    }
+}
+
+/// Granularity of the confusion-matrix ring: counts land in per-minute
+/// buckets, so a sample ages out at most one minute late.
+const QUALITY_BUCKET_SECONDS: u64 = 60;
+
+/// Aggregated TP/FP/TN/FN over the rolling window. "Positive" means the
+/// decision predicted a threat (see `action_predicts_threat`).
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct ConfusionCounts {
+    pub true_positives: u64,
+    pub false_positives: u64,
+    pub true_negatives: u64,
+    pub false_negatives: u64,
+}
+
+impl ConfusionCounts {
+    /// Fraction of threat predictions that really were threats; `None`
+    /// until the window holds a threat prediction.
+    pub fn precision(&self) -> Option<f64> {
+        ratio(self.true_positives, self.true_positives + self.false_positives)
+    }
+
+    /// Fraction of real threats the engine flagged; `None` until the
+    /// window holds a confirmed threat.
+    pub fn recall(&self) -> Option<f64> {
+        ratio(self.true_positives, self.true_positives + self.false_negatives)
+    }
+
+    /// Fraction of benign domains flagged as threats; `None` until the
+    /// window holds a confirmed benign domain.
+    pub fn false_positive_rate(&self) -> Option<f64> {
+        ratio(self.false_positives, self.false_positives + self.true_negatives)
+    }
+}
+
+fn ratio(numerator: u64, denominator: u64) -> Option<f64> {
+    (denominator > 0).then(|| numerator as f64 / denominator as f64)
+}
+
+/// Rolling confusion matrix built from `/feedback`: each decision's
+/// predicted side scored against the reported ground truth, in a ring of
+/// per-minute buckets so samples age out of the configured window.
+pub struct ConfusionMatrix {
+    window: Duration,
+    buckets: Mutex<VecDeque<Bucket>>,
+}
+
+struct Bucket {
+    started: Instant,
+    counts: ConfusionCounts,
+}
+
+impl ConfusionMatrix {
+    pub fn new(window_seconds: u64) -> Self {
+        Self {
+            window: Duration::from_secs(window_seconds.max(QUALITY_BUCKET_SECONDS)),
+            buckets: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, predicted_threat: bool, actual_threat: bool) {
+        self.record_at(Instant::now(), predicted_threat, actual_threat);
+    }
+
+    pub fn snapshot(&self) -> ConfusionCounts {
+        self.snapshot_at(Instant::now())
+    }
+
+    fn record_at(&self, now: Instant, predicted_threat: bool, actual_threat: bool) {
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::prune(&mut buckets, now, self.window);
+        let needs_bucket = buckets.back().map_or(true, |bucket| {
+            now.duration_since(bucket.started).as_secs() >= QUALITY_BUCKET_SECONDS
+        });
+        if needs_bucket {
+            buckets.push_back(Bucket {
+                started: now,
+                counts: ConfusionCounts::default(),
+            });
+        }
+        let counts = &mut buckets.back_mut().expect("bucket just ensured").counts;
+        match (predicted_threat, actual_threat) {
+            (true, true) => counts.true_positives += 1,
+            (true, false) => counts.false_positives += 1,
+            (false, true) => counts.false_negatives += 1,
+            (false, false) => counts.true_negatives += 1,
+        }
+    }
+
+    fn snapshot_at(&self, now: Instant) -> ConfusionCounts {
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::prune(&mut buckets, now, self.window);
+        let mut total = ConfusionCounts::default();
+        for bucket in buckets.iter() {
+            total.true_positives += bucket.counts.true_positives;
+            total.false_positives += bucket.counts.false_positives;
+            total.true_negatives += bucket.counts.true_negatives;
+            total.false_negatives += bucket.counts.false_negatives;
+        }
+        total
+    }
+
+    fn prune(buckets: &mut VecDeque<Bucket>, now: Instant, window: Duration) {
+        while buckets
+            .front()
+            .is_some_and(|bucket| now.duration_since(bucket.started) > window)
+        {
+            buckets.pop_front();
+        }
+    }
+}
+
+/// Granularity of the distribution ring, matching the confusion matrix.
+const DRIFT_BUCKET_SECONDS: u64 = 60;
+
+/// Rolling summary statistics for one feature over the drift window.
+#[derive(Debug, Clone, Copy, Default, serde::Serialize)]
+pub struct FeatureStats {
+    pub count: u64,
+    pub mean: f64,
+    pub variance: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Welford accumulator for one feature in one bucket; buckets merge with
+/// the parallel-variance formula at snapshot time.
+#[derive(Clone, Copy)]
+struct Running {
+    count: u64,
+    mean: f64,
+    m2: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for Running {
+    fn default() -> Self {
+        Self {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Running {
+    fn record(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn merge(&mut self, other: &Running) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let total = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.m2 += other.m2
+            + delta * delta * (self.count as f64 * other.count as f64 / total as f64);
+        self.mean += delta * other.count as f64 / total as f64;
+        self.count = total;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn stats(&self) -> FeatureStats {
+        FeatureStats {
+            count: self.count,
+            mean: if self.count > 0 { self.mean } else { 0.0 },
+            variance: if self.count > 1 {
+                self.m2 / (self.count - 1) as f64
+            } else {
+                0.0
+            },
+            min: if self.count > 0 { self.min } else { 0.0 },
+            max: if self.count > 0 { self.max } else { 0.0 },
+        }
+    }
+}
+
+/// Rolling per-feature distribution monitor: count/mean/variance/min/max
+/// for every computed feature over a ring of per-minute buckets, compared
+/// against a captured baseline snapshot so an upstream change that shifts
+/// a feature's distribution is flagged before it silently degrades the
+/// model.
+pub struct FeatureDistributions {
+    window: Duration,
+    buckets: Mutex<VecDeque<DistributionBucket>>,
+    baseline: Mutex<Option<Vec<FeatureStats>>>,
+}
+
+struct DistributionBucket {
+    started: Instant,
+    features: Vec<Running>,
+}
+
+impl FeatureDistributions {
+    pub fn new(window_seconds: u64) -> Self {
+        Self {
+            window: Duration::from_secs(window_seconds.max(DRIFT_BUCKET_SECONDS)),
+            buckets: Mutex::new(VecDeque::new()),
+            baseline: Mutex::new(None),
+        }
+    }
+
+    pub fn record(&self, features: &FeatureSet) {
+        self.record_at(Instant::now(), features);
+    }
+
+    /// Current-window statistics, in feature schema order; features never
+    /// computed in the window report a zero count.
+    pub fn snapshot(&self) -> Vec<FeatureStats> {
+        self.snapshot_at(Instant::now())
+    }
+
+    /// Freeze the current window as the baseline future windows are
+    /// compared against, and return it.
+    pub fn capture_baseline(&self) -> Vec<FeatureStats> {
+        let snapshot = self.snapshot();
+        *self.baseline.lock().unwrap() = Some(snapshot.clone());
+        snapshot
+    }
+
+    pub fn baseline(&self) -> Option<Vec<FeatureStats>> {
+        self.baseline.lock().unwrap().clone()
+    }
+
+    /// Names of the features whose current window has drifted beyond the
+    /// configured bands; empty until a baseline has been captured.
+    pub fn drifted(&self, config: &DriftConfig) -> Vec<&'static str> {
+        let Some(baseline) = self.baseline() else {
+            return Vec::new();
+        };
+        self.snapshot()
+            .iter()
+            .zip(baseline.iter())
+            .zip(FEATURE_NAMES.iter())
+            .filter(|((current, base), _)| stats_drifted(current, base, config))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+
+    fn record_at(&self, now: Instant, features: &FeatureSet) {
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::prune(&mut buckets, now, self.window);
+        let needs_bucket = buckets.back().map_or(true, |bucket| {
+            now.duration_since(bucket.started).as_secs() >= DRIFT_BUCKET_SECONDS
+        });
+        if needs_bucket {
+            buckets.push_back(DistributionBucket {
+                started: now,
+                features: vec![Running::default(); FEATURE_COUNT],
+            });
+        }
+        let bucket = buckets.back_mut().expect("bucket just ensured");
+        for (index, value) in features.indexed() {
+            bucket.features[index].record(value as f64);
+        }
+    }
+
+    fn snapshot_at(&self, now: Instant) -> Vec<FeatureStats> {
+        let mut buckets = self.buckets.lock().unwrap();
+        Self::prune(&mut buckets, now, self.window);
+        let mut totals = vec![Running::default(); FEATURE_COUNT];
+        for bucket in buckets.iter() {
+            for (total, running) in totals.iter_mut().zip(bucket.features.iter()) {
+                total.merge(running);
+            }
+        }
+        totals.iter().map(Running::stats).collect()
+    }
+
+    fn prune(buckets: &mut VecDeque<DistributionBucket>, now: Instant, window: Duration) {
+        while buckets
+            .front()
+            .is_some_and(|bucket| now.duration_since(bucket.started) > window)
+        {
+            buckets.pop_front();
+        }
+    }
+}
+
+/// Whether `current` sits outside the configured bands around `baseline`.
+/// Either side below `min_samples` never flags: thin data is noise.
+pub(crate) fn stats_drifted(
+    current: &FeatureStats,
+    baseline: &FeatureStats,
+    config: &DriftConfig,
+) -> bool {
+    if current.count < config.min_samples || baseline.count < config.min_samples {
+        return false;
+    }
+    // A near-constant baseline still flags any real mean movement.
+    let scale = baseline.variance.sqrt().max(1e-6);
+    if (current.mean - baseline.mean).abs() > config.mean_band * scale {
+        return true;
+    }
+    let ratio = current.variance.max(1e-12) / baseline.variance.max(1e-12);
+    ratio > config.variance_band || ratio < 1.0 / config.variance_band
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn labeled_feedback_yields_the_expected_rates() {
+        let matrix = ConfusionMatrix::new(3600);
+        for _ in 0..3 {
+            matrix.record(true, true);
+        }
+        matrix.record(true, false);
+        matrix.record(false, true);
+        for _ in 0..5 {
+            matrix.record(false, false);
+        }
+        let counts = matrix.snapshot();
+        assert_eq!(counts.true_positives, 3);
+        assert_eq!(counts.false_positives, 1);
+        assert_eq!(counts.false_negatives, 1);
+        assert_eq!(counts.true_negatives, 5);
+        assert_eq!(counts.precision(), Some(0.75));
+        assert_eq!(counts.recall(), Some(0.75));
+        assert_eq!(counts.false_positive_rate(), Some(1.0 / 6.0));
+        // An empty window reports no rates rather than fake zeros.
+        let empty = ConfusionMatrix::new(3600).snapshot();
+        assert_eq!(empty.precision(), None);
+        assert_eq!(empty.recall(), None);
+        assert_eq!(empty.false_positive_rate(), None);
+    }
+
+    #[test]
+    fn samples_age_out_of_the_window() {
+        let matrix = ConfusionMatrix::new(120);
+        let start = Instant::now();
+        matrix.record_at(start, true, true);
+        matrix.record_at(start + Duration::from_secs(90), true, false);
+        // Inside the window both samples count ...
+        let mid = matrix.snapshot_at(start + Duration::from_secs(100));
+        assert_eq!(mid.true_positives, 1);
+        assert_eq!(mid.false_positives, 1);
+        // ... and once the first bucket falls out, only the second remains.
+        let late = matrix.snapshot_at(start + Duration::from_secs(150));
+        assert_eq!(late.true_positives, 0);
+        assert_eq!(late.false_positives, 1);
+    }
+
+    #[test]
+    fn shifted_distributions_are_flagged_as_drift() {
+        use crate::features::Feature;
+
+        let config = DriftConfig {
+            window_seconds: 120,
+            mean_band: 3.0,
+            variance_band: 4.0,
+            min_samples: 100,
+        };
+        let monitor = FeatureDistributions::new(config.window_seconds);
+        let sample = |entropy: f32| {
+            let mut features = FeatureSet::default();
+            features.set(Feature::Entropy, entropy);
+            features.set(Feature::DomainLength, 12.0);
+            features
+        };
+
+        let start = Instant::now();
+        for i in 0..200 {
+            monitor.record_at(start, &sample(3.0 + (i % 10) as f32 * 0.05));
+        }
+        let baseline = monitor.capture_baseline();
+        let entropy = Feature::Entropy as usize;
+        assert_eq!(baseline[entropy].count, 200);
+        assert!((baseline[entropy].mean - 3.225).abs() < 0.01);
+        assert_eq!(baseline[entropy].min, 3.0);
+        // Nothing has moved yet, and never-computed features stay silent.
+        assert!(monitor.drifted(&config).is_empty());
+
+        // Age the healthy window out, then feed entropy shifted far past
+        // the band while domain_length holds still.
+        let later = start + Duration::from_secs(300);
+        for i in 0..200 {
+            monitor.record_at(later, &sample(6.0 + (i % 10) as f32 * 0.05));
+        }
+        assert_eq!(monitor.drifted(&config), vec!["entropy"]);
+
+        // Below min_samples the same shift is treated as noise.
+        let strict = DriftConfig {
+            min_samples: 1_000,
+            ..config
+        };
+        assert!(monitor.drifted(&strict).is_empty());
+    }
+
+    #[test]
+    fn warn_mapping_is_configurable() {
+        use crate::engine::action_predicts_threat;
+        assert!(!action_predicts_threat(Action::Allow, true));
+        assert!(action_predicts_threat(Action::Block, false));
+        assert!(action_predicts_threat(Action::Warn, true));
+        assert!(!action_predicts_threat(Action::Warn, false));
+        // WARN interstitials usually stop the visit, so they default to
+        // the threat side.
+        assert!(crate::config::QualityConfig::default().warn_counts_as_threat);
+    }
+}
@@ -0,0 +1,246 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::error::AppError;
+use crate::features::FEATURE_NAMES;
+
+/// A linear "student" model distilled from the offline teacher. Weights are
+/// positional over the model's own `feature_names` schema, so a model may
+/// use any subset (or ordering) of the globally known features.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentModel {
+    pub version: String,
+    pub weights: Vec<f64>,
+    pub bias: f64,
+    pub training_samples: u64,
+    #[serde(default)]
+    pub trained_at: Option<DateTime<Utc>>,
+    /// The features this model expects, in weight order. Legacy models
+    /// without the field fall back to the full `FEATURE_NAMES` schema.
+    #[serde(default)]
+    pub feature_names: Vec<String>,
+}
+
+impl Default for StudentModel {
+    fn default() -> Self {
+        Self {
+            version: "default".to_string(),
+            weights: vec![0.0; FEATURE_NAMES.len()],
+            bias: 0.0,
+            training_samples: 0,
+            trained_at: None,
+            feature_names: FEATURE_NAMES.iter().map(|n| n.to_string()).collect(),
+        }
+    }
+}
+
+impl StudentModel {
+    pub fn load(path: &str) -> Result<Self, AppError> {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| AppError::Model(format!("cannot read model {path}: {e}")))?;
+        let mut model: StudentModel = serde_json::from_str(&data)?;
+        if model.feature_names.is_empty() {
+            model.feature_names = FEATURE_NAMES.iter().map(|n| n.to_string()).collect();
+        }
+        if model.weights.len() != model.feature_names.len() {
+            return Err(AppError::Model(format!(
+                "model {path} has {} weights for {} feature names",
+                model.weights.len(),
+                model.feature_names.len()
+            )));
+        }
+        if let Some(unknown) = model
+            .feature_names
+            .iter()
+            .find(|name| !FEATURE_NAMES.contains(&name.as_str()))
+        {
+            return Err(AppError::Model(format!(
+                "model {path} expects unknown feature \"{unknown}\""
+            )));
+        }
+        Ok(model)
+    }
+
+    /// Project a feature set onto this model's own schema; features the
+    /// extractor did not populate pad to 0.0.
+    pub fn vector_for(&self, features: &crate::features::FeatureSet) -> Vec<f64> {
+        features.project(&self.feature_names)
+    }
+
+    pub fn save(&self, path: &str) -> Result<(), AppError> {
+        let data = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, data)
+            .map_err(|e| AppError::Model(format!("cannot write model {path}: {e}")))?;
+        Ok(())
+    }
+
+    /// Threat probability for a feature vector via the logistic link.
+    /// Accumulation runs in `f64`; the result narrows to `f32`, the
+    /// crate-wide type for probabilities on the wire and in storage. This
+    /// is the sanctioned counterpart to the widening in
+    /// `FeatureSet::project` — f32 carries ~7 significant digits, well
+    /// past the model's real resolution, so nothing observable is lost.
+    pub fn predict(&self, vector: &[f64]) -> f32 {
+        let z: f64 = self
+            .weights
+            .iter()
+            .zip(vector.iter())
+            .map(|(w, x)| w * x)
+            .sum::<f64>()
+            + self.bias;
+        sigmoid(z) as f32
+    }
+
+    /// One SGD step toward `label` (1.0 = threat, 0.0 = benign).
+    pub fn update(&mut self, vector: &[f64], label: f64, learning_rate: f64) {
+        let prediction = sigmoid(
+            self.weights
+                .iter()
+                .zip(vector.iter())
+                .map(|(w, x)| w * x)
+                .sum::<f64>()
+                + self.bias,
+        );
+        let error = prediction - label;
+        for (w, x) in self.weights.iter_mut().zip(vector.iter()) {
+            *w -= learning_rate * error * x;
+        }
+        self.bias -= learning_rate * error;
+        self.training_samples += 1;
+    }
+}
+
+fn sigmoid(z: f64) -> f64 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+/// Copy-on-write wrapper around the live student model.
+///
+/// Readers grab an `Arc` snapshot under a briefly held read lock, so scoring
+/// never waits on training. Feedback samples accumulate in a side buffer;
+/// when a batch fills, the trainer clones the current model, applies the
+/// batch, and atomically swaps the fresh `Arc` in.
+pub struct OnlineTrainer {
+    model: tokio::sync::RwLock<std::sync::Arc<StudentModel>>,
+    pending: tokio::sync::Mutex<Vec<(Vec<f64>, f64)>>,
+    batch_size: usize,
+    learning_rate: f64,
+}
+
+impl OnlineTrainer {
+    pub fn new(model: StudentModel, batch_size: usize, learning_rate: f64) -> Self {
+        Self {
+            model: tokio::sync::RwLock::new(std::sync::Arc::new(model)),
+            pending: tokio::sync::Mutex::new(Vec::new()),
+            batch_size: batch_size.max(1),
+            learning_rate,
+        }
+    }
+
+    /// Snapshot of the current model.
+    pub async fn current(&self) -> std::sync::Arc<StudentModel> {
+        self.model.read().await.clone()
+    }
+
+    /// Buffer one labeled sample. When the batch fills, retrain on a copy and
+    /// swap it in, returning the new model so the caller can persist it.
+    pub async fn record(
+        &self,
+        vector: Vec<f64>,
+        label: f64,
+    ) -> Option<std::sync::Arc<StudentModel>> {
+        // The pending lock is held across the retrain so concurrent batches
+        // serialize against each other; readers only touch the model lock,
+        // which is taken just for the pointer swap.
+        let mut pending = self.pending.lock().await;
+        pending.push((vector, label));
+        if pending.len() < self.batch_size {
+            return None;
+        }
+        let batch = std::mem::take(&mut *pending);
+
+        let mut updated = (*self.current().await).clone();
+        for (vector, label) in batch {
+            updated.update(&vector, label, self.learning_rate);
+        }
+        let updated = std::sync::Arc::new(updated);
+        *self.model.write().await = updated.clone();
+        Some(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_model_predicts_half() {
+        let model = StudentModel::default();
+        let vector = vec![0.0; FEATURE_NAMES.len()];
+        assert!((model.predict(&vector) - 0.5).abs() < 1e-6);
+    }
+
+    #[tokio::test]
+    async fn concurrent_scoring_and_feedback_do_not_deadlock() {
+        let trainer = std::sync::Arc::new(OnlineTrainer::new(StudentModel::default(), 1, 0.01));
+        let mut handles = Vec::new();
+        for _ in 0..8 {
+            let trainer = trainer.clone();
+            handles.push(tokio::spawn(async move {
+                for _ in 0..25 {
+                    let model = trainer.current().await;
+                    let _ = model.predict(&vec![0.5; FEATURE_NAMES.len()]);
+                    trainer
+                        .record(vec![0.5; FEATURE_NAMES.len()], 1.0)
+                        .await;
+                }
+            }));
+        }
+        for handle in handles {
+            handle.await.unwrap();
+        }
+        // Every recorded sample landed in a swapped model exactly once.
+        assert_eq!(trainer.current().await.training_samples, 200);
+    }
+
+    #[test]
+    fn subset_model_vectorizes_by_its_own_schema() {
+        let model = StudentModel {
+            version: "v1".to_string(),
+            weights: vec![1.0, 2.0],
+            bias: 0.0,
+            training_samples: 10,
+            trained_at: None,
+            feature_names: vec!["entropy".to_string(), "dga_score".to_string()],
+        };
+        let mut features = crate::features::FeatureSet::default();
+        features.set(crate::features::Feature::Entropy, 3.0);
+        features.set(crate::features::Feature::HomoglyphScore, 1.0);
+        // Own ordering, missing features padded with 0.0, extras ignored.
+        assert_eq!(model.vector_for(&features), vec![3.0, 0.0]);
+    }
+
+    #[test]
+    fn load_rejects_unknown_feature_names() {
+        let path = std::env::temp_dir().join("garuda-model-unknown-feature.json");
+        std::fs::write(
+            &path,
+            r#"{"version":"v1","weights":[0.1],"bias":0.0,"training_samples":1,"feature_names":["not_a_feature"]}"#,
+        )
+        .unwrap();
+        assert!(StudentModel::load(path.to_str().unwrap()).is_err());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn update_moves_prediction_toward_label() {
+        let mut model = StudentModel::default();
+        let vector = vec![1.0; FEATURE_NAMES.len()];
+        let before = model.predict(&vector);
+        for _ in 0..100 {
+            model.update(&vector, 1.0, 0.1);
+        }
+        assert!(model.predict(&vector) > before);
+        assert_eq!(model.training_samples, 100);
+    }
+}
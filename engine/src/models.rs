@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// The action the engine recommends for a domain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE")]
+pub enum Action {
+    Allow,
+    Warn,
+    Block,
+}
+
+impl Action {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Action::Allow => "ALLOW",
+            Action::Warn => "WARN",
+            Action::Block => "BLOCK",
+        }
+    }
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScoreRequest {
+    pub domain: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Client-supplied correlation id; when present it becomes the
+    /// `decision_id`, so feedback can reference the caller's own id.
+    #[serde(default)]
+    pub request_id: Option<String>,
+    /// Free-form caller context (source IP, referrer, ...).
+    #[serde(default)]
+    pub context: HashMap<String, String>,
+    /// Red-team overlay applied to the extracted features before model
+    /// inference. Admin-gated; names must come from `FEATURE_NAMES`.
+    #[serde(default)]
+    pub feature_overrides: HashMap<String, f32>,
+    /// Threat hunting: enqueue the analyzer task regardless of the
+    /// decision, so deep-analysis artifacts get collected even for a
+    /// benign-looking ALLOW. Admin-gated; the response is unaffected.
+    #[serde(default)]
+    pub force_analyze: bool,
+    /// Include the extracted feature map in the response, for clients
+    /// running their own secondary models. Off by default to keep
+    /// responses small.
+    #[serde(default)]
+    pub return_features: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScoreResponse {
+    pub decision_id: String,
+    pub domain: String,
+    pub action: Action,
+    pub probability: f32,
+    /// How sure the engine is of this decision (signal agreement), as
+    /// distinct from how threatening it judged the domain (`probability`);
+    /// see `decision_confidence` for the formula.
+    #[serde(default)]
+    pub confidence: f32,
+    pub reasons: Vec<String>,
+    pub cached: bool,
+    pub processing_time_ms: f64,
+    /// True when the caller's `feature_overrides` shaped this decision.
+    #[serde(default)]
+    pub features_overridden: bool,
+    /// Version of the student model that scored this decision; empty when
+    /// a hard-intel short circuit decided before the model ran.
+    #[serde(default)]
+    pub model_version: String,
+    /// Version of the engine's feature schema (`FEATURE_NAMES`).
+    #[serde(default)]
+    pub feature_schema_version: u32,
+    /// The extracted feature map, only when the request asked for it via
+    /// `return_features`; derived entirely from inputs the caller already
+    /// holds (domain, URL, request context), so nothing new leaks.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub features: Option<HashMap<String, f32>>,
+}
+
+/// Investigation query: find recent decisions similar to a seed domain.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SimilarRequest {
+    pub domain: String,
+    /// Lookback window in hours; defaults to 24, capped server-side.
+    #[serde(default)]
+    pub hours: Option<u32>,
+    /// Maximum results; defaults to 20, capped server-side.
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// One /similar result: a recent decision ranked against the seed.
+#[derive(Debug, Clone, Serialize)]
+pub struct SimilarDomain {
+    pub domain: String,
+    pub action: String,
+    pub probability: f32,
+    /// Cosine similarity of the stored feature vectors.
+    pub cosine_similarity: f64,
+    /// Normalized Levenshtein similarity of the domain names.
+    pub lexical_similarity: f64,
+    /// The ranking score: the better of the two similarities.
+    pub similarity: f64,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedbackRequest {
+    pub decision_id: String,
+    /// Ground truth: was the domain actually a threat?
+    pub actual_threat: bool,
+    /// Reward signal in [-1, 1] for the bandit.
+    #[serde(default)]
+    pub reward: f32,
+    #[serde(default)]
+    pub source: Option<String>,
+}
+
+/// A decision as persisted to ClickHouse.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Decision {
+    pub decision_id: String,
+    pub domain: String,
+    pub url: Option<String>,
+    pub action: Action,
+    pub probability: f32,
+    pub confidence: f32,
+    pub model_version: String,
+    pub features: HashMap<String, f32>,
+    pub reasons: Vec<String>,
+    pub processing_time_ms: f64,
+    /// Sampling rate this decision was logged under, so aggregate counts
+    /// can be scaled back up (1 / sample_rate).
+    pub sample_rate: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+/// Per-decision state stashed in Redis so /feedback can update the bandit
+/// and the model with the context that produced the decision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionContext {
+    pub decision_id: String,
+    pub domain: String,
+    pub action: Action,
+    pub probability: f32,
+    /// Empty for confident decisions: the bandit context is only built
+    /// when the bandit is consulted, and feedback only needs it then.
+    pub context_vector: Vec<f64>,
+    pub feature_vector: Vec<f64>,
+    /// The bandit arm that was pulled, when the bandit was consulted.
+    pub arm: Option<usize>,
+}
+
+/// A deep-analysis task pushed onto the Redis queue for the analyzer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyzerTask {
+    pub task_id: String,
+    pub decision_id: String,
+    pub domain: String,
+    pub url: Option<String>,
+    pub probability: f32,
+    pub features: HashMap<String, f32>,
+    pub created_at: DateTime<Utc>,
+    /// Failed analyzer attempts so far; bounded by `analyzer.max_retries`
+    /// before the task is dead-lettered.
+    #[serde(default)]
+    pub attempts: u32,
+}
+
+/// A task that exhausted its analyzer retries — or a queue payload that
+/// could not be decoded at all — parked on the dead-letter list for
+/// inspection or manual requeue.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeadLetter {
+    /// The decoded task; `None` for malformed payloads, which carry the
+    /// raw bytes in `raw` instead and cannot be requeued.
+    pub task: Option<AnalyzerTask>,
+    /// Lossy UTF-8 rendering of an undecodable payload.
+    #[serde(default)]
+    pub raw: Option<String>,
+    pub last_error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DecisionStats {
+    pub total: u64,
+    pub allow_count: u64,
+    pub warn_count: u64,
+    pub block_count: u64,
+    pub challenge_count: u64,
+    /// Decisions whose action the aggregation did not recognize.
+    pub other_count: u64,
+    pub avg_probability: f64,
+    pub avg_processing_time_ms: f64,
+    pub p95_processing_time_ms: f64,
+}
@@ -0,0 +1,690 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+
+use crate::bandit::ARMS;
+use crate::config::UntrainedPolicy;
+use crate::engine::{
+    action_for_deep_verdict, action_from_thresholds, combine_scores,
+    deterministic_uncertain_action, hard_intel_action, hard_intel_block_probability,
+    is_uncertain, model_is_untrained, private_ip_override, uncertain_enqueue_enabled,
+    ThreatEngine, BANDIT_REASON, NEUTRAL_DOMAIN_PRIOR,
+};
+use crate::error::AppError;
+use crate::features::{generate_reasons, Feature, FeatureSet};
+use crate::intel::HardIntelMatch;
+use crate::models::{Action, ScoreRequest};
+
+/// The built-in stages, in the order that reproduces the historical
+/// monolithic `score()` behavior.
+pub const DEFAULT_STAGES: &[&str] = &[
+    "hard_intel",
+    "internal_host",
+    "features",
+    "model",
+    "uncertainty",
+    "intel_floor",
+];
+
+/// Mutable state threaded through the pipeline for one scoring request.
+/// Stages read what earlier stages produced and write their own results.
+pub struct ScoringContext {
+    pub decision_id: String,
+    /// Normalized (trimmed, lowercased, no trailing dot) domain.
+    pub domain: String,
+    pub features: FeatureSet,
+    pub probability: f32,
+    pub action: Action,
+    pub reasons: Vec<String>,
+    /// Feature vector in the student model's own schema.
+    pub feature_vector: Vec<f64>,
+    pub context_vector: Vec<f64>,
+    /// The bandit arm pulled, when the bandit was consulted.
+    pub arm: Option<usize>,
+    pub untrained: bool,
+    /// Version of the model that produced `probability`; empty until the
+    /// model stage runs.
+    pub model_version: String,
+    /// A hard-intel match below the block-confidence gate, carried forward
+    /// so the floor stage can keep the decision out of ALLOW.
+    pub intel_floor: Option<(HardIntelMatch, String)>,
+    /// Whether a stage already queued the domain for deep analysis, so a
+    /// forced analysis does not enqueue it twice.
+    pub analyzer_enqueued: bool,
+}
+
+impl ScoringContext {
+    pub fn new(request: &ScoreRequest) -> Self {
+        Self {
+            decision_id: request
+                .request_id
+                .clone()
+                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+            domain: crate::features::normalize_domain(&request.domain),
+            features: FeatureSet::default(),
+            probability: 0.0,
+            action: Action::Allow,
+            reasons: Vec::new(),
+            feature_vector: Vec::new(),
+            context_vector: Vec::new(),
+            arm: None,
+            untrained: false,
+            model_version: String::new(),
+            intel_floor: None,
+            analyzer_enqueued: false,
+        }
+    }
+}
+
+/// Whether the threat-hunting flag still needs an analyzer enqueue after
+/// the pipeline ran: the uncertainty stage may have queued the domain
+/// already, and a forced duplicate would waste an analyzer slot.
+pub(crate) fn forced_analysis_needed(request: &ScoreRequest, ctx: &ScoringContext) -> bool {
+    request.force_analyze && !ctx.analyzer_enqueued
+}
+
+/// Whether the rest of the pipeline still runs after a stage.
+pub enum StageOutcome {
+    Continue,
+    /// Stop here; the context already holds the final decision.
+    ShortCircuit,
+}
+
+/// One step of the scoring pipeline. Stages are stateless; per-request
+/// state lives in the [`ScoringContext`].
+#[async_trait]
+pub trait ScoreStage: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError>;
+}
+
+/// Instantiate the configured stage list, failing on unknown names so a
+/// typo surfaces at startup rather than as a silently shorter pipeline.
+pub fn build(names: &[String]) -> Result<Vec<Box<dyn ScoreStage>>, AppError> {
+    names.iter().map(|name| stage_for(name)).collect()
+}
+
+fn stage_for(name: &str) -> Result<Box<dyn ScoreStage>, AppError> {
+    match name {
+        "hard_intel" => Ok(Box::new(HardIntelStage)),
+        "internal_host" => Ok(Box::new(InternalHostStage)),
+        "features" => Ok(Box::new(FeatureStage)),
+        "model" => Ok(Box::new(ModelStage)),
+        "uncertainty" => Ok(Box::new(UncertaintyStage)),
+        "intel_floor" => Ok(Box::new(IntelFloorStage)),
+        other => Err(config::ConfigError::Message(format!(
+            "unknown scoring stage \"{other}\""
+        ))
+        .into()),
+    }
+}
+
+/// Hard intelligence gate: tenant-pinned domains resolve outright, a
+/// confident listing blocks immediately, and a match below the confidence
+/// gate is carried as a floor for later stages.
+struct HardIntelStage;
+
+#[async_trait]
+impl ScoreStage for HardIntelStage {
+    fn name(&self) -> &'static str {
+        "hard_intel"
+    }
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        // Tenant-pinned domains are the most specific signal there is.
+        if let Some(tenant) = engine.tenant_for(request) {
+            if tenant.blocklist.contains(&ctx.domain) {
+                ctx.action = Action::Block;
+                ctx.probability = 1.0;
+                ctx.reasons = vec!["Listed on the tenant blocklist".to_string()];
+                return Ok(StageOutcome::ShortCircuit);
+            }
+            if tenant.allowlist.contains(&ctx.domain) {
+                ctx.action = Action::Allow;
+                ctx.reasons = vec!["Listed on the tenant allowlist".to_string()];
+                return Ok(StageOutcome::ShortCircuit);
+            }
+        }
+        let Some(intel_match) = engine
+            .intel()
+            .check_comprehensive(&ctx.domain, request.url.as_deref())
+            .await
+        else {
+            return Ok(StageOutcome::Continue);
+        };
+        engine
+            .metrics
+            .hard_intel_hits
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let (intel_action, reason) = hard_intel_action(
+            &intel_match,
+            engine.config().thresholds.hard_intel_min_block_confidence,
+        );
+        if intel_action == Action::Block {
+            ctx.action = Action::Block;
+            ctx.probability = hard_intel_block_probability(
+                intel_match.confidence,
+                engine.config().thresholds.hard_intel_block_probability,
+            );
+            ctx.reasons = vec![reason];
+            return Ok(StageOutcome::ShortCircuit);
+        }
+        ctx.intel_floor = Some((intel_match, reason));
+        Ok(StageOutcome::Continue)
+    }
+}
+
+/// Intranet hostnames (no recognized public suffix, or a configured
+/// internal suffix) resolve to `thresholds.internal_host_action` instead
+/// of running the public-web model, whose features assume PSL-parseable
+/// names. Runs after hard intel so a listed internal name still blocks.
+struct InternalHostStage;
+
+/// Reason attached to internal-host decisions.
+pub const INTERNAL_HOST_REASON: &str = "Internal hostname (no public suffix)";
+
+#[async_trait]
+impl ScoreStage for InternalHostStage {
+    fn name(&self) -> &'static str {
+        "internal_host"
+    }
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        _request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        if !crate::features::is_internal_host(
+            &ctx.domain,
+            &engine.config().features.internal_suffixes,
+        ) {
+            return Ok(StageOutcome::Continue);
+        }
+        ctx.features.set(Feature::IsInternalHost, 1.0);
+        ctx.action = engine.config().thresholds.internal_host_action;
+        ctx.reasons = vec![INTERNAL_HOST_REASON.to_string()];
+        Ok(StageOutcome::ShortCircuit)
+    }
+}
+
+/// Feature extraction, plus the synthesized below-gate intel feature.
+struct FeatureStage;
+
+#[async_trait]
+impl ScoreStage for FeatureStage {
+    fn name(&self) -> &'static str {
+        "features"
+    }
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        ctx.features = engine
+            .extractor()
+            .extract(&ctx.domain, request.url.as_deref())
+            .await?;
+        if let Some((intel_match, _)) = &ctx.intel_floor {
+            ctx.features.set(Feature::HardIntelHit, intel_match.confidence);
+        }
+        // Cluster velocity is request-scoped state, so it is synthesized
+        // here rather than inside the (cached) extractor.
+        let count = engine.velocity().observe(&ctx.domain);
+        let threshold = engine.config().features.velocity_campaign_threshold.max(1);
+        ctx.features.set(Feature::DomainVelocity, count as f32 / threshold as f32);
+        // An allowlisted domain is a vetted brand property, not a squat;
+        // drop the combosquatting signal before the model sees it.
+        if ctx.features.value(Feature::Combosquatting) > 0.0
+            && engine.intel().is_allowlisted(&ctx.domain).await
+        {
+            ctx.features.set(Feature::Combosquatting, 0.0);
+        }
+        // Learned per-domain reputation, an EWMA of feedback labels kept in
+        // Redis. Neutral when the domain has no history — and when Redis is
+        // unreachable, because reputation must not take scoring down with it.
+        let prior = match engine.redis().get_domain_prior(&ctx.domain).await {
+            Ok(Some(prior)) => prior,
+            Ok(None) => NEUTRAL_DOMAIN_PRIOR,
+            Err(e) => {
+                tracing::warn!(error = %e, "domain prior lookup failed; scoring as neutral");
+                NEUTRAL_DOMAIN_PRIOR
+            }
+        };
+        ctx.features.set(Feature::DomainPrior, prior);
+        // Recognized request-context keys (source_ip, referrer, user_agent,
+        // client_country) describe the caller, not the domain, so they are
+        // request-scoped as well.
+        ctx.features
+            .extend(&engine.extractor().context_features(&request.context, &ctx.domain));
+        // Red-team overlay: validated, admin-gated overrides win over
+        // everything extracted or synthesized above. Names were validated
+        // against the schema at the route, so `set_named` cannot miss.
+        for (name, value) in &request.feature_overrides {
+            ctx.features.set_named(name, *value);
+        }
+        Ok(StageOutcome::Continue)
+    }
+}
+
+/// Student model inference over the model's own feature schema, the blended
+/// probability, the bandit context, and the threshold-derived action.
+struct ModelStage;
+
+#[async_trait]
+impl ScoreStage for ModelStage {
+    fn name(&self) -> &'static str {
+        "model"
+    }
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        let tenant = engine.tenant_for(request);
+        let (vector, model_probability, untrained, version) = {
+            let shared = engine.model().current().await;
+            let model = match tenant.and_then(|t| t.model.as_ref()) {
+                Some(model) => model,
+                None => &*shared,
+            };
+            let vector = model.vector_for(&ctx.features);
+            let probability = model.predict(&vector);
+            (
+                vector,
+                probability,
+                model_is_untrained(model),
+                model.version.clone(),
+            )
+        };
+        ctx.feature_vector = vector;
+        ctx.untrained = untrained;
+        ctx.model_version = version;
+        ctx.probability = combine_scores(model_probability, &ctx.features);
+        // The bandit context is deliberately not built here: only the
+        // uncertainty-stage paths that actually reach `select_arm` pay for
+        // the projection and the per-arm matrix work, so the common
+        // confident path skips both.
+        let thresholds = tenant
+            .map(|t| &t.thresholds)
+            .unwrap_or(&engine.config().thresholds);
+        ctx.action = action_from_thresholds(ctx.probability, thresholds);
+        ctx.reasons = generate_reasons(&ctx.features, &engine.config().features.reason_thresholds);
+        if ctx.features.value(Feature::Combosquatting) > 0.0 {
+            if let Some((brand, _)) =
+                crate::features::combosquatting_brand(&ctx.domain, engine.extractor().brands())
+            {
+                ctx.reasons.push(format!(
+                    "combosquatting: '{brand}' token combined with a high-risk TLD"
+                ));
+            }
+        }
+        if ctx.features.value(Feature::BrandImpersonation) > 0.0 {
+            if let Some(brand) = engine.extractor().impersonated_brand(&ctx.domain) {
+                ctx.reasons.push(format!(
+                    "brand_impersonation: '{brand}' spelled with homoglyphs"
+                ));
+            }
+        }
+        // A public name answering into private or bogon space is enforced
+        // directly rather than left to the model weights; the reason was
+        // already generated from the feature above.
+        if ctx.features.value(Feature::ResolvesToPrivate) > 0.0 {
+            if let Some(action) = private_ip_override(ctx.action, thresholds.private_ip_action) {
+                ctx.action = action;
+            }
+        }
+        Ok(StageOutcome::Continue)
+    }
+}
+
+/// Fallback policy for an untrained model, and bandit / deep-verdict
+/// resolution in the uncertain band.
+struct UncertaintyStage;
+
+#[async_trait]
+impl ScoreStage for UncertaintyStage {
+    fn name(&self) -> &'static str {
+        "uncertainty"
+    }
+
+    async fn run(
+        &self,
+        engine: &ThreatEngine,
+        request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        // An untrained default model scores everything near 0.5; rather than
+        // mass-WARN, fall back to the configured conservative policy.
+        if ctx.untrained {
+            match engine.config().model.untrained_policy {
+                UntrainedPolicy::AllowAll => {
+                    ctx.action = Action::Allow;
+                    ctx.reasons
+                        .push("Untrained model: defaulting to ALLOW".to_string());
+                }
+                UntrainedPolicy::BanditOnly => {
+                    if engine.config().bandit.enabled {
+                        ctx.context_vector = engine.build_context_vector(&ctx.features);
+                        let selected = {
+                            engine.bandit().lock().await.select_arm(
+                                &ctx.context_vector,
+                                &engine.config().bandit.tie_break,
+                                crate::engine::decision_seed(&ctx.decision_id),
+                            )
+                        };
+                        ctx.arm = Some(selected);
+                        ctx.action = ARMS[selected];
+                        ctx.reasons
+                            .push("Untrained model: action selected by bandit".to_string());
+                    } else {
+                        ctx.action = Action::Allow;
+                        ctx.reasons.push(
+                            "Untrained model: bandit disabled, defaulting to ALLOW".to_string(),
+                        );
+                    }
+                }
+            }
+            return Ok(StageOutcome::Continue);
+        }
+        let thresholds = engine
+            .tenant_for(request)
+            .map(|t| &t.thresholds)
+            .unwrap_or(&engine.config().thresholds);
+        if !is_uncertain(ctx.probability, thresholds) {
+            return Ok(StageOutcome::Continue);
+        }
+        engine
+            .metrics
+            .uncertain_total
+            .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        // A cached deep-analysis verdict wins; otherwise let the bandit pick
+        // the action and queue the domain for deep analysis.
+        let deep_action = engine
+            .redis()
+            .get_deep_verdict(&ctx.domain)
+            .await
+            .ok()
+            .flatten()
+            .and_then(|verdict| {
+                action_for_deep_verdict(&verdict).map(|action| (action, verdict))
+            });
+        match deep_action {
+            Some((verdict_action, verdict)) => {
+                ctx.action = verdict_action;
+                ctx.reasons.push(format!("deep_verdict_applied ({verdict})"));
+            }
+            None if !engine.config().bandit.enabled => {
+                // With the bandit switched off the band resolves by a fixed
+                // rule instead of exploration; no arm is pulled, so feedback
+                // for this decision never reaches bandit state either.
+                ctx.action = deterministic_uncertain_action(ctx.probability, thresholds);
+                ctx.reasons.push(
+                    "Uncertain band resolved deterministically (bandit disabled)".to_string(),
+                );
+                if uncertain_enqueue_enabled(&engine.config().analyzer.enqueue_policy) {
+                    engine
+                        .metrics
+                        .enqueue_uncertain
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    engine.enqueue_analyzer_task(
+                        &ctx.decision_id,
+                        &ctx.domain,
+                        request,
+                        ctx.probability,
+                        &ctx.features,
+                        ctx.action == Action::Block,
+                    );
+                    ctx.analyzer_enqueued = true;
+                }
+            }
+            None => {
+                // A cold bandit's bounds are all identity prior; keep the
+                // threshold action until every arm has warmed up, but still
+                // queue the domain for deep analysis.
+                let selected = {
+                    let bandit = engine.bandit().lock().await;
+                    if bandit.is_warmed(engine.config().bandit.min_arm_pulls) {
+                        ctx.context_vector = engine.build_context_vector(&ctx.features);
+                        Some(bandit.select_arm(
+                            &ctx.context_vector,
+                            &engine.config().bandit.tie_break,
+                            crate::engine::decision_seed(&ctx.decision_id),
+                        ))
+                    } else {
+                        None
+                    }
+                };
+                match selected {
+                    Some(selected) => {
+                        ctx.arm = Some(selected);
+                        ctx.action = ARMS[selected];
+                        ctx.reasons.push(BANDIT_REASON.to_string());
+                    }
+                    None => ctx.reasons.push(
+                        "Uncertain band resolved by thresholds (bandit warming up)".to_string(),
+                    ),
+                }
+                if uncertain_enqueue_enabled(&engine.config().analyzer.enqueue_policy) {
+                    engine
+                        .metrics
+                        .enqueue_uncertain
+                        .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    engine.enqueue_analyzer_task(
+                        &ctx.decision_id,
+                        &ctx.domain,
+                        request,
+                        ctx.probability,
+                        &ctx.features,
+                        ctx.action == Action::Block,
+                    );
+                    ctx.analyzer_enqueued = true;
+                }
+            }
+        }
+        Ok(StageOutcome::Continue)
+    }
+}
+
+/// A below-gate intel match never lets the decision fall to ALLOW.
+struct IntelFloorStage;
+
+#[async_trait]
+impl ScoreStage for IntelFloorStage {
+    fn name(&self) -> &'static str {
+        "intel_floor"
+    }
+
+    async fn run(
+        &self,
+        _engine: &ThreatEngine,
+        _request: &ScoreRequest,
+        ctx: &mut ScoringContext,
+    ) -> Result<StageOutcome, AppError> {
+        if let Some((_, reason)) = ctx.intel_floor.take() {
+            if ctx.action == Action::Allow {
+                ctx.action = Action::Warn;
+            }
+            ctx.reasons.push(reason);
+        }
+        Ok(StageOutcome::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_stage_list_builds_in_order() {
+        let names: Vec<String> = DEFAULT_STAGES.iter().map(|s| s.to_string()).collect();
+        let stages = build(&names).unwrap();
+        let built: Vec<&str> = stages.iter().map(|s| s.name()).collect();
+        assert_eq!(built, DEFAULT_STAGES);
+    }
+
+    #[test]
+    fn config_default_matches_builtin_pipeline() {
+        assert_eq!(crate::config::PipelineConfig::default().stages, DEFAULT_STAGES);
+    }
+
+    #[test]
+    fn unknown_stage_is_rejected_at_build_time() {
+        assert!(build(&["telepathy".to_string()]).is_err());
+    }
+
+    #[test]
+    fn context_normalizes_the_domain_like_the_old_score_path() {
+        let request = ScoreRequest {
+            domain: "  ExAmPle.COM.  ".to_string(),
+            url: None,
+            request_id: None,
+            context: HashMap::new(),
+            feature_overrides: HashMap::new(),
+            force_analyze: false,
+            return_features: false,
+        };
+        let ctx = ScoringContext::new(&request);
+        assert_eq!(ctx.domain, "example.com");
+        assert_eq!(ctx.action, Action::Allow);
+        assert!(ctx.intel_floor.is_none());
+    }
+
+    #[test]
+    fn forced_analysis_enqueues_even_for_an_allow_decision() {
+        let mut request = ScoreRequest {
+            domain: "benign-looking.example".to_string(),
+            url: None,
+            request_id: None,
+            context: HashMap::new(),
+            feature_overrides: HashMap::new(),
+            force_analyze: true,
+            return_features: false,
+        };
+        // A clean ALLOW never reaches the uncertainty stage's enqueue, so
+        // the forced flag is what puts it on the queue.
+        let mut ctx = ScoringContext::new(&request);
+        assert_eq!(ctx.action, Action::Allow);
+        assert!(forced_analysis_needed(&request, &ctx));
+        // An uncertain-band decision already queued the domain; forcing it
+        // again must not produce a duplicate task.
+        ctx.analyzer_enqueued = true;
+        assert!(!forced_analysis_needed(&request, &ctx));
+        // Without the flag nothing changes for ordinary requests.
+        request.force_analyze = false;
+        ctx.analyzer_enqueued = false;
+        assert!(!forced_analysis_needed(&request, &ctx));
+    }
+
+    #[test]
+    fn a_clear_allow_never_consults_the_bandit() {
+        // Outside the uncertain band the uncertainty stage returns before
+        // any bandit work, and the model stage no longer builds the
+        // context vector eagerly — so a confident decision carries no
+        // context and no arm, and its feedback can never reach bandit
+        // state (`handle_feedback` only updates when an arm was pulled).
+        let thresholds = crate::config::ThresholdConfig::default();
+        assert!(!is_uncertain(0.05, &thresholds));
+        assert!(!is_uncertain(0.98, &thresholds));
+        let request = ScoreRequest {
+            domain: "plainly-benign.example".to_string(),
+            url: None,
+            request_id: None,
+            context: HashMap::new(),
+            feature_overrides: HashMap::new(),
+            force_analyze: false,
+            return_features: false,
+        };
+        let ctx = ScoringContext::new(&request);
+        assert!(ctx.context_vector.is_empty());
+        assert!(ctx.arm.is_none());
+    }
+
+    /// The crate is a binary, so this latency comparison lives here rather
+    /// than in a criterion bench. It is deliberately coarse: consulting the
+    /// bandit inverts one design matrix per arm, which dwarfs the dot
+    /// product the confident path pays, and the assertion only cares about
+    /// the ordering, not the magnitude.
+    #[test]
+    fn confident_path_is_cheaper_than_consulting_the_bandit() {
+        let config = crate::config::BanditConfig::default();
+        let model = crate::model::StudentModel {
+            version: "bench".to_string(),
+            weights: vec![0.5; config.context_features.len()],
+            bias: -1.0,
+            training_samples: 1_000,
+            trained_at: None,
+            feature_names: config.context_features.clone(),
+        };
+        let mut features = FeatureSet::default();
+        for name in &config.context_features {
+            features.set_named(name, 0.3);
+        }
+        let context = features.project(&config.context_features);
+        let mut bandit =
+            crate::bandit::LinUCBBandit::new(config.alpha, config.context_features.len());
+        for arm in 0..ARMS.len() {
+            for _ in 0..config.min_arm_pulls {
+                bandit.update(arm, &context, 0.1);
+            }
+        }
+        let thresholds = crate::config::ThresholdConfig::default();
+
+        let iterations = 500u64;
+        let started = std::time::Instant::now();
+        for _ in 0..iterations {
+            let vector = model.vector_for(&features);
+            let probability = model.predict(&vector);
+            std::hint::black_box(action_from_thresholds(probability, &thresholds));
+        }
+        let confident = started.elapsed();
+
+        let started = std::time::Instant::now();
+        for seed in 0..iterations {
+            let vector = model.vector_for(&features);
+            let probability = model.predict(&vector);
+            std::hint::black_box(action_from_thresholds(probability, &thresholds));
+            // What every decision used to pay before the band check
+            // guarded it.
+            let context = features.project(&config.context_features);
+            std::hint::black_box(bandit.select_arm(&context, &config.tie_break, seed));
+        }
+        let consulted = started.elapsed();
+
+        assert!(
+            consulted > confident,
+            "bandit path {consulted:?} should cost more than the confident path {confident:?}"
+        );
+    }
+
+    #[test]
+    fn client_request_id_becomes_the_decision_id() {
+        let request = ScoreRequest {
+            domain: "example.com".to_string(),
+            url: None,
+            request_id: Some("proxy-tx-8812".to_string()),
+            context: HashMap::new(),
+            feature_overrides: HashMap::new(),
+            force_analyze: false,
+            return_features: false,
+        };
+        // Feedback is correlated through the decision context stored under
+        // this id, so the client can reference its own transaction id.
+        assert_eq!(ScoringContext::new(&request).decision_id, "proxy-tx-8812");
+    }
+}
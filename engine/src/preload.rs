@@ -0,0 +1,115 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::task::JoinSet;
+use tracing::{info, warn};
+
+use crate::engine::ThreatEngine;
+
+/// Progress of the startup cache preload, reported as /health/ready
+/// metadata.
+#[derive(Debug, Default)]
+pub struct PreloadProgress {
+    total: AtomicUsize,
+    done: AtomicUsize,
+}
+
+impl PreloadProgress {
+    pub fn total(&self) -> usize {
+        self.total.load(Ordering::Relaxed)
+    }
+
+    pub fn done(&self) -> usize {
+        self.done.load(Ordering::Relaxed)
+    }
+}
+
+/// Warm the feature and intel caches from the configured top-domains list,
+/// so the most common legitimate domains do not pay the cold-cache latency
+/// on their first score. Runs detached so startup is never blocked on the
+/// list, and bounded to `preload_concurrency` in-flight domains so a long
+/// list does not open with a DNS thundering herd.
+pub fn spawn_preload(engine: Arc<ThreatEngine>) {
+    let Some(path) = engine.config().features.preload_path.clone() else {
+        return;
+    };
+    tokio::spawn(async move {
+        let raw = match tokio::fs::read_to_string(&path).await {
+            Ok(raw) => raw,
+            Err(e) => {
+                warn!(path, error = %e, "cannot read preload list; caches start cold");
+                return;
+            }
+        };
+        let domains = parse_domain_list(&raw, engine.config().features.preload_limit);
+        engine.preload().total.store(domains.len(), Ordering::Relaxed);
+        preload_domains(&engine, domains).await;
+        info!(done = engine.preload().done(), "cache preload finished");
+    });
+}
+
+/// The bounded preload loop, separate from the spawn so it can be driven
+/// to completion directly.
+async fn preload_domains(engine: &Arc<ThreatEngine>, domains: Vec<String>) {
+    let concurrency = engine.config().features.preload_concurrency.max(1);
+    let mut pool = JoinSet::new();
+    for domain in domains {
+        while pool.len() >= concurrency {
+            pool.join_next().await;
+        }
+        let engine = engine.clone();
+        pool.spawn(async move {
+            if let Err(e) = engine.extractor().extract(&domain, None).await {
+                warn!(domain, error = %e, "preload extraction failed");
+            }
+            engine.intel().check_comprehensive(&domain, None).await;
+            engine.preload().done.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    while pool.join_next().await.is_some() {}
+}
+
+/// Parse a top-domains list: one domain per line, blank lines and `#`
+/// comments skipped, normalized like scored domains, truncated to `limit`.
+fn parse_domain_list(raw: &str, limit: usize) -> Vec<String> {
+    raw.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(crate::features::normalize_domain)
+        .take(limit)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeatureConfig;
+    use crate::features::FeatureExtractor;
+
+    #[test]
+    fn list_parsing_skips_comments_and_caps_at_the_limit() {
+        let raw = "# top domains\nExample.COM\n\n  example.org.  \nexample.net\n";
+        assert_eq!(
+            parse_domain_list(raw, 2),
+            vec!["example.com".to_string(), "example.org".to_string()]
+        );
+        assert!(parse_domain_list("# only comments\n", 10).is_empty());
+    }
+
+    #[tokio::test]
+    async fn preloaded_domain_is_a_cache_hit_on_first_score() {
+        let extractor = FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        });
+        // The warm pass, exactly as the preloader performs it.
+        for domain in parse_domain_list("example.com\nexample.org\n", 10) {
+            extractor.extract(&domain, None).await.unwrap();
+        }
+        assert_eq!(extractor.cache_hits(), 0);
+
+        // First organic score of a preloaded domain never re-extracts.
+        extractor.extract("example.com", None).await.unwrap();
+        assert_eq!(extractor.cache_hits(), 1);
+    }
+}
@@ -0,0 +1,555 @@
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+
+use crate::config::RedisConfig;
+use crate::error::AppError;
+use crate::models::{AnalyzerTask, DeadLetter, DecisionContext};
+
+const CONTEXT_TTL_SECONDS: u64 = 86_400;
+
+/// Shared Redis handle: response cache, per-decision context, and the
+/// analyzer task queue.
+#[derive(Clone)]
+pub struct RedisClient {
+    conn: ConnectionManager,
+    queue_name: String,
+    /// Tasks that exhausted their retries; `<queue>:dead`, so
+    /// `garuda:tasks:dead` for the default queue.
+    dead_queue_name: String,
+    /// Queue payloads that failed to decode, dead-lettered on dequeue.
+    malformed_payloads: std::sync::Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl RedisClient {
+    pub async fn new(config: &RedisConfig) -> Result<Self, AppError> {
+        let client = redis::Client::open(config.url.as_str())?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self {
+            conn,
+            dead_queue_name: format!("{}:dead", config.queue_name),
+            queue_name: config.queue_name.clone(),
+            malformed_payloads: std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0)),
+        })
+    }
+
+    /// Payloads dead-lettered because they failed to decode. Exposed for
+    /// the `garuda_queue_malformed_total` counter.
+    pub fn malformed_payloads(&self) -> u64 {
+        self.malformed_payloads
+            .load(std::sync::atomic::Ordering::Relaxed)
+    }
+
+    pub async fn ping(&self) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        redis::cmd("PING").query_async::<_, String>(&mut conn).await?;
+        Ok(())
+    }
+
+    pub async fn get_cached_response(&self, key: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        Ok(conn.get(key).await?)
+    }
+
+    pub async fn cache_response(
+        &self,
+        key: &str,
+        value: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        conn.set_ex(key, value, ttl_seconds as usize).await?;
+        Ok(())
+    }
+
+    pub async fn store_decision_context(&self, context: &DecisionContext) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:ctx:{}", context.decision_id);
+        let payload = serde_json::to_string(context)?;
+        conn.set_ex(key, payload, CONTEXT_TTL_SECONDS as usize).await?;
+        Ok(())
+    }
+
+    pub async fn get_decision_context(
+        &self,
+        decision_id: &str,
+    ) -> Result<Option<DecisionContext>, AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:ctx:{decision_id}");
+        let payload: Option<String> = conn.get(key).await?;
+        match payload {
+            Some(p) => Ok(Some(serde_json::from_str(&p)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Push a deep-analysis task onto the queue unless one for the same
+    /// domain is already in flight. A `SET NX EX` guard keyed by domain
+    /// holds the in-flight task id for the dedup window; suppressed callers
+    /// get that id back so their decision references the existing task.
+    /// Returns the in-flight task id and whether this call enqueued it.
+    pub async fn enqueue_analyzer_task(
+        &self,
+        task: &AnalyzerTask,
+        dedup_ttl_seconds: u64,
+    ) -> Result<(String, bool), AppError> {
+        let mut conn = self.conn.clone();
+        let guard = format!("garuda:analyzer:inflight:{}", task.domain);
+        let acquired: bool = redis::cmd("SET")
+            .arg(&guard)
+            .arg(&task.task_id)
+            .arg("NX")
+            .arg("EX")
+            .arg(dedup_ttl_seconds)
+            .query_async(&mut conn)
+            .await?;
+        if !acquired {
+            let inflight: Option<String> = conn.get(&guard).await?;
+            // The guard can expire between SET and GET; treat that as ours.
+            if let Some(inflight) = inflight {
+                return Ok((inflight, false));
+            }
+        }
+        let payload = encode_task(task)?;
+        conn.lpush(&self.queue_name, payload).await?;
+        Ok((task.task_id.clone(), true))
+    }
+
+    /// Blocking pop with a short timeout; `None` means the queue was empty.
+    /// A payload that fails to decode is parked on the dead-letter list
+    /// rather than propagated, so one corrupt message cannot stall the
+    /// consumer.
+    pub async fn dequeue_analyzer_task(&self) -> Result<Option<AnalyzerTask>, AppError> {
+        let mut conn = self.conn.clone();
+        let result: Option<(String, Vec<u8>)> = conn.brpop(&self.queue_name, 5).await?;
+        let Some((_, payload)) = result else {
+            return Ok(None);
+        };
+        match decode_task(&payload) {
+            Ok(task) => Ok(Some(task)),
+            Err(e) => {
+                self.malformed_payloads
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                tracing::warn!(error = %e, "malformed queue payload dead-lettered");
+                let entry = serde_json::to_string(&DeadLetter {
+                    task: None,
+                    raw: Some(String::from_utf8_lossy(&payload).into_owned()),
+                    last_error: e.to_string(),
+                    failed_at: chrono::Utc::now(),
+                })?;
+                conn.lpush(&self.dead_queue_name, entry).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Record the analyzer's verdict for a domain so later uncertain
+    /// decisions can reuse it instead of re-enqueuing deep analysis.
+    pub async fn store_deep_verdict(
+        &self,
+        domain: &str,
+        verdict: &str,
+        ttl_seconds: u64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:verdict:{domain}");
+        conn.set_ex(key, verdict, ttl_seconds as usize).await?;
+        Ok(())
+    }
+
+    pub async fn get_deep_verdict(&self, domain: &str) -> Result<Option<String>, AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:verdict:{domain}");
+        Ok(conn.get(key).await?)
+    }
+
+    /// The learned reputation prior for a domain, if it has feedback
+    /// history that has not yet expired.
+    pub async fn get_domain_prior(&self, domain: &str) -> Result<Option<f32>, AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:prior:{domain}");
+        let value: Option<String> = conn.get(key).await?;
+        Ok(value.and_then(|v| v.parse().ok()))
+    }
+
+    /// Store a domain's updated reputation prior. Every write refreshes
+    /// the TTL, so decay is by wholesale expiry back to neutral rather
+    /// than incremental arithmetic.
+    pub async fn set_domain_prior(
+        &self,
+        domain: &str,
+        prior: f32,
+        ttl_seconds: u64,
+    ) -> Result<(), AppError> {
+        let mut conn = self.conn.clone();
+        let key = format!("garuda:prior:{domain}");
+        conn.set_ex(key, prior.to_string(), ttl_seconds as usize).await?;
+        Ok(())
+    }
+
+    /// A sample of cached score responses for the background re-scorer:
+    /// (key, payload, remaining TTL in seconds). Keys match the
+    /// `garuda:response:*` entries the score route writes; SCAN walks
+    /// cursor-by-cursor so large caches are never blocked on one command,
+    /// and the walk stops as soon as the sample is full.
+    pub async fn sample_cached_responses(
+        &self,
+        count: usize,
+    ) -> Result<Vec<(String, String, i64)>, AppError> {
+        let mut conn = self.conn.clone();
+        let mut keys: Vec<String> = Vec::new();
+        let mut cursor: u64 = 0;
+        loop {
+            let (next, batch): (u64, Vec<String>) = redis::cmd("SCAN")
+                .arg(cursor)
+                .arg("MATCH")
+                .arg("garuda:response:*")
+                .arg("COUNT")
+                .arg(count.clamp(10, 1000))
+                .query_async(&mut conn)
+                .await?;
+            keys.extend(batch);
+            cursor = next;
+            if cursor == 0 || keys.len() >= count {
+                break;
+            }
+        }
+        keys.truncate(count);
+        let mut out = Vec::with_capacity(keys.len());
+        for key in keys {
+            // The entry can expire between SCAN and GET; just skip it.
+            let payload: Option<String> = conn.get(&key).await?;
+            let ttl: i64 = conn.ttl(&key).await?;
+            if let Some(payload) = payload {
+                out.push((key, payload, ttl));
+            }
+        }
+        Ok(out)
+    }
+
+    pub async fn get_queue_length(&self) -> Result<u64, AppError> {
+        let mut conn = self.conn.clone();
+        Ok(conn.llen(&self.queue_name).await?)
+    }
+
+    /// Re-enqueue a failed task with its retry counter bumped, or park it
+    /// on the dead-letter list with the error once `max_retries` attempts
+    /// have failed. Returns true when the task was dead-lettered.
+    pub async fn retry_or_dead_letter(
+        &self,
+        task: &AnalyzerTask,
+        error: &str,
+        max_retries: u32,
+    ) -> Result<bool, AppError> {
+        let mut task = task.clone();
+        task.attempts += 1;
+        let mut conn = self.conn.clone();
+        if task.attempts >= max_retries {
+            // Dead letters are stored as JSON: they exist to be read by
+            // operators and the inspection endpoint, not for throughput.
+            let entry = serde_json::to_string(&DeadLetter {
+                task: Some(task),
+                raw: None,
+                last_error: error.to_string(),
+                failed_at: chrono::Utc::now(),
+            })?;
+            conn.lpush(&self.dead_queue_name, entry).await?;
+            return Ok(true);
+        }
+        let payload = encode_task(&task)?;
+        conn.lpush(&self.queue_name, payload).await?;
+        Ok(false)
+    }
+
+    pub async fn get_dead_letter_length(&self) -> Result<u64, AppError> {
+        let mut conn = self.conn.clone();
+        Ok(conn.llen(&self.dead_queue_name).await?)
+    }
+
+    /// The most recent dead letters, newest first.
+    pub async fn peek_dead_letters(&self, count: usize) -> Result<Vec<DeadLetter>, AppError> {
+        let mut conn = self.conn.clone();
+        let entries: Vec<String> = conn
+            .lrange(&self.dead_queue_name, 0, count.saturating_sub(1) as isize)
+            .await?;
+        entries
+            .iter()
+            .map(|entry| Ok(serde_json::from_str(entry)?))
+            .collect()
+    }
+
+    /// Move every dead letter back onto the task queue with a fresh retry
+    /// budget. Malformed payloads have nothing to requeue and stay parked.
+    /// Returns how many tasks were requeued.
+    pub async fn requeue_dead_letters(&self) -> Result<u64, AppError> {
+        let mut conn = self.conn.clone();
+        let mut moved = 0;
+        let mut unrequeueable = Vec::new();
+        loop {
+            let entry: Option<String> = conn.rpop(&self.dead_queue_name, None).await?;
+            let Some(entry) = entry else { break };
+            let dead: DeadLetter = serde_json::from_str(&entry)?;
+            match dead.task {
+                Some(mut task) => {
+                    task.attempts = 0;
+                    conn.lpush(&self.queue_name, encode_task(&task)?).await?;
+                    moved += 1;
+                }
+                None => unrequeueable.push(entry),
+            }
+        }
+        for entry in unrequeueable {
+            conn.lpush(&self.dead_queue_name, entry).await?;
+        }
+        Ok(moved)
+    }
+}
+
+/// Version bytes prefixing binary queue payloads. Legacy JSON payloads
+/// start with `{` (0x7b), so the formats are disjoint on the first byte
+/// and mixed queues decode correctly during migration. v2 appended the
+/// retry counter to the task layout.
+const QUEUE_PAYLOAD_V1: u8 = 1;
+const QUEUE_PAYLOAD_V2: u8 = 2;
+
+/// The v1 task layout, before the retry counter; kept so in-flight v1
+/// payloads still decode during an upgrade.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct AnalyzerTaskV1 {
+    task_id: String,
+    decision_id: String,
+    domain: String,
+    url: Option<String>,
+    probability: f32,
+    features: std::collections::HashMap<String, f32>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Encode a task for the queue: one version byte, then a varint bincode
+/// body. Dropping JSON field names and quoting makes the payload markedly
+/// smaller for a full feature map, which matters at high enqueue rates.
+fn encode_task(task: &AnalyzerTask) -> Result<Vec<u8>, AppError> {
+    use bincode::Options;
+    let body = bincode::options()
+        .serialize(task)
+        .map_err(|e| AppError::Internal(format!("task encoding failed: {e}")))?;
+    let mut payload = Vec::with_capacity(body.len() + 1);
+    payload.push(QUEUE_PAYLOAD_V2);
+    payload.extend(body);
+    Ok(payload)
+}
+
+/// Decode a queue payload in any of the binary versions or the legacy
+/// JSON format.
+fn decode_task(payload: &[u8]) -> Result<AnalyzerTask, AppError> {
+    use bincode::Options;
+    match payload.first() {
+        Some(&QUEUE_PAYLOAD_V2) => bincode::options()
+            .deserialize(&payload[1..])
+            .map_err(|e| AppError::Internal(format!("task decoding failed: {e}"))),
+        Some(&QUEUE_PAYLOAD_V1) => {
+            let v1: AnalyzerTaskV1 = bincode::options()
+                .deserialize(&payload[1..])
+                .map_err(|e| AppError::Internal(format!("task decoding failed: {e}")))?;
+            Ok(AnalyzerTask {
+                task_id: v1.task_id,
+                decision_id: v1.decision_id,
+                domain: v1.domain,
+                url: v1.url,
+                probability: v1.probability,
+                features: v1.features,
+                created_at: v1.created_at,
+                attempts: 0,
+            })
+        }
+        Some(b'{') => Ok(serde_json::from_slice(payload)?),
+        other => Err(AppError::Internal(format!(
+            "unknown queue payload version {other:?}"
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn sample_task() -> AnalyzerTask {
+        AnalyzerTask {
+            task_id: "t1".to_string(),
+            decision_id: "d1".to_string(),
+            domain: "example.com".to_string(),
+            url: Some("https://example.com/login".to_string()),
+            probability: 0.55,
+            features: (0..40)
+                .map(|i| (format!("feature_{i}"), i as f32 / 40.0))
+                .collect(),
+            created_at: Utc::now(),
+            attempts: 0,
+        }
+    }
+
+    #[test]
+    fn binary_payload_round_trips() {
+        let task = sample_task();
+        let payload = encode_task(&task).unwrap();
+        let decoded = decode_task(&payload).unwrap();
+        assert_eq!(decoded.task_id, task.task_id);
+        assert_eq!(decoded.domain, task.domain);
+        assert_eq!(decoded.features.len(), task.features.len());
+    }
+
+    #[test]
+    fn legacy_json_payloads_still_decode() {
+        let task = sample_task();
+        let payload = serde_json::to_vec(&task).unwrap();
+        let decoded = decode_task(&payload).unwrap();
+        assert_eq!(decoded.decision_id, task.decision_id);
+    }
+
+    #[test]
+    fn binary_payload_is_smaller_than_json() {
+        let task = sample_task();
+        let binary = encode_task(&task).unwrap().len();
+        let json = serde_json::to_vec(&task).unwrap().len();
+        assert!(binary < json, "binary {binary} bytes vs json {json} bytes");
+    }
+
+    #[test]
+    fn v1_binary_payloads_decode_with_zero_attempts() {
+        use bincode::Options;
+        let task = sample_task();
+        let v1 = AnalyzerTaskV1 {
+            task_id: task.task_id.clone(),
+            decision_id: task.decision_id.clone(),
+            domain: task.domain.clone(),
+            url: task.url.clone(),
+            probability: task.probability,
+            features: task.features.clone(),
+            created_at: task.created_at,
+        };
+        let mut payload = vec![QUEUE_PAYLOAD_V1];
+        payload.extend(bincode::options().serialize(&v1).unwrap());
+        let decoded = decode_task(&payload).unwrap();
+        assert_eq!(decoded.task_id, task.task_id);
+        assert_eq!(decoded.attempts, 0);
+    }
+
+    #[test]
+    fn garbage_payloads_are_rejected() {
+        assert!(decode_task(&[0xff, 0x00]).is_err());
+        assert!(decode_task(&[]).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Redis at redis://127.0.0.1/"]
+    async fn duplicate_domain_enqueues_once_per_window() {
+        let domain = format!("{}.example", uuid::Uuid::new_v4());
+        let client = RedisClient::new(&RedisConfig {
+            url: "redis://127.0.0.1/".into(),
+            queue_name: format!("garuda:test:{}", uuid::Uuid::new_v4()),
+        })
+        .await
+        .unwrap();
+
+        let task = |id: &str| AnalyzerTask {
+            task_id: id.to_string(),
+            decision_id: id.to_string(),
+            domain: domain.clone(),
+            url: None,
+            probability: 0.5,
+            features: Default::default(),
+            created_at: Utc::now(),
+            attempts: 0,
+        };
+
+        let (first, enqueued) = client.enqueue_analyzer_task(&task("t1"), 60).await.unwrap();
+        assert!(enqueued);
+
+        let (inflight, enqueued) = client.enqueue_analyzer_task(&task("t2"), 60).await.unwrap();
+        assert!(!enqueued);
+        assert_eq!(inflight, first);
+        assert_eq!(client.get_queue_length().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Redis at redis://127.0.0.1/"]
+    async fn repeated_failures_park_the_task_in_the_dlq() {
+        let client = RedisClient::new(&RedisConfig {
+            url: "redis://127.0.0.1/".into(),
+            queue_name: format!("garuda:test:{}", uuid::Uuid::new_v4()),
+        })
+        .await
+        .unwrap();
+        let mut task = sample_task();
+
+        // Two failures re-enqueue with a bumped counter ...
+        for attempt in 1..3 {
+            assert!(!client
+                .retry_or_dead_letter(&task, "fetch timed out", 3)
+                .await
+                .unwrap());
+            task = client
+                .dequeue_analyzer_task()
+                .await
+                .unwrap()
+                .expect("requeued task");
+            assert_eq!(task.attempts, attempt);
+        }
+        // ... and the third parks the task on the dead-letter list.
+        assert!(client
+            .retry_or_dead_letter(&task, "fetch timed out", 3)
+            .await
+            .unwrap());
+        assert_eq!(client.get_queue_length().await.unwrap(), 0);
+        assert_eq!(client.get_dead_letter_length().await.unwrap(), 1);
+        let dead = client.peek_dead_letters(10).await.unwrap();
+        assert_eq!(dead[0].task.as_ref().unwrap().task_id, task.task_id);
+        assert_eq!(dead[0].last_error, "fetch timed out");
+
+        // Requeuing resets the retry budget.
+        assert_eq!(client.requeue_dead_letters().await.unwrap(), 1);
+        let requeued = client.dequeue_analyzer_task().await.unwrap().unwrap();
+        assert_eq!(requeued.attempts, 0);
+        assert_eq!(client.get_dead_letter_length().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    #[ignore = "requires a local Redis at redis://127.0.0.1/"]
+    async fn corrupt_payload_is_skipped_and_a_valid_task_still_processes() {
+        let client = RedisClient::new(&RedisConfig {
+            url: "redis://127.0.0.1/".into(),
+            queue_name: format!("garuda:test:{}", uuid::Uuid::new_v4()),
+        })
+        .await
+        .unwrap();
+
+        // Garbage first, then a valid task behind it.
+        let mut conn = client.conn.clone();
+        let _: () = conn
+            .lpush(&client.queue_name, vec![0xff_u8, 0x01])
+            .await
+            .unwrap();
+        client
+            .enqueue_analyzer_task(&sample_task(), 60)
+            .await
+            .unwrap();
+
+        // The corrupt payload is consumed without erroring ...
+        assert!(client.dequeue_analyzer_task().await.unwrap().is_none());
+        assert_eq!(client.malformed_payloads(), 1);
+        // ... and the valid task behind it still comes through.
+        let task = client
+            .dequeue_analyzer_task()
+            .await
+            .unwrap()
+            .expect("valid task survives the corrupt one");
+        assert_eq!(task.task_id, sample_task().task_id);
+
+        // The garbage is parked, inspectable, and never requeued.
+        assert_eq!(client.get_dead_letter_length().await.unwrap(), 1);
+        let dead = client.peek_dead_letters(1).await.unwrap();
+        assert!(dead[0].task.is_none());
+        assert_eq!(client.requeue_dead_letters().await.unwrap(), 0);
+        assert_eq!(client.get_dead_letter_length().await.unwrap(), 1);
+    }
+}
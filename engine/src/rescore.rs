@@ -0,0 +1,135 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tracing::{info, warn};
+
+use crate::engine::ThreatEngine;
+use crate::error::AppError;
+use crate::models::{Action, ScoreRequest, ScoreResponse};
+
+/// Periodically re-score a sample of cached BLOCK verdicts and refresh the
+/// cache when the verdict changed — typically because the domain was
+/// remediated and dropped off a blocklist feed. Without this, a long-TTL
+/// BLOCK entry keeps enforcing until it expires under a client request.
+/// Disabled by default; see `[rescore]` in the configuration.
+pub fn spawn_rescore(engine: Arc<ThreatEngine>) {
+    let config = engine.config().rescore.clone();
+    if !config.enabled {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut ticker =
+            tokio::time::interval(Duration::from_secs(config.interval_seconds.max(1)));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = rescore_pass(&engine).await {
+                warn!(error = %e, "re-scoring pass failed");
+            }
+        }
+    });
+}
+
+/// One pass: sample cached responses, re-score the stale BLOCK entries
+/// through the full pipeline, and write back any verdict that flipped.
+/// Re-scores are ordinary decisions — they are logged and counted like
+/// organic traffic.
+async fn rescore_pass(engine: &ThreatEngine) -> Result<(), AppError> {
+    let config = &engine.config().rescore;
+    let ttl_block = engine.config().server.cache_ttl_block;
+    let entries = engine.redis().sample_cached_responses(config.sample_size).await?;
+    for (key, payload, remaining_ttl) in entries {
+        // Undecodable entries (a schema from a newer build, say) age out on
+        // their own TTL; nothing useful to re-score.
+        let Ok(cached) = serde_json::from_str::<ScoreResponse>(&payload) else {
+            continue;
+        };
+        if !due_for_rescore(cached.action, remaining_ttl, ttl_block, config.min_age_seconds) {
+            continue;
+        }
+        let request = ScoreRequest {
+            domain: cached.domain.clone(),
+            url: None,
+            request_id: None,
+            context: Default::default(),
+            feature_overrides: Default::default(),
+            force_analyze: false,
+            return_features: false,
+        };
+        let fresh = engine.score(&request).await?;
+        if fresh.action == cached.action {
+            continue;
+        }
+        engine.metrics.rescore_flips.fetch_add(1, Ordering::Relaxed);
+        info!(
+            domain = %cached.domain,
+            from = %cached.action,
+            to = %fresh.action,
+            "cached verdict flipped on re-score"
+        );
+        let ttl = crate::routes::cache_ttl_for(&engine.config().server, &fresh);
+        if let Ok(payload) = serde_json::to_string(&fresh) {
+            let _ = engine.redis().cache_response(&key, &payload, ttl).await;
+        }
+    }
+    Ok(())
+}
+
+/// Whether a cached entry is due: only BLOCK verdicts, and only once older
+/// than `min_age` seconds. Age is inferred from the remaining TTL — BLOCK
+/// responses are always cached for `ttl_block` — so no timestamp has to
+/// ride along in the cache entry.
+fn due_for_rescore(action: Action, remaining_ttl: i64, ttl_block: u64, min_age: u64) -> bool {
+    if action != Action::Block {
+        return false;
+    }
+    let age = ttl_block.saturating_sub(remaining_ttl.max(0) as u64);
+    age >= min_age
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::IntelConfig;
+    use crate::intel::HardIntelChecker;
+
+    #[test]
+    fn only_sufficiently_old_block_verdicts_are_due() {
+        // A 3600s BLOCK TTL with 3000s remaining is 600s old.
+        assert!(due_for_rescore(Action::Block, 3000, 3600, 600));
+        assert!(!due_for_rescore(Action::Block, 3100, 3600, 600));
+        // ALLOW and WARN entries re-evaluate via their own short TTLs.
+        assert!(!due_for_rescore(Action::Allow, 0, 3600, 600));
+        assert!(!due_for_rescore(Action::Warn, 0, 3600, 600));
+        // A key without an expiry (TTL -1) counts as maximally old.
+        assert!(due_for_rescore(Action::Block, -1, 3600, 600));
+    }
+
+    #[tokio::test]
+    async fn intel_delisting_flips_a_cached_block_on_rescore() {
+        let path = std::env::temp_dir().join(format!("garuda-rescore-{}.txt", uuid::Uuid::new_v4()));
+        std::fs::write(&path, "remediated.example\n").unwrap();
+        let checker = HardIntelChecker::new(IntelConfig {
+            blocklist_path: Some(path.to_string_lossy().into_owned()),
+            ..IntelConfig::default()
+        });
+        checker.load_local_lists().await.unwrap();
+
+        // While listed, the domain scores a hard-intel BLOCK — the verdict
+        // the cache would be holding.
+        let hit = checker.check_local_lists("remediated.example").await.unwrap();
+        let (cached_action, _) = crate::engine::hard_intel_action(
+            &hit,
+            crate::config::ThresholdConfig::default().hard_intel_min_block_confidence,
+        );
+        assert_eq!(cached_action, Action::Block);
+
+        // Once the list drops the entry, the same lookup comes back clean,
+        // so a re-score lands on the model path instead of the hard block.
+        std::fs::write(&path, "# remediated, delisted upstream\n").unwrap();
+        checker.load_local_lists().await.unwrap();
+        assert!(checker.check_local_lists("remediated.example").await.is_none());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,1030 @@
+use std::sync::Arc;
+use std::sync::atomic::Ordering;
+
+use axum::extract::State;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use tower_http::compression::CompressionLayer;
+
+use crate::engine::ThreatEngine;
+use crate::error::AppError;
+use crate::models::{FeedbackRequest, ScoreRequest, ScoreResponse, SimilarDomain, SimilarRequest};
+use crate::storage::RecentDecisionRow;
+
+pub fn router(engine: Arc<ThreatEngine>) -> Router {
+    let mut router = Router::new();
+    if engine.config().server.debug_endpoints {
+        router = router.route("/debug/score", post(debug_score));
+    }
+    let compression = engine.config().server.compression;
+    let router = router
+        .route("/score", post(score))
+        .route("/bandit/stats", get(bandit_stats))
+        .route("/bandit/reset", post(bandit_reset))
+        .route("/bandit/export", get(bandit_export))
+        .route("/bandit/import", post(bandit_import))
+        .route("/score/batch", post(score_batch))
+        .route("/analyzer/dead_letters", get(dead_letters))
+        .route("/analyzer/dead_letters/requeue", post(dead_letters_requeue))
+        .route("/feedback", post(feedback))
+        .route("/similar", post(similar))
+        .route("/decisions", get(decisions))
+        .route("/intel/lookup/{domain}", get(intel_lookup))
+        .route("/health", get(health))
+        .route("/health/ready", get(ready))
+        .route("/model/info", get(model_info))
+        .route("/stats", get(stats))
+        .route("/features/stats", get(feature_stats))
+        .route("/features/stats/baseline", post(feature_stats_baseline))
+        .route("/quality", get(quality))
+        .route("/config", get(config_view))
+        .route("/admin/flags", get(admin_flags).post(admin_flags_update))
+        .route("/metrics", get(metrics))
+        .with_state(engine);
+    if compression {
+        // The layer's default predicate honors Accept-Encoding and already
+        // exempts `text/event-stream`, so streaming responses stay intact;
+        // content types are preserved for the Prometheus exposition.
+        router.layer(CompressionLayer::new())
+    } else {
+        router
+    }
+}
+
+async fn score(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<ScoreRequest>,
+) -> Result<axum::response::Response, AppError> {
+    use axum::response::IntoResponse;
+
+    validate_score_request(&request)?;
+    require_admin_for_overrides(&engine, &headers, &request)?;
+    let deadline = client_deadline(&headers, engine.config().server.max_deadline_ms)?;
+
+    // Key on the canonical form so equivalent spellings (case, trailing
+    // dot, whitespace) share one cache entry and one verdict.
+    let cache_key = format!(
+        "garuda:response:{}",
+        crate::features::normalize_domain(&request.domain)
+    );
+    // A cached response carries someone else's decision_id; a caller that
+    // supplied its own correlation id needs a decision recorded under it.
+    // Overridden requests bypass the cache entirely: the stored response
+    // was scored on organic features.
+    // A forced analysis also has to skip the cache: a cached response
+    // would short-circuit scoring and nothing would reach the analyzer.
+    // Feature-returning requests do too: cached responses never carry the
+    // feature map.
+    if request.request_id.is_none()
+        && request.feature_overrides.is_empty()
+        && !request.force_analyze
+        && !request.return_features
+    {
+        if let Ok(Some(cached)) = engine.redis().get_cached_response(&cache_key).await {
+            if let Ok(mut response) = serde_json::from_str::<ScoreResponse>(&cached) {
+                response.cached = true;
+                engine.metrics.cache_hits.fetch_add(1, Ordering::Relaxed);
+                return Ok(Json(response).into_response());
+            }
+        }
+    }
+
+    let (response, timings) = engine.score_timed(&request, deadline).await?;
+
+    // Likewise never publish an overridden decision for organic callers,
+    // nor a decision a client deadline cut short, nor a feature-laden
+    // response that would hand the map to callers who never asked.
+    if request.feature_overrides.is_empty()
+        && !request.return_features
+        && !response
+            .reasons
+            .iter()
+            .any(|reason| reason == crate::engine::DEADLINE_REASON)
+    {
+        let ttl = cache_ttl_for(&engine.config().server, &response);
+        if let Ok(payload) = serde_json::to_string(&response) {
+            let _ = engine.redis().cache_response(&cache_key, &payload, ttl).await;
+        }
+    }
+
+    let mut http = Json(response).into_response();
+    if engine.config().server.server_timing {
+        if let Ok(value) = axum::http::HeaderValue::from_str(&server_timing_value(&timings)) {
+            http.headers_mut().insert("server-timing", value);
+        }
+    }
+    Ok(http)
+}
+
+/// Shared request validation for the scoring endpoints: a non-empty,
+/// syntactically possible domain and, when the caller supplies its own
+/// correlation id, a sane one (1-128 characters of `[A-Za-z0-9._-]`) so it
+/// is safe in Redis keys and logs. Syntactically impossible hostnames
+/// (underscores, spaces, empty labels) get a specific 400 instead of
+/// being scored on garbage features; names that are merely nonexistent
+/// still score.
+fn validate_score_request(request: &ScoreRequest) -> Result<(), AppError> {
+    if request.domain.trim().is_empty() {
+        return Err(AppError::InvalidRequest("domain must not be empty".into()));
+    }
+    let normalized = crate::features::normalize_domain(&request.domain);
+    if let Some(violation) = crate::features::domain_syntax_error(&normalized) {
+        return Err(AppError::InvalidRequest(format!(
+            "invalid_domain_syntax: {violation}"
+        )));
+    }
+    if let Some(id) = &request.request_id {
+        let valid = (1..=128).contains(&id.len())
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+        if !valid {
+            return Err(AppError::InvalidRequest(
+                "request_id must be 1-128 characters of [A-Za-z0-9._-]".into(),
+            ));
+        }
+    }
+    for name in request.feature_overrides.keys() {
+        if !crate::features::FEATURE_NAMES.contains(&name.as_str()) {
+            return Err(AppError::InvalidRequest(format!(
+                "unknown feature override \"{name}\""
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// The per-request latency budget from `X-Deadline-Ms`, clamped to the
+/// configured maximum. A zero (or already spent) budget is still honored
+/// with a fast hard-intel-only answer; only an unparseable value is an
+/// error.
+fn client_deadline(
+    headers: &axum::http::HeaderMap,
+    max_deadline_ms: u64,
+) -> Result<Option<std::time::Duration>, AppError> {
+    let Some(value) = headers.get("x-deadline-ms") else {
+        return Ok(None);
+    };
+    let ms: u64 = value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .ok_or_else(|| {
+            AppError::InvalidRequest("x-deadline-ms must be a non-negative integer".into())
+        })?;
+    Ok(Some(std::time::Duration::from_millis(ms.min(max_deadline_ms))))
+}
+
+/// Feature overrides reshape decisions at will and forced analysis spends
+/// analyzer capacity, so both are for red-team / threat-hunting use only:
+/// admin token required, and implicitly disabled wherever no admin token
+/// is configured.
+fn require_admin_for_overrides(
+    engine: &ThreatEngine,
+    headers: &axum::http::HeaderMap,
+    request: &ScoreRequest,
+) -> Result<(), AppError> {
+    if request.feature_overrides.is_empty() && !request.force_analyze {
+        return Ok(());
+    }
+    require_admin(engine, headers)
+}
+
+/// Format per-stage wall times as a `Server-Timing` header value, e.g.
+/// `hard_intel;dur=0.21, features;dur=0.93, model;dur=0.10`.
+fn server_timing_value(timings: &[crate::features::StageTiming]) -> String {
+    timings
+        .iter()
+        .map(|t| format!("{};dur={:.2}", t.stage, t.duration_ms))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// TTL for a cached response, chosen by the decision's action. Decisions the
+/// bandit resolved in the uncertain band always get the short WARN TTL so
+/// they are re-evaluated promptly.
+pub(crate) fn cache_ttl_for(server: &crate::config::ServerConfig, response: &ScoreResponse) -> u64 {
+    if response.reasons.iter().any(|r| r == crate::engine::BANDIT_REASON) {
+        return server.cache_ttl_warn;
+    }
+    match response.action {
+        crate::models::Action::Allow => server.cache_ttl_allow,
+        crate::models::Action::Warn => server.cache_ttl_warn,
+        crate::models::Action::Block => server.cache_ttl_block,
+    }
+}
+
+/// Score a request and additionally return every extracted feature plus the
+/// wall time of each extraction stage. Only mounted when
+/// `server.debug_endpoints` is set.
+async fn debug_score(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+    Json(request): Json<ScoreRequest>,
+) -> Result<Json<Value>, AppError> {
+    validate_score_request(&request)?;
+    require_admin_for_overrides(&engine, &headers, &request)?;
+    let domain = crate::features::normalize_domain(&request.domain);
+    let (features, timings) = engine
+        .extractor()
+        .extract_traced(&domain, request.url.as_deref())
+        .await?;
+    let response = engine.score(&request).await?;
+    Ok(Json(json!({
+        "response": response,
+        "features": features.to_named_map(),
+        "dictionary_words": crate::features::segment_dictionary_words(&domain),
+        "stage_timings": timings,
+    })))
+}
+
+const MAX_BATCH_SIZE: usize = 100;
+
+async fn score_batch(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+    Json(requests): Json<Vec<ScoreRequest>>,
+) -> Result<Json<Vec<ScoreResponse>>, AppError> {
+    if requests.len() > MAX_BATCH_SIZE {
+        return Err(AppError::InvalidRequest(format!(
+            "batch size {} exceeds maximum {MAX_BATCH_SIZE}",
+            requests.len()
+        )));
+    }
+    let mut responses = Vec::with_capacity(requests.len());
+    for request in &requests {
+        validate_score_request(request)?;
+        require_admin_for_overrides(&engine, &headers, request)?;
+        responses.push(engine.score(request).await?);
+    }
+    Ok(Json(responses))
+}
+
+async fn feedback(
+    State(engine): State<Arc<ThreatEngine>>,
+    Json(request): Json<FeedbackRequest>,
+) -> Result<Json<Value>, AppError> {
+    engine.handle_feedback(&request).await?;
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+const MAX_SIMILAR_RESULTS: usize = 50;
+const MAX_SIMILAR_LOOKBACK_HOURS: u32 = 168;
+/// Candidate decisions pulled from storage per query, independent of the
+/// result cap.
+const SIMILAR_CANDIDATE_LIMIT: usize = 10_000;
+/// Minimum combined similarity for a candidate to make the results.
+const SIMILAR_MIN_SCORE: f64 = 0.5;
+
+/// Recent decisions ranked by similarity to a seed domain, for campaign
+/// investigations: structurally similar names (edit distance) and
+/// behaviorally similar feature vectors (cosine) both qualify.
+async fn similar(
+    State(engine): State<Arc<ThreatEngine>>,
+    Json(request): Json<SimilarRequest>,
+) -> Result<Json<Value>, AppError> {
+    let domain = crate::features::normalize_domain(&request.domain);
+    if domain.is_empty() {
+        return Err(AppError::InvalidRequest("domain must not be empty".into()));
+    }
+    let hours = request.hours.unwrap_or(24).clamp(1, MAX_SIMILAR_LOOKBACK_HOURS);
+    let limit = request.limit.unwrap_or(20).clamp(1, MAX_SIMILAR_RESULTS);
+    let features = engine.extractor().extract(&domain, None).await?;
+    let seed_vector = crate::features::features_to_vector(&features);
+    let rows = engine
+        .storage()
+        .get_recent_decisions(hours, SIMILAR_CANDIDATE_LIMIT)
+        .await?;
+    let results = rank_similar(&domain, &seed_vector, rows, limit);
+    Ok(Json(json!({
+        "domain": domain,
+        "hours": hours,
+        "results": results,
+    })))
+}
+
+fn rank_similar(
+    seed_domain: &str,
+    seed_vector: &[f64],
+    rows: Vec<RecentDecisionRow>,
+    limit: usize,
+) -> Vec<SimilarDomain> {
+    let mut ranked: Vec<SimilarDomain> = rows
+        .into_iter()
+        .filter_map(|row| {
+            if row.domain == seed_domain {
+                return None;
+            }
+            let features: std::collections::HashMap<String, f32> =
+                serde_json::from_str(&row.features).unwrap_or_default();
+            let vector = crate::features::features_to_vector(
+                &crate::features::FeatureSet::from_named_map(&features),
+            );
+            let cosine = cosine_similarity(seed_vector, &vector);
+            let lexical = lexical_similarity(seed_domain, &row.domain);
+            let similarity = cosine.max(lexical);
+            if similarity < SIMILAR_MIN_SCORE {
+                return None;
+            }
+            Some(SimilarDomain {
+                domain: row.domain,
+                action: row.action,
+                probability: row.probability,
+                cosine_similarity: cosine,
+                lexical_similarity: lexical,
+                similarity,
+            })
+        })
+        .collect();
+    ranked.sort_by(|a, b| {
+        b.similarity
+            .partial_cmp(&a.similarity)
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    ranked.truncate(limit);
+    ranked
+}
+
+fn cosine_similarity(a: &[f64], b: &[f64]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f64]| v.iter().map(|x| x * x).sum::<f64>().sqrt();
+    let denominator = norm(a) * norm(b);
+    if denominator == 0.0 {
+        0.0
+    } else {
+        dot / denominator
+    }
+}
+
+/// Levenshtein distance normalized into a [0, 1] similarity.
+fn lexical_similarity(a: &str, b: &str) -> f64 {
+    let longest = a.chars().count().max(b.chars().count());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - strsim::levenshtein(a, b) as f64 / longest as f64
+}
+
+/// Admin gate for maintenance endpoints: requires `X-Admin-Token` to match
+/// the configured token; endpoints are disabled while no token is set.
+fn require_admin(
+    engine: &ThreatEngine,
+    headers: &axum::http::HeaderMap,
+) -> Result<(), AppError> {
+    let configured = &engine.config().server.admin_token;
+    if configured.is_empty() {
+        return Err(AppError::Unauthorized(
+            "admin endpoints are disabled (no admin_token configured)".into(),
+        ));
+    }
+    match headers.get("x-admin-token").and_then(|v| v.to_str().ok()) {
+        Some(token) if token == configured => Ok(()),
+        _ => Err(AppError::Unauthorized("invalid admin token".into())),
+    }
+}
+
+/// The effective runtime configuration — defaults, file and environment
+/// merged — with secrets masked; the place to look when a threshold "is
+/// not taking effect" because an env override won.
+async fn config_view(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    let mut view = engine.config().redacted();
+    // Runtime flags can diverge from the boot configuration; the live
+    // state is what an operator is debugging against.
+    view["intel"]["external_lookups"] = json!(engine.intel().external_lookups_enabled());
+    Ok(Json(view))
+}
+
+/// Body of a runtime-flag update; absent fields are left untouched.
+#[derive(serde::Deserialize)]
+struct FlagsUpdate {
+    external_lookups: Option<bool>,
+}
+
+/// Current runtime operational flags. These reset to their configured
+/// boot values on restart.
+async fn admin_flags(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    Ok(Json(json!({
+        "external_lookups": engine.intel().external_lookups_enabled(),
+    })))
+}
+
+/// Apply runtime-flag changes without a restart — the incident lever for
+/// shedding external API cost or a misbehaving upstream.
+async fn admin_flags_update(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+    Json(update): Json<FlagsUpdate>,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    if let Some(enabled) = update.external_lookups {
+        engine.intel().set_external_lookups(enabled);
+    }
+    Ok(Json(json!({
+        "external_lookups": engine.intel().external_lookups_enabled(),
+    })))
+}
+
+/// Per-arm pull counts and whether the bandit has cleared its warmup gate;
+/// while unwarmed the uncertain band falls back to the threshold action.
+async fn bandit_stats(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    let min_arm_pulls = engine.config().bandit.min_arm_pulls;
+    let bandit = engine.bandit().lock().await;
+    let arm_pulls = bandit.arm_pulls();
+    let warmed = bandit.is_warmed(min_arm_pulls);
+    drop(bandit);
+    Ok(Json(json!({
+        "arm_pulls": arm_pulls,
+        "min_arm_pulls": min_arm_pulls,
+        "warmed": warmed,
+    })))
+}
+
+async fn bandit_reset(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    engine.bandit_reset().await;
+    Ok(Json(json!({ "status": "reset" })))
+}
+
+async fn bandit_export(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<crate::bandit::LinUCBParameters>, AppError> {
+    require_admin(&engine, &headers)?;
+    Ok(Json(engine.bandit_export().await))
+}
+
+async fn bandit_import(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+    Json(params): Json<crate::bandit::LinUCBParameters>,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    engine.bandit_import(params).await?;
+    Ok(Json(json!({ "status": "imported" })))
+}
+
+/// The most recent dead-lettered analyzer tasks, newest first, with the
+/// total list depth.
+async fn dead_letters(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    let depth = engine.redis().get_dead_letter_length().await?;
+    let entries = engine.redis().peek_dead_letters(50).await?;
+    Ok(Json(json!({ "depth": depth, "entries": entries })))
+}
+
+async fn dead_letters_requeue(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    let requeued = engine.redis().requeue_dead_letters().await?;
+    Ok(Json(json!({ "requeued": requeued })))
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn ready(State(engine): State<Arc<ThreatEngine>>) -> Result<Json<Value>, AppError> {
+    engine.redis().ping().await?;
+    let mut body = json!({
+        "status": "ready",
+        "model_untrained": engine.model_untrained().await,
+    });
+    // Preload is advisory metadata: the instance is ready regardless, the
+    // first scores just run against colder caches.
+    if engine.config().features.preload_path.is_some() {
+        body["preload"] = json!({
+            "done": engine.preload().done(),
+            "total": engine.preload().total(),
+        });
+    }
+    Ok(Json(body))
+}
+
+async fn model_info(State(engine): State<Arc<ThreatEngine>>) -> Json<Value> {
+    Json(engine.model_info().await)
+}
+
+/// Default page size for /decisions when the caller does not ask for one.
+const DEFAULT_DECISIONS_PAGE: usize = 50;
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct DecisionsParams {
+    action: Option<String>,
+    min_probability: Option<f32>,
+    domain: Option<String>,
+    /// RFC 3339 lower bound on the decision timestamp.
+    since: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+/// Browse recent decisions without direct ClickHouse access: filter by
+/// action, minimum probability, domain substring, and start time, paged
+/// newest-first. A full page carries a `next_cursor`; feed it back to get
+/// the next page.
+async fn decisions(
+    State(engine): State<Arc<ThreatEngine>>,
+    axum::extract::Query(params): axum::extract::Query<DecisionsParams>,
+) -> Result<Json<Value>, AppError> {
+    let cursor = params
+        .cursor
+        .as_deref()
+        .map(|c| {
+            crate::storage::parse_cursor(c)
+                .ok_or_else(|| AppError::InvalidRequest("malformed cursor".into()))
+        })
+        .transpose()?;
+    if params.min_probability.is_some_and(|p| !p.is_finite()) {
+        return Err(AppError::InvalidRequest(
+            "min_probability must be finite".into(),
+        ));
+    }
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_DECISIONS_PAGE)
+        .clamp(1, crate::storage::MAX_DECISIONS_PAGE);
+    let filter = crate::storage::DecisionsFilter {
+        action: params.action,
+        min_probability: params.min_probability,
+        domain: params.domain,
+        since: params.since,
+        limit,
+        cursor,
+    };
+    let rows = engine.storage().query_decisions(&filter).await?;
+    let next_cursor = crate::storage::next_cursor(&rows, limit);
+    Ok(Json(json!({
+        "decisions": rows,
+        "next_cursor": next_cursor,
+    })))
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct IntelLookupParams {
+    /// Answer from the local lists only, skipping external APIs.
+    skip_external: Option<bool>,
+}
+
+/// Which intel lists cover a domain, without making a scoring decision.
+/// Reports every local-list hit (the domain or a matching parent), and —
+/// unless `?skip_external=true` — also runs the comprehensive check,
+/// which can add an external source such as Google Safe Browsing.
+async fn intel_lookup(
+    State(engine): State<Arc<ThreatEngine>>,
+    axum::extract::Path(domain): axum::extract::Path<String>,
+    axum::extract::Query(params): axum::extract::Query<IntelLookupParams>,
+) -> Result<Json<Value>, AppError> {
+    let domain = crate::features::normalize_domain(&domain);
+    if domain.is_empty() {
+        return Err(AppError::InvalidRequest("domain must not be empty".into()));
+    }
+    let mut matches = engine.intel().local_matches(&domain).await;
+    if !params.skip_external.unwrap_or(false) {
+        if let Some(hit) = engine.intel().check_comprehensive(&domain, None).await {
+            if !matches.iter().any(|m| m.source == hit.source) {
+                matches.push(hit);
+            }
+        }
+    }
+    Ok(Json(json!({
+        "domain": domain,
+        "listed": !matches.is_empty(),
+        "matches": matches,
+    })))
+}
+
+async fn stats(State(engine): State<Arc<ThreatEngine>>) -> Result<Json<Value>, AppError> {
+    let decisions = engine.storage().get_decision_stats(24).await?;
+    let intel = engine.intel().get_statistics().await;
+    let queue_length = engine.redis().get_queue_length().await.unwrap_or(0);
+    let dead_letter_length = engine.redis().get_dead_letter_length().await.unwrap_or(0);
+    Ok(Json(json!({
+        "decisions": decisions,
+        "intel": intel,
+        "analyzer_queue_length": queue_length,
+        "analyzer_dead_letter_length": dead_letter_length,
+    })))
+}
+
+/// Live model quality from the feedback loop: the rolling confusion
+/// matrix with its derived rates. Rates are `null` until the window
+/// holds the relevant outcomes, so dashboards never read a fake zero.
+async fn quality(State(engine): State<Arc<ThreatEngine>>) -> Json<Value> {
+    let counts = engine.quality.snapshot();
+    Json(json!({
+        "window_seconds": engine.config().quality.window_seconds,
+        "counts": counts,
+        "precision": counts.precision(),
+        "recall": counts.recall(),
+        "false_positive_rate": counts.false_positive_rate(),
+    }))
+}
+
+/// Rolling per-feature distribution statistics, the baseline they are
+/// judged against, and the features currently outside the drift bands.
+/// Features never computed in a window are omitted from its map.
+async fn feature_stats(State(engine): State<Arc<ThreatEngine>>) -> Json<Value> {
+    let names = crate::features::FEATURE_NAMES;
+    let as_map = |stats: &[crate::metrics::FeatureStats]| -> Value {
+        names
+            .iter()
+            .zip(stats.iter())
+            .filter(|(_, stats)| stats.count > 0)
+            .map(|(name, stats)| (name.to_string(), json!(stats)))
+            .collect::<serde_json::Map<String, Value>>()
+            .into()
+    };
+    Json(json!({
+        "window_seconds": engine.config().drift.window_seconds,
+        "features": as_map(&engine.drift.snapshot()),
+        "baseline": engine.drift.baseline().map(|baseline| as_map(&baseline)),
+        "drifted": engine.drift.drifted(&engine.config().drift),
+    }))
+}
+
+/// Freeze the current window as the drift baseline. Admin-gated: the
+/// baseline defines what "normal" means for drift alerting.
+async fn feature_stats_baseline(
+    State(engine): State<Arc<ThreatEngine>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, AppError> {
+    require_admin(&engine, &headers)?;
+    let baseline = engine.drift.capture_baseline();
+    let captured = baseline.iter().filter(|stats| stats.count > 0).count();
+    Ok(Json(json!({ "status": "captured", "features": captured })))
+}
+
+async fn metrics(State(engine): State<Arc<ThreatEngine>>) -> String {
+    let mut body = engine.metrics.render();
+    body.push_str(&format!(
+        "# TYPE garuda_gsb_circuit_open gauge\ngaruda_gsb_circuit_open {}\n",
+        engine.intel().gsb_circuit_open() as u8
+    ));
+    body.push_str(&format!(
+        "# TYPE garuda_logging_in_flight gauge\ngaruda_logging_in_flight {}\n\
+         # TYPE garuda_logging_dropped_total counter\ngaruda_logging_dropped_total {}\n",
+        engine.logger().in_flight(),
+        engine.logger().dropped()
+    ));
+    body.push_str(&format!(
+        "# TYPE garuda_queue_malformed_total counter\n\
+         garuda_queue_malformed_total {}\n",
+        engine.redis().malformed_payloads()
+    ));
+    body.push_str(&format!(
+        "# TYPE garuda_feature_cache_bytes gauge\ngaruda_feature_cache_bytes {}\n\
+         # TYPE garuda_feature_cache_evictions_total counter\n\
+         garuda_feature_cache_evictions_total {}\n",
+        engine.extractor().cache_bytes(),
+        engine.extractor().cache_evictions()
+    ));
+    body.push_str(&format!(
+        "# TYPE garuda_feature_cache_hits_total counter\n\
+         garuda_feature_cache_hits_total {}\n",
+        engine.extractor().cache_hits()
+    ));
+    body.push_str(&format!(
+        "# TYPE garuda_clickhouse_active_endpoint gauge\n\
+         garuda_clickhouse_active_endpoint {}\n\
+         # TYPE garuda_clickhouse_failovers_total counter\n\
+         garuda_clickhouse_failovers_total {}\n",
+        engine.storage().active_endpoint(),
+        engine.storage().failovers()
+    ));
+    body.push_str(&engine.intel().statistics_gauges().await);
+    if let Ok(depth) = engine.redis().get_dead_letter_length().await {
+        body.push_str(&format!(
+            "# TYPE garuda_analyzer_dead_letter_depth gauge\n\
+             garuda_analyzer_dead_letter_depth {depth}\n"
+        ));
+    }
+    // Rolling-window quality counts are gauges, not counters: samples age
+    // out, so the values can go down. Rates are omitted while undefined.
+    let quality = engine.quality.snapshot();
+    body.push_str(&format!(
+        "# TYPE garuda_quality_true_positives gauge\ngaruda_quality_true_positives {}\n\
+         # TYPE garuda_quality_false_positives gauge\ngaruda_quality_false_positives {}\n\
+         # TYPE garuda_quality_true_negatives gauge\ngaruda_quality_true_negatives {}\n\
+         # TYPE garuda_quality_false_negatives gauge\ngaruda_quality_false_negatives {}\n",
+        quality.true_positives,
+        quality.false_positives,
+        quality.true_negatives,
+        quality.false_negatives
+    ));
+    let rates = [
+        ("garuda_quality_precision", quality.precision()),
+        ("garuda_quality_recall", quality.recall()),
+        ("garuda_quality_false_positive_rate", quality.false_positive_rate()),
+    ];
+    for (name, rate) in rates {
+        if let Some(rate) = rate {
+            body.push_str(&format!("# TYPE {name} gauge\n{name} {rate}\n"));
+        }
+    }
+    // Drift is relative to an operator-captured baseline; before one
+    // exists only the (zero) total is exported.
+    let drifted = engine.drift.drifted(&engine.config().drift);
+    body.push_str(&format!(
+        "# TYPE garuda_features_drifted gauge\ngaruda_features_drifted {}\n",
+        drifted.len()
+    ));
+    if !drifted.is_empty() {
+        body.push_str("# TYPE garuda_feature_drift gauge\n");
+        for name in drifted {
+            body.push_str(&format!("garuda_feature_drift{{feature=\"{name}\"}} 1\n"));
+        }
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::http::{header, Request};
+    use axum::routing::get;
+    use axum::Router;
+    use tower::ServiceExt;
+    use tower_http::compression::CompressionLayer;
+
+    #[test]
+    fn cache_ttl_follows_action() {
+        let server = crate::config::ServerConfig::default();
+        let mut response = crate::models::ScoreResponse {
+            decision_id: "d".into(),
+            domain: "example.com".into(),
+            action: crate::models::Action::Allow,
+            probability: 0.1,
+            confidence: 0.0,
+            reasons: vec![],
+            cached: false,
+            processing_time_ms: 1.0,
+            features_overridden: false,
+            model_version: String::new(),
+            feature_schema_version: 0,
+            features: None,
+        };
+        assert_eq!(super::cache_ttl_for(&server, &response), server.cache_ttl_allow);
+
+        response.action = crate::models::Action::Block;
+        assert_eq!(super::cache_ttl_for(&server, &response), server.cache_ttl_block);
+
+        // Bandit-refined decisions use the short WARN TTL even when blocked.
+        response.reasons.push(crate::engine::BANDIT_REASON.to_string());
+        assert_eq!(super::cache_ttl_for(&server, &response), server.cache_ttl_warn);
+    }
+
+    #[test]
+    fn server_timing_header_value_is_well_formed() {
+        let timings = vec![
+            crate::features::StageTiming { stage: "hard_intel", duration_ms: 0.21 },
+            crate::features::StageTiming { stage: "features", duration_ms: 0.934 },
+            crate::features::StageTiming { stage: "model", duration_ms: 0.1 },
+        ];
+        let value = super::server_timing_value(&timings);
+        assert_eq!(value, "hard_intel;dur=0.21, features;dur=0.93, model;dur=0.10");
+        // The formatted string must survive as an HTTP header value.
+        assert!(axum::http::HeaderValue::from_str(&value).is_ok());
+    }
+
+    #[test]
+    fn model_version_survives_the_response_cache_round_trip() {
+        let response = crate::models::ScoreResponse {
+            decision_id: "d".into(),
+            domain: "example.com".into(),
+            action: crate::models::Action::Warn,
+            probability: 0.6,
+            confidence: 0.0,
+            reasons: vec![],
+            cached: false,
+            processing_time_ms: 1.0,
+            features_overridden: false,
+            model_version: "2024-06-01T12:00:00Z".into(),
+            feature_schema_version: crate::features::FEATURE_SCHEMA_VERSION,
+            features: None,
+        };
+        let payload = serde_json::to_string(&response).unwrap();
+        let back: crate::models::ScoreResponse = serde_json::from_str(&payload).unwrap();
+        assert_eq!(back.model_version, response.model_version);
+        assert_eq!(back.feature_schema_version, crate::features::FEATURE_SCHEMA_VERSION);
+
+        // Responses cached before the upgrade lack the fields entirely.
+        let legacy: crate::models::ScoreResponse = serde_json::from_str(
+            r#"{"decision_id":"d","domain":"example.com","action":"ALLOW",
+                "probability":0.1,"reasons":[],"cached":true,"processing_time_ms":1.0}"#,
+        )
+        .unwrap();
+        assert_eq!(legacy.model_version, "");
+        assert_eq!(legacy.feature_schema_version, 0);
+    }
+
+    #[test]
+    fn probability_serializes_at_f32_precision() {
+        let response = crate::models::ScoreResponse {
+            decision_id: "d".into(),
+            domain: "example.com".into(),
+            action: crate::models::Action::Warn,
+            probability: 0.123_456_789,
+            confidence: 0.0,
+            reasons: vec![],
+            cached: false,
+            processing_time_ms: 1.0,
+            features_overridden: false,
+            model_version: "v1".into(),
+            feature_schema_version: crate::features::FEATURE_SCHEMA_VERSION,
+            features: None,
+        };
+        // Probabilities are f32 end to end: the literal rounds to the
+        // nearest f32 and the JSON carries exactly that value, with no
+        // spurious f64 digits from an intermediate widening.
+        let payload = serde_json::to_string(&response).unwrap();
+        assert!(payload.contains("\"probability\":0.12345679"), "{payload}");
+        let back: crate::models::ScoreResponse = serde_json::from_str(&payload).unwrap();
+        assert_eq!(back.probability, 0.123_456_789_f32);
+    }
+
+    #[test]
+    fn feature_map_is_serialized_only_when_present() {
+        let mut response = crate::models::ScoreResponse {
+            decision_id: "d".into(),
+            domain: "example.com".into(),
+            action: crate::models::Action::Allow,
+            probability: 0.1,
+            confidence: 0.0,
+            reasons: vec![],
+            cached: false,
+            processing_time_ms: 1.0,
+            features_overridden: false,
+            model_version: "v1".into(),
+            feature_schema_version: crate::features::FEATURE_SCHEMA_VERSION,
+            features: None,
+        };
+        // The default response omits the key entirely rather than carrying
+        // a null, so existing clients see no schema change.
+        let payload = serde_json::to_string(&response).unwrap();
+        assert!(!payload.contains("\"features\""), "{payload}");
+
+        response.features = Some(std::collections::HashMap::from([(
+            "domain_entropy".to_string(),
+            3.5_f32,
+        )]));
+        let payload = serde_json::to_string(&response).unwrap();
+        assert!(payload.contains("\"domain_entropy\":3.5"), "{payload}");
+    }
+
+    #[test]
+    fn client_request_ids_are_validated() {
+        let request = |id: Option<&str>| crate::models::ScoreRequest {
+            domain: "example.com".to_string(),
+            url: None,
+            request_id: id.map(str::to_string),
+            context: Default::default(),
+            feature_overrides: Default::default(),
+            force_analyze: false,
+            return_features: false,
+        };
+        assert!(super::validate_score_request(&request(None)).is_ok());
+        assert!(super::validate_score_request(&request(Some("proxy-tx.8812"))).is_ok());
+        assert!(super::validate_score_request(&request(Some(""))).is_err());
+        assert!(super::validate_score_request(&request(Some("has spaces"))).is_err());
+        assert!(super::validate_score_request(&request(Some(&"x".repeat(129)))).is_err());
+    }
+
+    #[test]
+    fn feature_override_names_must_come_from_the_schema() {
+        let request = |name: &str| crate::models::ScoreRequest {
+            domain: "example.com".to_string(),
+            url: None,
+            request_id: None,
+            context: Default::default(),
+            feature_overrides: std::collections::HashMap::from([(name.to_string(), 1.0)]),
+            force_analyze: false,
+            return_features: false,
+        };
+        assert!(super::validate_score_request(&request("dga_score")).is_ok());
+        assert!(super::validate_score_request(&request("warp_factor")).is_err());
+    }
+
+    #[test]
+    fn client_deadline_header_parses_and_clamps() {
+        let headers = |value: &str| {
+            let mut headers = axum::http::HeaderMap::new();
+            headers.insert("x-deadline-ms", value.parse().unwrap());
+            headers
+        };
+        let deadline = |value: &str| super::client_deadline(&headers(value), 10_000);
+
+        // No header means no deadline; a tiny one survives as given.
+        assert_eq!(
+            super::client_deadline(&axum::http::HeaderMap::new(), 10_000).unwrap(),
+            None
+        );
+        assert_eq!(
+            deadline("3").unwrap(),
+            Some(std::time::Duration::from_millis(3))
+        );
+        // Zero is honored (hard-intel-only), oversized budgets clamp to the
+        // configured maximum, and garbage is a client error.
+        assert_eq!(
+            deadline("0").unwrap(),
+            Some(std::time::Duration::ZERO)
+        );
+        assert_eq!(
+            deadline("999999999").unwrap(),
+            Some(std::time::Duration::from_millis(10_000))
+        );
+        assert!(deadline("soon").is_err());
+        assert!(deadline("-5").is_err());
+    }
+
+    #[test]
+    fn similar_ranking_covers_both_lookalikes_and_feature_twins() {
+        let seed = "secure-login.example";
+        let mut seed_features = crate::features::FeatureSet::default();
+        seed_features.set(crate::features::Feature::DgaScore, 0.9);
+        seed_features.set(crate::features::Feature::Entropy, 4.0);
+        let seed_vector = crate::features::features_to_vector(&seed_features);
+        let row = |domain: &str, features: &str| crate::storage::RecentDecisionRow {
+            domain: domain.to_string(),
+            features: features.to_string(),
+            action: "WARN".to_string(),
+            probability: 0.6,
+        };
+        let rows = vec![
+            // Near-identical name, no stored features.
+            row("secure-login1.example", "{}"),
+            // Identical feature vector, unrelated name.
+            row("unrelated-name.test", r#"{"dga_score":0.9,"entropy":4.0}"#),
+            // Neither similar name nor similar features.
+            row("completely-different.org", "{}"),
+            // The seed itself and unparseable feature JSON are dropped.
+            row(seed, "{}"),
+            row("garbage-features.example", "not json"),
+        ];
+
+        let ranked = super::rank_similar(seed, &seed_vector, rows, 10);
+        let domains: Vec<&str> = ranked.iter().map(|r| r.domain.as_str()).collect();
+        assert_eq!(domains, vec!["unrelated-name.test", "secure-login1.example"]);
+        assert!(ranked[0].cosine_similarity > 0.99);
+        assert!(ranked[1].lexical_similarity > 0.9);
+
+        // The result cap applies after ranking.
+        let rows = vec![
+            row("secure-login1.example", "{}"),
+            row("secure-login2.example", "{}"),
+        ];
+        assert_eq!(super::rank_similar(seed, &seed_vector, rows, 1).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn large_json_response_is_gzipped_when_requested() {
+        let app = Router::new()
+            .route(
+                "/big",
+                get(|| async {
+                    axum::Json(vec![serde_json::json!({"domain": "example.com"}); 500])
+                }),
+            )
+            .layer(CompressionLayer::new());
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .uri("/big")
+                    .header(header::ACCEPT_ENCODING, "gzip")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response
+                .headers()
+                .get(header::CONTENT_ENCODING)
+                .and_then(|v| v.to_str().ok()),
+            Some("gzip")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use tracing::{info, warn};
+
+use crate::config::ThresholdConfig;
+use crate::engine::{action_from_thresholds, combine_scores, model_is_untrained, ThreatEngine};
+use crate::error::AppError;
+use crate::features::FeatureExtractor;
+use crate::model::StudentModel;
+use crate::models::Action;
+
+/// Embedded canary set scored at startup: unambiguous domains whose action
+/// any sane model must get right. The good half are household names (all
+/// popularity-anchored), the bad half are the lexical worst case — long
+/// random-looking labels and brand bait under throwaway TLDs. A model
+/// loaded with shuffled feature ordering or inverted weights misjudges at
+/// least one of them.
+const CANARIES: &[(&str, Action)] = &[
+    ("google.com", Action::Allow),
+    ("wikipedia.org", Action::Allow),
+    ("github.com", Action::Allow),
+    ("xk9qj2zr7w4mh8tv.top", Action::Block),
+    ("paypa1-secure-login-verify.icu", Action::Block),
+    ("qpwzj4xv8tkfmr2b.gq", Action::Block),
+];
+
+/// One misjudged canary.
+#[derive(Debug)]
+struct CanaryFailure {
+    domain: &'static str,
+    expected: Action,
+    got: Action,
+    probability: f32,
+}
+
+/// Score every canary through the real extractor, model, and thresholds —
+/// the same path a live request takes through the feature and model stages,
+/// minus intel and the bandit, which have no bearing on a misloaded model.
+async fn canary_failures(
+    extractor: &FeatureExtractor,
+    model: &StudentModel,
+    thresholds: &ThresholdConfig,
+) -> Result<Vec<CanaryFailure>, AppError> {
+    let mut failures = Vec::new();
+    for &(domain, expected) in CANARIES {
+        let features = extractor.extract(domain, None).await?;
+        let probability = combine_scores(model.predict(&model.vector_for(&features)), &features);
+        let got = action_from_thresholds(probability, thresholds);
+        if got != expected {
+            failures.push(CanaryFailure {
+                domain,
+                expected,
+                got,
+                probability,
+            });
+        }
+    }
+    Ok(failures)
+}
+
+/// The startup self-test: score the canaries before serving traffic and,
+/// per `self_test.fail_on_error`, either refuse to start or warn per
+/// failure. An untrained model skips the check — it has nothing to
+/// validate and the pipeline already handles it via the untrained policy.
+pub async fn run(engine: &ThreatEngine) -> Result<(), AppError> {
+    if !engine.config().self_test.enabled {
+        return Ok(());
+    }
+    let model = engine.model().current().await;
+    if model_is_untrained(&model) {
+        info!("skipping startup self-test: model is untrained");
+        return Ok(());
+    }
+    let failures =
+        canary_failures(engine.extractor(), &model, &engine.config().thresholds).await?;
+    if failures.is_empty() {
+        info!(canaries = CANARIES.len(), model_version = %model.version, "startup self-test passed");
+        return Ok(());
+    }
+    for failure in &failures {
+        warn!(
+            domain = failure.domain,
+            expected = failure.expected.as_str(),
+            got = failure.got.as_str(),
+            probability = failure.probability,
+            "startup self-test canary misjudged"
+        );
+    }
+    if engine.config().self_test.fail_on_error {
+        return Err(AppError::Model(format!(
+            "startup self-test failed: {} of {} canaries misjudged (model {})",
+            failures.len(),
+            CANARIES.len(),
+            model.version
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::FeatureConfig;
+    use crate::features::FEATURE_NAMES;
+
+    fn offline_extractor() -> FeatureExtractor {
+        FeatureExtractor::new(FeatureConfig {
+            dns_enabled: false,
+            ..FeatureConfig::default()
+        })
+    }
+
+    /// A model with the sign of every signal flipped: lexically hostile
+    /// domains score benign and vice versa. Trained (samples > 0), so the
+    /// untrained skip does not apply.
+    fn inverted_model() -> StudentModel {
+        StudentModel {
+            version: "inverted".to_string(),
+            weights: vec![-6.0],
+            bias: 3.0,
+            training_samples: 1_000,
+            trained_at: None,
+            feature_names: vec!["dga_score".to_string()],
+        }
+    }
+
+    #[tokio::test]
+    async fn inverted_model_fails_the_self_test() {
+        let failures = canary_failures(
+            &offline_extractor(),
+            &inverted_model(),
+            &ThresholdConfig::default(),
+        )
+        .await
+        .unwrap();
+        // Every bad canary comes back short of BLOCK: the inverted model
+        // scores them benign, and the lexical blend alone cannot reach the
+        // block threshold.
+        assert!(failures
+            .iter()
+            .any(|f| f.expected == Action::Block && f.got != Action::Block));
+    }
+
+    #[tokio::test]
+    async fn neutral_model_clears_the_good_canaries() {
+        // A zero-weight model has no opinion; the popularity anchor and the
+        // lexical blend alone must keep household names out of WARN/BLOCK.
+        let neutral = StudentModel {
+            training_samples: 1_000,
+            ..StudentModel::default()
+        };
+        let failures = canary_failures(
+            &offline_extractor(),
+            &neutral,
+            &ThresholdConfig::default(),
+        )
+        .await
+        .unwrap();
+        assert!(failures.iter().all(|f| f.expected != Action::Allow));
+        // Schema sanity: the canary model in the other test projects a real
+        // feature, so the assertion there exercises ordering too.
+        assert!(FEATURE_NAMES.contains(&"dga_score"));
+    }
+}
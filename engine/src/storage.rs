@@ -0,0 +1,756 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use clickhouse::Client;
+use tracing::{info, warn};
+
+use crate::config::ClickHouseConfig;
+use crate::error::AppError;
+use crate::models::{Decision, DecisionStats};
+
+/// One ClickHouse endpoint and its failure state.
+struct Endpoint {
+    url: String,
+    client: Client,
+    /// When the endpoint last failed; it is skipped while the failover
+    /// cooldown has not elapsed. Cleared by a successful health probe.
+    failed_at: std::sync::Mutex<Option<Instant>>,
+}
+
+impl Endpoint {
+    fn cooling_down(&self, cooldown: Duration) -> bool {
+        self.failed_at
+            .lock()
+            .unwrap()
+            .map(|failed_at| failed_at.elapsed() < cooldown)
+            .unwrap_or(false)
+    }
+}
+
+/// Wrapper over the ClickHouse HTTP client for decision logging and
+/// aggregate queries, with multi-region failover: every request prefers
+/// the primary, rotates to the next healthy endpoint on error, and a
+/// failed endpoint sits out a cooldown until the health probe (or the
+/// cooldown clock) restores it.
+pub struct ClickHouseClient {
+    /// Endpoints in preference order; index 0 is the primary.
+    endpoints: Vec<Endpoint>,
+    cooldown: Duration,
+    /// Index of the endpoint that served the last successful request.
+    active: AtomicUsize,
+    /// Requests that had to rotate past a failed endpoint.
+    failovers: AtomicU64,
+}
+
+impl ClickHouseClient {
+    pub fn new(config: &ClickHouseConfig) -> Self {
+        let endpoints = std::iter::once(&config.url)
+            .chain(&config.replica_urls)
+            .map(|url| Endpoint {
+                url: url.clone(),
+                client: build_client(config, url),
+                failed_at: std::sync::Mutex::new(None),
+            })
+            .collect();
+        Self {
+            endpoints,
+            cooldown: Duration::from_secs(config.failover_cooldown_seconds),
+            active: AtomicUsize::new(0),
+            failovers: AtomicU64::new(0),
+        }
+    }
+
+    /// Cheap connectivity probe run at startup; succeeds when any endpoint
+    /// answers.
+    pub async fn test_connection(&self) -> Result<(), AppError> {
+        self.fetch_all::<u8>("SELECT 1").await.map(|_| ())
+    }
+
+    /// Create the tables the insert paths assume, if absent. Run at startup
+    /// when `clickhouse.auto_migrate` is set, so a fresh deployment logs
+    /// from the first decision instead of failing every insert with a
+    /// missing-table error. Every statement is `IF NOT EXISTS`, so rerunning
+    /// against an existing schema is a no-op.
+    pub async fn migrate(&self) -> Result<(), AppError> {
+        for ddl in MIGRATIONS {
+            self.execute(ddl).await?;
+        }
+        info!(tables = MIGRATIONS.len(), "ClickHouse schema migration applied");
+        Ok(())
+    }
+
+    /// Index (0 = primary) of the endpoint that served the last successful
+    /// request. Exposed as the `garuda_clickhouse_active_endpoint` gauge.
+    pub fn active_endpoint(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Requests that rotated past a failed endpoint, for the
+    /// `garuda_clickhouse_failovers_total` counter.
+    pub fn failovers(&self) -> u64 {
+        self.failovers.load(Ordering::Relaxed)
+    }
+
+    /// Endpoints to try, in preference order: the primary and then the
+    /// replicas, skipping any still cooling down from a failure. When
+    /// everything is cooling down all endpoints are tried anyway — a
+    /// degraded attempt beats refusing outright.
+    fn candidates(&self) -> Vec<usize> {
+        let healthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&index| !self.endpoints[index].cooling_down(self.cooldown))
+            .collect();
+        if healthy.is_empty() {
+            (0..self.endpoints.len()).collect()
+        } else {
+            healthy
+        }
+    }
+
+    fn note_success(&self, index: usize) {
+        *self.endpoints[index].failed_at.lock().unwrap() = None;
+        let previous = self.active.swap(index, Ordering::Relaxed);
+        if previous != index {
+            info!(
+                endpoint = %self.endpoints[index].url,
+                "ClickHouse requests now served by a different endpoint"
+            );
+        }
+    }
+
+    fn note_failure(&self, index: usize) {
+        *self.endpoints[index].failed_at.lock().unwrap() = Some(Instant::now());
+        self.failovers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Run a statement with failover across the candidate endpoints.
+    async fn execute(&self, sql: &str) -> Result<(), AppError> {
+        let mut last_error = None;
+        for index in self.candidates() {
+            match self.endpoints[index].client.query(sql).execute().await {
+                Ok(()) => {
+                    self.note_success(index);
+                    return Ok(());
+                }
+                Err(e) => {
+                    warn!(endpoint = %self.endpoints[index].url, error = %e, "ClickHouse request failed");
+                    self.note_failure(index);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(AppError::Storage(last_error.expect("at least one endpoint")))
+    }
+
+    /// Run a query with failover across the candidate endpoints.
+    async fn fetch_all<T>(&self, sql: &str) -> Result<Vec<T>, AppError>
+    where
+        T: clickhouse::Row + for<'b> serde::Deserialize<'b>,
+    {
+        let mut last_error = None;
+        for index in self.candidates() {
+            match self.endpoints[index].client.query(sql).fetch_all::<T>().await {
+                Ok(rows) => {
+                    self.note_success(index);
+                    return Ok(rows);
+                }
+                Err(e) => {
+                    warn!(endpoint = %self.endpoints[index].url, error = %e, "ClickHouse query failed");
+                    self.note_failure(index);
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(AppError::Storage(last_error.expect("at least one endpoint")))
+    }
+
+    /// Spawn the background probe that rechecks cooling-down endpoints and
+    /// restores them as soon as they answer, instead of waiting out the
+    /// full cooldown.
+    pub fn start_health_probe(self: &Arc<Self>, interval_seconds: u64) {
+        if self.endpoints.len() < 2 {
+            return;
+        }
+        let storage = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(Duration::from_secs(interval_seconds.max(1))).await;
+                for endpoint in &storage.endpoints {
+                    if !endpoint.cooling_down(storage.cooldown) {
+                        continue;
+                    }
+                    if endpoint.client.query("SELECT 1").fetch_one::<u8>().await.is_ok() {
+                        *endpoint.failed_at.lock().unwrap() = None;
+                        info!(endpoint = %endpoint.url, "ClickHouse endpoint recovered");
+                    }
+                }
+            }
+        });
+    }
+
+    pub async fn log_decision(&self, decision: &Decision) -> Result<(), AppError> {
+        let features_json = serde_json::to_string(&decision.features)?;
+        let reasons_json = serde_json::to_string(&decision.reasons)?;
+        let sql = format!(
+            "INSERT INTO decisions \
+             (decision_id, domain, url, action, probability, confidence, model_version, \
+              features, reasons, processing_time_ms, sample_rate, timestamp) \
+             VALUES ('{}', '{}', '{}', '{}', {}, {}, '{}', '{}', '{}', {}, {}, '{}')",
+            clickhouse_escape(&decision.decision_id),
+            clickhouse_escape(&decision.domain),
+            clickhouse_escape(decision.url.as_deref().unwrap_or("")),
+            decision.action.as_str(),
+            decision.probability,
+            decision.confidence,
+            clickhouse_escape(&decision.model_version),
+            clickhouse_escape(&features_json),
+            clickhouse_escape(&reasons_json),
+            decision.processing_time_ms,
+            decision.sample_rate,
+            decision.timestamp.format("%Y-%m-%d %H:%M:%S"),
+        );
+        self.execute(&sql).await
+    }
+
+    pub async fn log_analyzer_result(
+        &self,
+        task_id: &str,
+        domain: &str,
+        verdict: &str,
+        notes: &str,
+    ) -> Result<(), AppError> {
+        let sql = format!(
+            "INSERT INTO analyzer_results (task_id, domain, verdict, notes, timestamp) \
+             VALUES ('{}', '{}', '{}', '{}', now())",
+            clickhouse_escape(task_id),
+            clickhouse_escape(domain),
+            clickhouse_escape(verdict),
+            clickhouse_escape(notes),
+        );
+        self.execute(&sql).await
+    }
+
+    /// The most recent decision per domain over the trailing `hours`
+    /// window, for the /similar investigation endpoint.
+    pub async fn get_recent_decisions(
+        &self,
+        hours: u32,
+        limit: usize,
+    ) -> Result<Vec<RecentDecisionRow>, AppError> {
+        let sql = format!(
+            "SELECT domain, argMax(features, timestamp) AS features, \
+             argMax(action, timestamp) AS action, \
+             argMax(probability, timestamp) AS probability \
+             FROM decisions WHERE timestamp > now() - INTERVAL {hours} HOUR \
+             GROUP BY domain LIMIT {limit}"
+        );
+        self.fetch_all(&sql).await
+    }
+
+    /// One page of decision summaries, newest first, for the /decisions
+    /// browse endpoint. Rows are ordered by (timestamp, decision_id)
+    /// descending and the cursor excludes everything at or after its
+    /// position, so a page stays stable while newer decisions are being
+    /// inserted ahead of it.
+    pub async fn query_decisions(
+        &self,
+        filter: &DecisionsFilter,
+    ) -> Result<Vec<DecisionSummaryRow>, AppError> {
+        self.fetch_all(&decisions_sql(filter)).await
+    }
+
+    /// Aggregate decision counts over the trailing `hours` window.
+    pub async fn get_decision_stats(&self, hours: u32) -> Result<DecisionStats, AppError> {
+        let sql = format!(
+            "SELECT action, count() AS count, avg(probability) AS avg_probability, \
+             avg(processing_time_ms) AS avg_processing_time_ms, \
+             quantile(0.95)(processing_time_ms) AS p95_processing_time_ms \
+             FROM decisions WHERE timestamp > now() - INTERVAL {hours} HOUR \
+             GROUP BY action"
+        );
+        let rows: Vec<DecisionStatsRow> = self.fetch_all(&sql).await?;
+        Ok(aggregate_stats(rows))
+    }
+}
+
+/// DDL for the tables the insert paths write to, in creation order. Column
+/// names and types mirror the INSERT statements above and the `*Row`
+/// structs below — a column added to either must be added here too. The
+/// sort key matches the read paths: decisions are always filtered or
+/// ordered by timestamp first.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE IF NOT EXISTS decisions (\
+     decision_id String, \
+     domain String, \
+     url String, \
+     action LowCardinality(String), \
+     probability Float32, \
+     confidence Float32, \
+     model_version LowCardinality(String), \
+     features String, \
+     reasons String, \
+     processing_time_ms Float64, \
+     sample_rate Float64, \
+     timestamp DateTime\
+     ) ENGINE = MergeTree \
+     PARTITION BY toYYYYMMDD(timestamp) \
+     ORDER BY (timestamp, decision_id)",
+    "CREATE TABLE IF NOT EXISTS analyzer_results (\
+     task_id String, \
+     domain String, \
+     verdict LowCardinality(String), \
+     notes String, \
+     timestamp DateTime\
+     ) ENGINE = MergeTree \
+     PARTITION BY toYYYYMMDD(timestamp) \
+     ORDER BY (timestamp, task_id)",
+];
+
+/// Build one configured HTTP client for an endpoint URL.
+fn build_client(config: &ClickHouseConfig, url: &str) -> Client {
+    let mut client = Client::default()
+        .with_url(url)
+        .with_database(&config.database)
+        .with_user(&config.user)
+        .with_password(&config.password)
+        .with_option(
+            "connect_timeout",
+            &config.connect_timeout_seconds.to_string(),
+        );
+    if url.starts_with("https://") && !config.verify_tls {
+        warn!("ClickHouse TLS certificate verification is disabled");
+        let https = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_tls_config(tls::no_verify_config())
+            .https_or_http()
+            .enable_http1()
+            .build();
+        client = client.with_http_client(hyper::Client::builder().build(https));
+    }
+    client
+}
+
+/// Hard cap on page size for the /decisions browse endpoint.
+pub const MAX_DECISIONS_PAGE: usize = 500;
+
+/// Filters for the decisions browse query, validated by the route before
+/// they reach the SQL builder.
+#[derive(Debug, Default)]
+pub struct DecisionsFilter {
+    pub action: Option<String>,
+    pub min_probability: Option<f32>,
+    /// Case-insensitive substring match on the domain.
+    pub domain: Option<String>,
+    pub since: Option<chrono::DateTime<chrono::Utc>>,
+    pub limit: usize,
+    /// Decoded cursor from the previous page: the last row's unix
+    /// timestamp and decision id.
+    pub cursor: Option<(u32, String)>,
+}
+
+/// One decision summary row for the browse endpoint.
+#[derive(Debug, clickhouse::Row, serde::Serialize, serde::Deserialize)]
+pub struct DecisionSummaryRow {
+    pub decision_id: String,
+    pub domain: String,
+    pub action: String,
+    pub probability: f32,
+    pub model_version: String,
+    /// Unix seconds; doubles as the first half of the row's cursor.
+    pub timestamp: u32,
+}
+
+/// Build the browse query. Every string filter goes through `clickhouse_escape`, and
+/// the limit is clamped here as well as in the route so no caller of
+/// `query_decisions` can request an unbounded page.
+fn decisions_sql(filter: &DecisionsFilter) -> String {
+    let mut conditions = Vec::new();
+    if let Some(action) = &filter.action {
+        conditions.push(format!("action = '{}'", clickhouse_escape(action)));
+    }
+    if let Some(min) = filter.min_probability {
+        conditions.push(format!("probability >= {min}"));
+    }
+    if let Some(needle) = &filter.domain {
+        conditions.push(format!(
+            "positionCaseInsensitive(domain, '{}') > 0",
+            clickhouse_escape(needle)
+        ));
+    }
+    if let Some(since) = filter.since {
+        conditions.push(format!("timestamp >= toDateTime({})", since.timestamp()));
+    }
+    if let Some((ts, id)) = &filter.cursor {
+        conditions.push(format!(
+            "(toUnixTimestamp(timestamp), decision_id) < ({ts}, '{}')",
+            clickhouse_escape(id)
+        ));
+    }
+    let where_clause = if conditions.is_empty() {
+        String::new()
+    } else {
+        format!(" WHERE {}", conditions.join(" AND "))
+    };
+    let limit = filter.limit.clamp(1, MAX_DECISIONS_PAGE);
+    format!(
+        "SELECT decision_id, domain, action, probability, model_version, \
+         toUnixTimestamp(timestamp) AS timestamp FROM decisions{where_clause} \
+         ORDER BY timestamp DESC, decision_id DESC LIMIT {limit}"
+    )
+}
+
+/// Decode a client-supplied cursor (`<unix_ts>:<decision_id>`); `None`
+/// means it was malformed.
+pub fn parse_cursor(cursor: &str) -> Option<(u32, String)> {
+    let (ts, id) = cursor.split_once(':')?;
+    if id.is_empty() {
+        return None;
+    }
+    Some((ts.parse().ok()?, id.to_string()))
+}
+
+/// The cursor for the page after `rows`, or `None` when the page came up
+/// short — a short page means the scan is exhausted.
+pub fn next_cursor(rows: &[DecisionSummaryRow], limit: usize) -> Option<String> {
+    if rows.len() < limit.max(1) {
+        return None;
+    }
+    rows.last()
+        .map(|row| format!("{}:{}", row.timestamp, row.decision_id))
+}
+
+/// One recent decision from the decisions table: the domain, its stored
+/// feature JSON, and the action that was taken.
+#[derive(Debug, clickhouse::Row, serde::Deserialize)]
+pub struct RecentDecisionRow {
+    pub domain: String,
+    pub features: String,
+    pub action: String,
+    pub probability: f32,
+}
+
+/// One per-action aggregation row from the decisions table.
+#[derive(Debug, clickhouse::Row, serde::Deserialize)]
+pub struct DecisionStatsRow {
+    pub action: String,
+    pub count: u64,
+    pub avg_probability: f64,
+    pub avg_processing_time_ms: f64,
+    pub p95_processing_time_ms: f64,
+}
+
+/// Fold per-action rows into the aggregate view. Unknown actions still
+/// count toward the totals so a future action (e.g. CHALLENGE) degrades
+/// to "counted but unlabeled" rather than vanishing.
+fn aggregate_stats(rows: Vec<DecisionStatsRow>) -> DecisionStats {
+    let mut stats = DecisionStats::default();
+    let mut prob_sum = 0.0;
+    let mut time_sum = 0.0;
+    for row in rows {
+        match row.action.as_str() {
+            "ALLOW" => stats.allow_count = row.count,
+            "WARN" => stats.warn_count = row.count,
+            "BLOCK" => stats.block_count = row.count,
+            "CHALLENGE" => stats.challenge_count = row.count,
+            other => {
+                warn!(action = other, count = row.count, "unrecognized action in stats");
+                stats.other_count += row.count;
+            }
+        }
+        stats.total += row.count;
+        prob_sum += row.avg_probability * row.count as f64;
+        time_sum += row.avg_processing_time_ms * row.count as f64;
+        // Exact p95s do not combine across groups; the max is a safe
+        // upper bound for the overall figure.
+        stats.p95_processing_time_ms =
+            stats.p95_processing_time_ms.max(row.p95_processing_time_ms);
+    }
+    if stats.total > 0 {
+        stats.avg_probability = prob_sum / stats.total as f64;
+        stats.avg_processing_time_ms = time_sum / stats.total as f64;
+    }
+    stats
+}
+
+/// Escape a value for interpolation into a ClickHouse string literal.
+///
+/// ClickHouse uses backslash escapes, so doubling quotes alone is not
+/// enough: a trailing `\` before a doubled quote re-opens the literal, and
+/// raw newlines, tabs, or NULs inside an INSERT can truncate or corrupt the
+/// statement. Stopgap until the parameterized-insert migration lands.
+fn clickhouse_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\'' => out.push_str("\\'"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(action: &str, count: u64, avg: f64, p95: f64) -> DecisionStatsRow {
+        DecisionStatsRow {
+            action: action.to_string(),
+            count,
+            avg_probability: 0.5,
+            avg_processing_time_ms: avg,
+            p95_processing_time_ms: p95,
+        }
+    }
+
+    #[test]
+    fn aggregates_full_action_set_including_unknowns() {
+        let stats = aggregate_stats(vec![
+            row("ALLOW", 80, 2.0, 5.0),
+            row("WARN", 10, 4.0, 9.0),
+            row("BLOCK", 5, 3.0, 6.0),
+            row("CHALLENGE", 3, 8.0, 20.0),
+            row("QUARANTINE", 2, 1.0, 2.0),
+        ]);
+        assert_eq!(stats.allow_count, 80);
+        assert_eq!(stats.warn_count, 10);
+        assert_eq!(stats.block_count, 5);
+        assert_eq!(stats.challenge_count, 3);
+        assert_eq!(stats.other_count, 2);
+        // Unknown actions still count toward the total.
+        assert_eq!(stats.total, 100);
+        assert_eq!(stats.p95_processing_time_ms, 20.0);
+        assert!((stats.avg_probability - 0.5).abs() < 1e-9);
+    }
+
+    fn summary(ts: u32, id: &str) -> DecisionSummaryRow {
+        DecisionSummaryRow {
+            decision_id: id.to_string(),
+            domain: "example.com".to_string(),
+            action: "BLOCK".to_string(),
+            probability: 0.9,
+            model_version: "v1".to_string(),
+            timestamp: ts,
+        }
+    }
+
+    #[test]
+    fn decisions_sql_escapes_filters_and_caps_the_limit() {
+        let filter = DecisionsFilter {
+            action: Some("BLOCK".to_string()),
+            min_probability: Some(0.8),
+            domain: Some("o'reilly".to_string()),
+            since: chrono::DateTime::from_timestamp(1_700_000_000, 0),
+            limit: 10_000,
+            cursor: Some((1_700_003_600, "d-42".to_string())),
+        };
+        let sql = decisions_sql(&filter);
+        assert!(sql.contains("action = 'BLOCK'"));
+        assert!(sql.contains("probability >= 0.8"));
+        // The embedded quote is backslash-escaped, not passed through.
+        assert!(sql.contains("positionCaseInsensitive(domain, 'o\\'reilly') > 0"));
+        assert!(sql.contains("timestamp >= toDateTime(1700000000)"));
+        assert!(sql.contains(
+            "(toUnixTimestamp(timestamp), decision_id) < (1700003600, 'd-42')"
+        ));
+        assert!(sql.ends_with(&format!("LIMIT {MAX_DECISIONS_PAGE}")));
+    }
+
+    #[test]
+    fn clickhouse_escape_neutralizes_adversarial_strings() {
+        // A literal-safe string contains no bare quote or control character,
+        // and every backslash starts a recognized escape — so a trailing
+        // backslash can never swallow the closing quote.
+        fn literal_safe(escaped: &str) {
+            let mut chars = escaped.chars();
+            while let Some(c) = chars.next() {
+                assert!(!matches!(c, '\'' | '\n' | '\r' | '\t' | '\0'), "bare {c:?}");
+                if c == '\\' {
+                    let next = chars.next().expect("dangling backslash");
+                    assert!(matches!(next, '\\' | '\'' | 'n' | 'r' | 't' | '0'));
+                }
+            }
+        }
+        let adversarial = [
+            "o'reilly",
+            "evil\\",
+            "evil\\'",
+            "a'); DROP TABLE decisions; --",
+            "line1\nline2\ttabbed",
+            "nul\0byte",
+            "\\\\'\\n'\\",
+            "xn--mnchen-3ya.de",
+            "пример.испытание",
+            "日本語.jp'",
+        ];
+        for input in adversarial {
+            literal_safe(&clickhouse_escape(input));
+        }
+        // Plain domains pass through untouched, including non-ASCII.
+        assert_eq!(clickhouse_escape("example.com"), "example.com");
+        assert_eq!(clickhouse_escape("пример.рф"), "пример.рф");
+        assert_eq!(clickhouse_escape("back\\slash'"), "back\\\\slash\\'");
+        assert_eq!(clickhouse_escape("nul\0\ttab"), "nul\\0\\ttab");
+    }
+
+    #[test]
+    fn unfiltered_decisions_sql_has_no_where_clause() {
+        let filter = DecisionsFilter { limit: 50, ..Default::default() };
+        let sql = decisions_sql(&filter);
+        assert!(!sql.contains("WHERE"));
+        assert!(sql.ends_with("LIMIT 50"));
+        // A zero limit never turns into an unbounded scan.
+        let zero = DecisionsFilter::default();
+        assert!(decisions_sql(&zero).ends_with("LIMIT 1"));
+    }
+
+    #[test]
+    fn cursor_round_trips_and_rejects_garbage() {
+        let rows = vec![summary(1_700_000_300, "d-3"), summary(1_700_000_100, "d-1")];
+        let cursor = next_cursor(&rows, 2).expect("full page yields a cursor");
+        assert_eq!(parse_cursor(&cursor), Some((1_700_000_100, "d-1".to_string())));
+
+        // A short page means the scan is exhausted: no cursor.
+        assert_eq!(next_cursor(&rows, 3), None);
+        assert_eq!(next_cursor(&[], 1), None);
+
+        assert_eq!(parse_cursor("not-a-cursor"), None);
+        assert_eq!(parse_cursor("123:"), None);
+        assert_eq!(parse_cursor("soon:d-1"), None);
+    }
+
+    #[test]
+    fn failed_primary_routes_requests_to_the_secondary() {
+        let client = ClickHouseClient::new(&ClickHouseConfig {
+            url: "http://primary:8123".to_string(),
+            replica_urls: vec!["http://secondary:8123".to_string()],
+            // Long enough that the cooldown cannot lapse mid-test.
+            failover_cooldown_seconds: 3600,
+            ..ClickHouseConfig::default()
+        });
+        assert_eq!(client.candidates(), vec![0, 1]);
+        assert_eq!(client.active_endpoint(), 0);
+
+        // A primary failure takes it out of rotation; the next request
+        // lands on the secondary, which becomes the active endpoint.
+        client.note_failure(0);
+        assert_eq!(client.candidates(), vec![1]);
+        assert_eq!(client.failovers(), 1);
+        client.note_success(1);
+        assert_eq!(client.active_endpoint(), 1);
+
+        // With every endpoint cooling down, all of them are tried anyway.
+        client.note_failure(1);
+        assert_eq!(client.candidates(), vec![0, 1]);
+
+        // A recovered primary (health probe success) rejoins at the front.
+        *client.endpoints[0].failed_at.lock().unwrap() = None;
+        assert_eq!(client.candidates(), vec![0]);
+        client.note_success(0);
+        assert_eq!(client.active_endpoint(), 0);
+    }
+
+    #[test]
+    fn empty_result_set_yields_default_stats() {
+        let stats = aggregate_stats(Vec::new());
+        assert_eq!(stats.total, 0);
+        assert_eq!(stats.avg_processing_time_ms, 0.0);
+    }
+}
+
+/// Live-cluster round trip, behind the `clickhouse-integration` feature:
+/// `cargo test --features clickhouse-integration` with a ClickHouse
+/// reachable at `GARUDA_TEST_CLICKHOUSE_URL` (default localhost:8123).
+#[cfg(all(test, feature = "clickhouse-integration"))]
+mod integration_tests {
+    use super::*;
+    use crate::models::{Action, Decision};
+
+    fn test_config(database: &str) -> ClickHouseConfig {
+        ClickHouseConfig {
+            url: std::env::var("GARUDA_TEST_CLICKHOUSE_URL")
+                .unwrap_or_else(|_| "http://localhost:8123".to_string()),
+            database: database.to_string(),
+            ..ClickHouseConfig::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn migrate_then_insert_round_trips() {
+        // Bootstrap through `default`, which always exists; the migration
+        // itself runs inside an isolated test database.
+        ClickHouseClient::new(&test_config("default"))
+            .execute("CREATE DATABASE IF NOT EXISTS garuda_migrate_test")
+            .await
+            .expect("create test database");
+        let client = ClickHouseClient::new(&test_config("garuda_migrate_test"));
+        client.migrate().await.expect("migration on a fresh database");
+        // Rerunning against the existing schema must be a no-op.
+        client.migrate().await.expect("migration is idempotent");
+
+        let decision = Decision {
+            decision_id: uuid::Uuid::new_v4().to_string(),
+            domain: "migration-test.example".to_string(),
+            url: None,
+            action: Action::Allow,
+            probability: 0.25,
+            confidence: 0.5,
+            model_version: "it".to_string(),
+            features: std::collections::HashMap::new(),
+            reasons: vec!["integration".to_string()],
+            processing_time_ms: 1.5,
+            sample_rate: 1.0,
+            timestamp: chrono::Utc::now(),
+        };
+        client.log_decision(&decision).await.expect("decision insert");
+        client
+            .log_analyzer_result("it-task", "migration-test.example", "BENIGN", "")
+            .await
+            .expect("analyzer insert");
+
+        let rows = client
+            .query_decisions(&DecisionsFilter {
+                domain: Some("migration-test.example".to_string()),
+                limit: 10,
+                ..Default::default()
+            })
+            .await
+            .expect("browse query against the migrated schema");
+        assert!(rows.iter().any(|row| row.decision_id == decision.decision_id));
+    }
+}
+
+/// rustls client config that skips certificate verification, for clusters
+/// using self-signed certificates.
+pub(crate) mod tls {
+    use std::sync::Arc;
+
+    use rustls::client::{ServerCertVerified, ServerCertVerifier};
+
+    struct NoVerify;
+
+    impl ServerCertVerifier for NoVerify {
+        fn verify_server_cert(
+            &self,
+            _end_entity: &rustls::Certificate,
+            _intermediates: &[rustls::Certificate],
+            _server_name: &rustls::ServerName,
+            _scts: &mut dyn Iterator<Item = &[u8]>,
+            _ocsp_response: &[u8],
+            _now: std::time::SystemTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            Ok(ServerCertVerified::assertion())
+        }
+    }
+
+    pub fn no_verify_config() -> rustls::ClientConfig {
+        rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(NoVerify))
+            .with_no_client_auth()
+    }
+}
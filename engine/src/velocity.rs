@@ -0,0 +1,174 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Sliding-window tracker of distinct newly-seen domains per structural
+/// cluster. Coordinated campaigns churn out many never-before-seen domains
+/// sharing shape ("login-44321.top", "verify-99213.top"); a cluster whose
+/// first-seen rate spikes is suspicious even when each individual domain
+/// looks bland.
+pub struct VelocityTracker {
+    window: Duration,
+    max_entries: usize,
+    inner: Mutex<Inner>,
+}
+
+struct Inner {
+    /// Observations in arrival order, so pruning walks from the front.
+    events: VecDeque<Event>,
+    /// Distinct-new-domain count per cluster pattern within the window.
+    counts: HashMap<String, u32>,
+    /// Domains currently inside the window; repeats do not count twice.
+    seen: HashSet<String>,
+}
+
+struct Event {
+    at: Instant,
+    domain: String,
+    pattern: String,
+}
+
+impl VelocityTracker {
+    pub fn new(window_seconds: u64, max_entries: usize) -> Self {
+        Self {
+            window: Duration::from_secs(window_seconds),
+            max_entries: max_entries.max(1),
+            inner: Mutex::new(Inner {
+                events: VecDeque::new(),
+                counts: HashMap::new(),
+                seen: HashSet::new(),
+            }),
+        }
+    }
+
+    /// Record an observation of `domain` and return the number of distinct
+    /// new domains (including this one) sharing its cluster pattern within
+    /// the window.
+    pub fn observe(&self, domain: &str) -> u32 {
+        self.observe_at(domain, Instant::now())
+    }
+
+    fn observe_at(&self, domain: &str, now: Instant) -> u32 {
+        let pattern = cluster_pattern(domain);
+        let mut inner = self.inner.lock().expect("velocity tracker poisoned");
+        inner.prune(now.checked_sub(self.window));
+
+        if inner.seen.contains(domain) {
+            return inner.counts.get(&pattern).copied().unwrap_or(1);
+        }
+        // Memory bound: drop the oldest observation once full, exactly as if
+        // it had aged out of the window.
+        if inner.events.len() >= self.max_entries {
+            inner.pop_front();
+        }
+        inner.seen.insert(domain.to_string());
+        let count = inner.counts.entry(pattern.clone()).or_insert(0);
+        *count += 1;
+        let count = *count;
+        inner.events.push_back(Event {
+            at: now,
+            domain: domain.to_string(),
+            pattern,
+        });
+        count
+    }
+}
+
+impl Inner {
+    fn prune(&mut self, cutoff: Option<Instant>) {
+        let Some(cutoff) = cutoff else { return };
+        while self.events.front().is_some_and(|e| e.at < cutoff) {
+            self.pop_front();
+        }
+    }
+
+    fn pop_front(&mut self) {
+        if let Some(event) = self.events.pop_front() {
+            self.seen.remove(&event.domain);
+            if let Some(count) = self.counts.get_mut(&event.pattern) {
+                *count -= 1;
+                if *count == 0 {
+                    self.counts.remove(&event.pattern);
+                }
+            }
+        }
+    }
+}
+
+/// Collapse a domain into the structural shape of its first label plus the
+/// TLD: runs of letters become `a`, runs of digits `9`, separators are kept.
+/// "login-44321.top" and "verify-99213.top" both map to "a-9.top".
+fn cluster_pattern(domain: &str) -> String {
+    let label = domain.split('.').next().unwrap_or(domain);
+    let tld = domain.rsplit('.').next().unwrap_or("");
+    let mut shape = String::new();
+    let mut last = '\0';
+    for c in label.chars() {
+        let class = if c.is_ascii_digit() {
+            '9'
+        } else if c.is_alphabetic() {
+            'a'
+        } else {
+            c
+        };
+        if class != last {
+            shape.push(class);
+            last = class;
+        }
+    }
+    format!("{shape}.{tld}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn campaign_domains_share_a_pattern() {
+        assert_eq!(cluster_pattern("login-44321.top"), "a-9.top");
+        assert_eq!(cluster_pattern("verify-99213.top"), "a-9.top");
+        assert_ne!(cluster_pattern("example.com"), cluster_pattern("example.top"));
+    }
+
+    #[test]
+    fn burst_of_similar_new_domains_raises_velocity() {
+        let tracker = VelocityTracker::new(600, 1000);
+        let now = Instant::now();
+        for i in 0..9 {
+            tracker.observe_at(&format!("login-{i:05}.top"), now);
+        }
+        assert_eq!(tracker.observe_at("verify-99213.top", now), 10);
+        // A structurally different domain starts its own cluster.
+        assert_eq!(tracker.observe_at("example.com", now), 1);
+    }
+
+    #[test]
+    fn repeat_observations_do_not_inflate_the_count() {
+        let tracker = VelocityTracker::new(600, 1000);
+        let now = Instant::now();
+        assert_eq!(tracker.observe_at("login-1.top", now), 1);
+        assert_eq!(tracker.observe_at("login-1.top", now), 1);
+        assert_eq!(tracker.observe_at("login-2.top", now), 2);
+    }
+
+    #[test]
+    fn observations_age_out_of_the_window() {
+        let tracker = VelocityTracker::new(60, 1000);
+        let start = Instant::now();
+        for i in 0..5 {
+            tracker.observe_at(&format!("login-{i}.top"), start);
+        }
+        let later = start + Duration::from_secs(120);
+        assert_eq!(tracker.observe_at("login-99.top", later), 1);
+    }
+
+    #[test]
+    fn entry_bound_caps_memory() {
+        let tracker = VelocityTracker::new(600, 4);
+        let now = Instant::now();
+        for i in 0..100 {
+            let count = tracker.observe_at(&format!("login-{i:04}.top"), now);
+            assert!(count <= 4);
+        }
+    }
+}
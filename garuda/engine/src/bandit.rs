@@ -0,0 +1,117 @@
+use tokio::sync::Mutex;
+
+/// Compact LinUCB over the three actions, used to resolve scores in the
+/// uncertain band between the warn and block thresholds.
+pub struct Bandit {
+    inner: Mutex<Inner>,
+    alpha: f64,
+}
+
+struct Inner {
+    arms: Vec<Arm>,
+}
+
+struct Arm {
+    a: Vec<Vec<f64>>,
+    b: Vec<f64>,
+    pulls: u64,
+}
+
+pub const ACTIONS: [&str; 3] = ["ALLOW", "WARN", "BLOCK"];
+const DIMENSIONS: usize = 4;
+
+impl Bandit {
+    pub fn new(alpha: f64) -> Self {
+        Self {
+            alpha,
+            inner: Mutex::new(Inner {
+                arms: (0..ACTIONS.len())
+                    .map(|_| Arm {
+                        a: identity(DIMENSIONS),
+                        b: vec![0.0; DIMENSIONS],
+                        pulls: 0,
+                    })
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Context is [prob, entropy, length/100, digit_ratio].
+    pub async fn select_action(&self, context: &[f64; DIMENSIONS]) -> usize {
+        let inner = self.inner.lock().await;
+        let mut best = 0;
+        let mut best_ucb = f64::NEG_INFINITY;
+        for (index, arm) in inner.arms.iter().enumerate() {
+            let a_inv = invert(&arm.a);
+            let theta = mat_vec(&a_inv, &arm.b);
+            let exploit: f64 = theta.iter().zip(context).map(|(t, x)| t * x).sum();
+            let explore =
+                self.alpha * mat_vec(&a_inv, context).iter().zip(context).map(|(a, b)| a * b).sum::<f64>().max(0.0).sqrt();
+            if exploit + explore > best_ucb {
+                best_ucb = exploit + explore;
+                best = index;
+            }
+        }
+        best
+    }
+
+    pub async fn update(&self, action: usize, context: &[f64; DIMENSIONS], reward: f64) {
+        let mut inner = self.inner.lock().await;
+        let arm = &mut inner.arms[action];
+        for i in 0..DIMENSIONS {
+            for j in 0..DIMENSIONS {
+                arm.a[i][j] += context[i] * context[j];
+            }
+        }
+        for (b, x) in arm.b.iter_mut().zip(context) {
+            *b += reward * x;
+        }
+        arm.pulls += 1;
+    }
+}
+
+fn identity(n: usize) -> Vec<Vec<f64>> {
+    let mut m = vec![vec![0.0; n]; n];
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] = 1.0;
+    }
+    m
+}
+
+fn mat_vec(m: &[Vec<f64>], v: &[f64]) -> Vec<f64> {
+    m.iter()
+        .map(|row| row.iter().zip(v).map(|(a, b)| a * b).sum())
+        .collect()
+}
+
+fn invert(m: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    let n = m.len();
+    let mut aug: Vec<Vec<f64>> = m
+        .iter()
+        .enumerate()
+        .map(|(i, row)| {
+            let mut r = row.clone();
+            r.extend((0..n).map(|j| if i == j { 1.0 } else { 0.0 }));
+            r
+        })
+        .collect();
+    for col in 0..n {
+        let pivot = (col..n)
+            .max_by(|&x, &y| aug[x][col].abs().partial_cmp(&aug[y][col].abs()).unwrap())
+            .unwrap();
+        aug.swap(col, pivot);
+        let p = aug[col][col];
+        for x in aug[col].iter_mut() {
+            *x /= p;
+        }
+        for row in 0..n {
+            if row != col {
+                let f = aug[row][col];
+                for k in 0..2 * n {
+                    aug[row][k] -= f * aug[col][k];
+                }
+            }
+        }
+    }
+    aug.into_iter().map(|row| row[n..].to_vec()).collect()
+}
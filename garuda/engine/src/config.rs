@@ -0,0 +1,42 @@
+/// Engine configuration, sourced entirely from `GARUDA_*` environment
+/// variables with defaults suitable for local development.
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub host: String,
+    pub port: u16,
+    pub warn_threshold: f64,
+    pub block_threshold: f64,
+    pub clickhouse_url: String,
+    pub clickhouse_database: String,
+    pub clickhouse_user: String,
+    pub clickhouse_password: String,
+    pub redis_url: String,
+    pub model_path: String,
+    pub intel_cache_capacity: u64,
+    pub intel_cache_ttl_seconds: u64,
+    pub blocklist_path: Option<String>,
+}
+
+impl EngineConfig {
+    pub fn from_env() -> anyhow::Result<Self> {
+        Ok(Self {
+            host: env_or("GARUDA_HOST", "0.0.0.0"),
+            port: env_or("GARUDA_PORT", "8100").parse()?,
+            warn_threshold: env_or("GARUDA_WARN_THRESHOLD", "0.5").parse()?,
+            block_threshold: env_or("GARUDA_BLOCK_THRESHOLD", "0.8").parse()?,
+            clickhouse_url: env_or("GARUDA_CLICKHOUSE_URL", "http://localhost:8123"),
+            clickhouse_database: env_or("GARUDA_CLICKHOUSE_DATABASE", "garuda"),
+            clickhouse_user: env_or("GARUDA_CLICKHOUSE_USER", "default"),
+            clickhouse_password: env_or("GARUDA_CLICKHOUSE_PASSWORD", ""),
+            redis_url: env_or("GARUDA_REDIS_URL", "redis://127.0.0.1:6379"),
+            model_path: env_or("GARUDA_MODEL_PATH", "models/student.json"),
+            intel_cache_capacity: env_or("GARUDA_INTEL_CACHE_CAPACITY", "10000").parse()?,
+            intel_cache_ttl_seconds: env_or("GARUDA_INTEL_CACHE_TTL", "1800").parse()?,
+            blocklist_path: std::env::var("GARUDA_BLOCKLIST_PATH").ok(),
+        })
+    }
+}
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
@@ -0,0 +1,79 @@
+use crate::types::Features;
+
+/// The twelve lexical features the garuda student model consumes, keyed by
+/// name rather than position.
+pub struct Featurizer;
+
+impl Featurizer {
+    pub fn featurize(&self, domain: &str) -> Features {
+        let mut features = Features::new();
+        let labels: Vec<&str> = domain.split('.').collect();
+        let sld = labels.first().copied().unwrap_or(domain);
+        let len = domain.len() as f64;
+
+        features.insert("length".to_string(), len);
+        features.insert("sld_length".to_string(), sld.len() as f64);
+        features.insert(
+            "num_digits".to_string(),
+            domain.chars().filter(|c| c.is_ascii_digit()).count() as f64,
+        );
+        features.insert(
+            "num_dashes".to_string(),
+            domain.matches('-').count() as f64,
+        );
+        features.insert("num_labels".to_string(), labels.len() as f64);
+        features.insert("entropy".to_string(), shannon_entropy(domain));
+        features.insert(
+            "digit_ratio".to_string(),
+            domain.chars().filter(|c| c.is_ascii_digit()).count() as f64 / len,
+        );
+        features.insert(
+            "vowel_ratio".to_string(),
+            domain
+                .chars()
+                .filter(|c| matches!(c, 'a' | 'e' | 'i' | 'o' | 'u'))
+                .count() as f64
+                / len,
+        );
+        features.insert(
+            "max_label_length".to_string(),
+            labels.iter().map(|l| l.len()).max().unwrap_or(0) as f64,
+        );
+        features.insert(
+            "is_punycode".to_string(),
+            if labels.iter().any(|l| l.starts_with("xn--")) {
+                1.0
+            } else {
+                0.0
+            },
+        );
+        features.insert(
+            "tld_length".to_string(),
+            labels.last().map(|l| l.len()).unwrap_or(0) as f64,
+        );
+        features.insert(
+            "has_www".to_string(),
+            if labels.first() == Some(&"www") { 1.0 } else { 0.0 },
+        );
+        features
+    }
+}
+
+/// Shannon entropy over the characters of the input.
+pub fn shannon_entropy(s: &str) -> f64 {
+    if s.is_empty() {
+        return 0.0;
+    }
+    let mut counts = std::collections::HashMap::new();
+    for c in s.chars() {
+        *counts.entry(c).or_insert(0usize) += 1;
+    }
+    let len = s.chars().count() as f64;
+    counts
+        .values()
+        .map(|&n| {
+            let p = n as f64 / len;
+            -p * p.log2()
+        })
+        .sum()
+}
@@ -0,0 +1,66 @@
+use std::collections::HashSet;
+
+use moka::future::Cache;
+use tokio::sync::RwLock;
+
+use crate::config::EngineConfig;
+
+/// Local blocklist intelligence with a TTL'd, capacity-bounded lookup cache.
+pub struct Intel {
+    blocklist: RwLock<HashSet<String>>,
+    /// Caches both hits (`Some(source)`) and misses (`None`).
+    cache: Cache<String, Option<String>>,
+}
+
+impl Intel {
+    pub fn new(config: &EngineConfig) -> Self {
+        Self {
+            blocklist: RwLock::new(HashSet::new()),
+            cache: Cache::builder()
+                .max_capacity(config.intel_cache_capacity)
+                .time_to_live(std::time::Duration::from_secs(
+                    config.intel_cache_ttl_seconds,
+                ))
+                .build(),
+        }
+    }
+
+    pub async fn load_from_file(&self, path: &str) -> anyhow::Result<()> {
+        let content = tokio::fs::read_to_string(path).await?;
+        let mut blocklist = self.blocklist.write().await;
+        for line in content.lines() {
+            let line = line.trim();
+            if !line.is_empty() && !line.starts_with('#') {
+                blocklist.insert(line.to_lowercase());
+            }
+        }
+        tracing::info!(count = blocklist.len(), "loaded blocklist");
+        Ok(())
+    }
+
+    /// Check a domain, walking up its parent suffixes so `login.evil.com`
+    /// matches a listing for `evil.com`.
+    pub async fn check(&self, domain: &str) -> Option<String> {
+        if let Some(cached) = self.cache.get(domain).await {
+            return cached;
+        }
+        let result = self.check_uncached(domain).await;
+        self.cache.insert(domain.to_string(), result.clone()).await;
+        result
+    }
+
+    async fn check_uncached(&self, domain: &str) -> Option<String> {
+        let blocklist = self.blocklist.read().await;
+        let mut candidate = domain;
+        loop {
+            if blocklist.contains(candidate) {
+                return Some(candidate.to_string());
+            }
+            match candidate.split_once('.') {
+                // Never match a bare TLD.
+                Some((_, rest)) if rest.contains('.') => candidate = rest,
+                _ => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,29 @@
+mod bandit;
+mod config;
+mod featurizer;
+mod intel;
+mod metrics;
+mod model;
+mod queue;
+mod routes;
+mod storage;
+mod types;
+
+use std::sync::Arc;
+
+use crate::config::EngineConfig;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt().init();
+
+    let config = EngineConfig::from_env()?;
+    let state = Arc::new(routes::AppState::new(config.clone()).await?);
+
+    let app = routes::router(state);
+    let addr = format!("{}:{}", config.host, config.port);
+    let listener = tokio::net::TcpListener::bind(&addr).await?;
+    tracing::info!("garuda engine listening on {addr}");
+    axum::serve(listener, app).await?;
+    Ok(())
+}
@@ -0,0 +1,182 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Instant;
+
+/// Seconds of history kept for the rolling QPS window.
+const WINDOW_SECONDS: u64 = 60;
+
+/// Latency histogram upper bounds, in seconds.
+const LATENCY_BOUNDS: &[f64] = &[
+    0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0,
+];
+
+/// Request metrics. Counters and the latency histogram are cumulative, so
+/// Prometheus can rate() and aggregate them correctly across replicas;
+/// QPS is additionally kept over a rolling window instead of since-start,
+/// so it neither resets meaning on deploys nor dilutes over uptime.
+pub struct Metrics {
+    started: Instant,
+    pub requests: AtomicU64,
+    pub blocks: AtomicU64,
+    pub warns: AtomicU64,
+    latency: Histogram,
+    window: RollingWindow,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            requests: AtomicU64::new(0),
+            blocks: AtomicU64::new(0),
+            warns: AtomicU64::new(0),
+            latency: Histogram::new(),
+            window: RollingWindow::new(WINDOW_SECONDS),
+        }
+    }
+
+    fn now_second(&self) -> u64 {
+        self.started.elapsed().as_secs()
+    }
+
+    pub fn record(&self, action: &str, latency_us: u64) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        self.latency.observe(latency_us as f64 / 1e6);
+        self.window.record_at(self.now_second());
+        match action {
+            "BLOCK" => {
+                self.blocks.fetch_add(1, Ordering::Relaxed);
+            }
+            "WARN" => {
+                self.warns.fetch_add(1, Ordering::Relaxed);
+            }
+            _ => {}
+        }
+    }
+
+    /// Requests per second averaged over the rolling window.
+    pub fn qps(&self) -> f64 {
+        self.window.sum_at(self.now_second()) as f64 / WINDOW_SECONDS as f64
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = format!(
+            "# TYPE garuda_requests_total counter\n\
+             garuda_requests_total {}\n\
+             # TYPE garuda_blocks_total counter\n\
+             garuda_blocks_total {}\n\
+             # TYPE garuda_warns_total counter\n\
+             garuda_warns_total {}\n",
+            self.requests.load(Ordering::Relaxed),
+            self.blocks.load(Ordering::Relaxed),
+            self.warns.load(Ordering::Relaxed),
+        );
+        out.push_str(&self.latency.render("garuda_request_latency_seconds"));
+        out
+    }
+}
+
+/// Cumulative latency histogram in standard Prometheus exposition form.
+struct Histogram {
+    buckets: Vec<AtomicU64>,
+    sum_us: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            buckets: (0..LATENCY_BOUNDS.len()).map(|_| AtomicU64::new(0)).collect(),
+            sum_us: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    fn observe(&self, seconds: f64) {
+        for (i, bound) in LATENCY_BOUNDS.iter().enumerate() {
+            if seconds <= *bound {
+                self.buckets[i].fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.sum_us.fetch_add((seconds * 1e6) as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn render(&self, name: &str) -> String {
+        let mut out = format!("# TYPE {name} histogram\n");
+        for (i, bound) in LATENCY_BOUNDS.iter().enumerate() {
+            out.push_str(&format!(
+                "{name}_bucket{{le=\"{bound}\"}} {}\n",
+                self.buckets[i].load(Ordering::Relaxed)
+            ));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        out.push_str(&format!("{name}_bucket{{le=\"+Inf\"}} {count}\n"));
+        out.push_str(&format!(
+            "{name}_sum {}\n",
+            self.sum_us.load(Ordering::Relaxed) as f64 / 1e6
+        ));
+        out.push_str(&format!("{name}_count {count}\n"));
+        out
+    }
+}
+
+/// One-second buckets in a ring; a bucket is reset lazily when its slot is
+/// reused for a newer second, so old traffic ages out of the sum.
+struct RollingWindow {
+    window: u64,
+    buckets: Vec<AtomicU64>,
+    seconds: Vec<AtomicU64>,
+}
+
+impl RollingWindow {
+    fn new(window: u64) -> Self {
+        Self {
+            window,
+            buckets: (0..window).map(|_| AtomicU64::new(0)).collect(),
+            seconds: (0..window).map(|_| AtomicU64::new(u64::MAX)).collect(),
+        }
+    }
+
+    fn record_at(&self, second: u64) {
+        let idx = (second % self.window) as usize;
+        if self.seconds[idx].swap(second, Ordering::Relaxed) != second {
+            self.buckets[idx].store(0, Ordering::Relaxed);
+        }
+        self.buckets[idx].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn sum_at(&self, second: u64) -> u64 {
+        let oldest = second.saturating_sub(self.window - 1);
+        self.buckets
+            .iter()
+            .zip(&self.seconds)
+            .filter(|(_, s)| {
+                let s = s.load(Ordering::Relaxed);
+                s >= oldest && s <= second
+            })
+            .map(|(b, _)| b.load(Ordering::Relaxed))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RollingWindow;
+
+    #[test]
+    fn window_rolls_old_seconds_out() {
+        let window = RollingWindow::new(60);
+        for _ in 0..30 {
+            window.record_at(10);
+        }
+        assert_eq!(window.sum_at(10), 30);
+        // Still inside the 60s window.
+        assert_eq!(window.sum_at(69), 30);
+        // One second past the window: the burst has aged out.
+        assert_eq!(window.sum_at(70), 0);
+
+        // A reused ring slot is reset, not accumulated.
+        window.record_at(70);
+        assert_eq!(window.sum_at(70), 1);
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::Features;
+
+/// Linear student model with weights keyed by feature name. Features absent
+/// from the weight map contribute nothing, so the model and featurizer can
+/// evolve somewhat independently.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StudentModel {
+    pub version: String,
+    pub weights: HashMap<String, f64>,
+    pub bias: f64,
+    pub training_samples: u64,
+}
+
+impl Default for StudentModel {
+    fn default() -> Self {
+        Self {
+            version: "default".to_string(),
+            weights: HashMap::new(),
+            bias: 0.0,
+            training_samples: 0,
+        }
+    }
+}
+
+impl StudentModel {
+    pub fn load(path: &str) -> anyhow::Result<Self> {
+        let data = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn predict(&self, features: &Features) -> f64 {
+        let z: f64 = self
+            .weights
+            .iter()
+            .map(|(name, weight)| weight * features.get(name).copied().unwrap_or(0.0))
+            .sum::<f64>()
+            + self.bias;
+        1.0 / (1.0 + (-z).exp())
+    }
+}
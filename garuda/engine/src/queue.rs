@@ -0,0 +1,47 @@
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use serde::{Deserialize, Serialize};
+
+const TASKS_KEY: &str = "garuda:tasks";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Task {
+    pub task_id: String,
+    pub decision_id: String,
+    pub domain: String,
+}
+
+/// Redis-backed analyzer task queue.
+#[derive(Clone)]
+pub struct Queue {
+    conn: ConnectionManager,
+}
+
+impl Queue {
+    pub async fn connect(url: &str) -> anyhow::Result<Self> {
+        let client = redis::Client::open(url)?;
+        let conn = ConnectionManager::new(client).await?;
+        Ok(Self { conn })
+    }
+
+    pub async fn enqueue_task(&self, task: &Task) -> anyhow::Result<()> {
+        let mut conn = self.conn.clone();
+        let payload = serde_json::to_string(task)?;
+        conn.lpush(TASKS_KEY, payload).await?;
+        Ok(())
+    }
+
+    pub async fn dequeue_task(&self) -> anyhow::Result<Option<Task>> {
+        let mut conn = self.conn.clone();
+        let result: Option<(String, String)> = conn.brpop(TASKS_KEY, 5).await?;
+        match result {
+            Some((_, payload)) => Ok(Some(serde_json::from_str(&payload)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub async fn len(&self) -> anyhow::Result<u64> {
+        let mut conn = self.conn.clone();
+        Ok(conn.llen(TASKS_KEY).await?)
+    }
+}
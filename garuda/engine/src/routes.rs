@@ -0,0 +1,170 @@
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::bandit::{Bandit, ACTIONS};
+use crate::config::EngineConfig;
+use crate::featurizer::Featurizer;
+use crate::intel::Intel;
+use crate::metrics::Metrics;
+use crate::model::StudentModel;
+use crate::queue::Queue;
+use crate::storage::Storage;
+use crate::types::{FeedbackRequest, ScoreRequest, ScoreResponse};
+
+pub struct AppState {
+    pub config: EngineConfig,
+    pub featurizer: Featurizer,
+    pub model: StudentModel,
+    pub intel: Intel,
+    pub storage: Storage,
+    pub queue: Queue,
+    pub bandit: Bandit,
+    pub metrics: Metrics,
+}
+
+impl AppState {
+    pub async fn new(config: EngineConfig) -> anyhow::Result<Self> {
+        let model = StudentModel::load(&config.model_path).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "using default student model");
+            StudentModel::default()
+        });
+        let intel = Intel::new(&config);
+        if let Some(path) = &config.blocklist_path {
+            intel.load_from_file(path).await?;
+        }
+        let storage = Storage::new(&config);
+        let queue = Queue::connect(&config.redis_url).await?;
+        Ok(Self {
+            featurizer: Featurizer,
+            model,
+            intel,
+            storage,
+            queue,
+            bandit: Bandit::new(1.0),
+            metrics: Metrics::new(),
+            config,
+        })
+    }
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    Router::new()
+        .route("/score", post(score))
+        .route("/feedback", post(feedback))
+        .route("/health", get(health))
+        .route("/metrics", get(metrics))
+        .with_state(state)
+}
+
+async fn score(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<ScoreRequest>,
+) -> Json<ScoreResponse> {
+    let started = Instant::now();
+    let domain = request.domain.trim().to_lowercase();
+    let decision_id = Uuid::new_v4().to_string();
+    let mut reasons = Vec::new();
+
+    let (action, prob) = if let Some(matched) = state.intel.check(&domain).await {
+        reasons.push(format!("blocklisted: {matched}"));
+        ("BLOCK".to_string(), 1.0)
+    } else {
+        let features = state.featurizer.featurize(&domain);
+        let prob = state.model.predict(&features);
+        let action = if prob >= state.config.block_threshold {
+            "BLOCK"
+        } else if prob >= state.config.warn_threshold {
+            let context = [
+                prob,
+                features.get("entropy").copied().unwrap_or(0.0),
+                features.get("length").copied().unwrap_or(0.0) / 100.0,
+                features.get("digit_ratio").copied().unwrap_or(0.0),
+            ];
+            ACTIONS[state.bandit.select_action(&context).await]
+        } else {
+            "ALLOW"
+        };
+        (action.to_string(), prob)
+    };
+
+    let latency_us = started.elapsed().as_micros() as u64;
+    state.metrics.record(&action, latency_us);
+
+    {
+        let state = state.clone();
+        let decision_id = decision_id.clone();
+        let domain = domain.clone();
+        let action = action.clone();
+        tokio::spawn(async move {
+            if let Err(e) = state
+                .storage
+                .insert_decision(&decision_id, &domain, &action, prob)
+                .await
+            {
+                tracing::warn!(error = %e, "decision insert failed");
+            }
+        });
+    }
+
+    Json(ScoreResponse {
+        decision_id,
+        domain,
+        action,
+        prob,
+        reasons,
+    })
+}
+
+async fn feedback(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<FeedbackRequest>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    if !(-1.0..=1.0).contains(&request.reward) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "reward must be in [-1, 1]".to_string(),
+        ));
+    }
+    // The decision's action comes from the decisions table; without it the
+    // reward can be attributed neither to a bandit arm nor to the rewards
+    // table, so an unknown id is the caller's error.
+    let action = state
+        .storage
+        .get_decision_action(&request.decision_id)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .ok_or_else(|| {
+            (
+                StatusCode::NOT_FOUND,
+                format!("unknown decision_id: {}", request.decision_id),
+            )
+        })?;
+    // The context used at decision time is not retained; feed the reward to a
+    // neutral context so the bandit still learns the arm-level bias.
+    let context = [0.5, 0.0, 0.0, 0.0];
+    let arm = ACTIONS.iter().position(|a| *a == action).unwrap_or(1);
+    state.bandit.update(arm, &context, request.reward).await;
+    if let Err(e) = state
+        .storage
+        .insert_reward(&request.decision_id, &action, request.reward)
+        .await
+    {
+        tracing::warn!(error = %e, "reward insert failed");
+    }
+    Ok(Json(json!({ "status": "ok" })))
+}
+
+async fn health() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+async fn metrics(State(state): State<Arc<AppState>>) -> String {
+    state.metrics.render()
+}
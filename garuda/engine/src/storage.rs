@@ -0,0 +1,111 @@
+use clickhouse::Client;
+
+use crate::config::EngineConfig;
+
+/// ClickHouse persistence for decisions and rewards.
+pub struct Storage {
+    client: Client,
+}
+
+impl Storage {
+    pub fn new(config: &EngineConfig) -> Self {
+        let client = Client::default()
+            .with_url(&config.clickhouse_url)
+            .with_database(&config.clickhouse_database)
+            .with_user(&config.clickhouse_user)
+            .with_password(&config.clickhouse_password);
+        Self { client }
+    }
+
+    pub async fn insert_decision(
+        &self,
+        decision_id: &str,
+        domain: &str,
+        action: &str,
+        prob: f64,
+    ) -> anyhow::Result<()> {
+        let sql = format!(
+            "INSERT INTO garuda.decisions (decision_id, domain, action, prob, ts) \
+             VALUES ('{}', '{}', '{}', {}, now())",
+            escape(decision_id),
+            escape(domain),
+            escape(action),
+            prob,
+        );
+        self.client.query(&sql).execute().await?;
+        Ok(())
+    }
+
+    /// Action recorded for a decision, or `None` for an unknown id.
+    pub async fn get_decision_action(&self, decision_id: &str) -> anyhow::Result<Option<String>> {
+        let sql = format!(
+            "SELECT action FROM garuda.decisions WHERE decision_id = '{}' LIMIT 1",
+            escape(decision_id),
+        );
+        Ok(self.client.query(&sql).fetch_optional::<String>().await?)
+    }
+
+    /// Record a feedback reward for offline retraining.
+    pub async fn insert_reward(
+        &self,
+        decision_id: &str,
+        action: &str,
+        reward: f64,
+    ) -> anyhow::Result<()> {
+        let sql = format!(
+            "INSERT INTO garuda.rewards (decision_id, action, reward, ts) \
+             VALUES ('{}', '{}', {}, now())",
+            escape(decision_id),
+            escape(action),
+            reward,
+        );
+        self.client.query(&sql).execute().await?;
+        Ok(())
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\'', "''")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "requires a local ClickHouse at http://localhost:8123 with the garuda schema"]
+    async fn feedback_writes_a_reward_row() {
+        let config = crate::config::EngineConfig::from_env().unwrap();
+        let storage = Storage::new(&config);
+        let decision_id = uuid::Uuid::new_v4().to_string();
+
+        storage
+            .insert_decision(&decision_id, "example.com", "WARN", 0.6)
+            .await
+            .unwrap();
+        let action = storage
+            .get_decision_action(&decision_id)
+            .await
+            .unwrap()
+            .expect("decision just inserted");
+        storage
+            .insert_reward(&decision_id, &action, -1.0)
+            .await
+            .unwrap();
+
+        let rows: u64 = storage
+            .client
+            .query(&format!(
+                "SELECT count() FROM garuda.rewards WHERE decision_id = '{decision_id}'"
+            ))
+            .fetch_one()
+            .await
+            .unwrap();
+        assert_eq!(rows, 1);
+        assert!(storage
+            .get_decision_action("no-such-decision")
+            .await
+            .unwrap()
+            .is_none());
+    }
+}
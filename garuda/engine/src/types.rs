@@ -0,0 +1,28 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ScoreRequest {
+    pub domain: String,
+    #[serde(default)]
+    pub url: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScoreResponse {
+    pub decision_id: String,
+    pub domain: String,
+    pub action: String,
+    pub prob: f64,
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct FeedbackRequest {
+    pub decision_id: String,
+    pub reward: f64,
+}
+
+/// Named feature map produced by the featurizer.
+pub type Features = HashMap<String, f64>;